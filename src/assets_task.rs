@@ -0,0 +1,100 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{self, Error, ErrorKind, Write},
+    path::PathBuf,
+};
+
+use serde_json::json;
+use tes3::esp::{TES3Object, TypeInfo};
+
+use crate::{parse_plugin, plugin_file_exists, report_to_string, ESerializedType};
+
+/// List the asset paths referenced by the records of a plugin: meshes, icons, sounds and textures.
+/// Paths are prefixed with their data folder, deduplicated case-insensitively and sorted.
+/// Without a format one path per line is written, to a file or to stdout.
+/// Returns the number of assets
+pub fn assets(
+    input: &Option<PathBuf>,
+    output: &Option<PathBuf>,
+    format: &Option<ESerializedType>,
+) -> io::Result<usize> {
+    let input_path: &PathBuf;
+    // check no input
+    if let Some(i) = input {
+        input_path = i;
+    } else {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "No input path specified.",
+        ));
+    }
+    if !plugin_file_exists(input_path) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Input path does not exist",
+        ));
+    }
+
+    let plugin = match parse_plugin(input_path) {
+        Ok(p) => p,
+        Err(_) => return Err(Error::new(ErrorKind::Other, "Plugin parsing failed.")),
+    };
+
+    // assets by lowercase path, the first spelling is kept
+    let mut assets: BTreeMap<String, String> = BTreeMap::new();
+    for object in &plugin.objects {
+        for asset in get_asset_paths(object) {
+            assets.entry(asset.to_lowercase()).or_insert(asset);
+        }
+    }
+    let assets: Vec<String> = assets.into_values().collect();
+
+    let text = match format {
+        Some(format) => report_to_string(format, &json!({ "assets": assets }))?,
+        None => assets.iter().map(|a| format!("{}\n", a)).collect(),
+    };
+    match output {
+        Some(path) => {
+            File::create(path)?.write_all(text.as_bytes())?;
+            println!("{} assets written to {}", assets.len(), path.display());
+        }
+        None => io::stdout().write_all(text.as_bytes())?,
+    }
+
+    Ok(assets.len())
+}
+
+/// Get the asset paths of a record, prefixed with their data folder
+pub fn get_asset_paths(object: &TES3Object) -> Vec<String> {
+    let fields = get_asset_fields(object.tag_str());
+    if fields.is_empty() {
+        return vec![];
+    }
+
+    let value = serde_json::to_value(object).unwrap_or_default();
+    let mut paths = vec![];
+    for (field, folder) in fields {
+        if let Some(path) = value[field].as_str() {
+            let path = path.trim().replace('\\', "/");
+            if !path.is_empty() {
+                paths.push(format!("{}/{}", folder, path));
+            }
+        }
+    }
+    paths
+}
+
+/// The asset path fields of a record type and the data folder they are relative to
+fn get_asset_fields(tag: &str) -> &'static [(&'static str, &'static str)] {
+    match tag {
+        "ACTI" | "BODY" | "CONT" | "CREA" | "DOOR" | "NPC_" | "STAT" => &[("mesh", "meshes")],
+        "ALCH" | "APPA" | "ARMO" | "BOOK" | "CLOT" | "INGR" | "LIGH" | "LOCK" | "MISC" | "PROB"
+        | "REPA" | "WEAP" => &[("mesh", "meshes"), ("icon", "icons")],
+        "MGEF" => &[("icon", "icons"), ("texture", "textures")],
+        "BSGN" => &[("texture", "textures")],
+        "LTEX" => &[("file_name", "textures")],
+        "SOUN" => &[("sound_path", "sound")],
+        _ => &[],
+    }
+}
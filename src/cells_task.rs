@@ -0,0 +1,143 @@
+use std::{
+    collections::HashSet,
+    io::{self, Error, ErrorKind},
+    path::PathBuf,
+};
+
+use tes3::esp::{CellFlags, EditorId, Plugin, TES3Object};
+
+use crate::{append_ext, parse_plugin, plugin_file_exists, save_plugin};
+
+/// Options for extracting cells
+#[derive(Default)]
+pub struct CellsOptions {
+    /// Minimum grid x coordinate, inclusive. A missing bound is unbounded
+    pub min_x: Option<i32>,
+    /// Maximum grid x coordinate, inclusive. A missing bound is unbounded
+    pub max_x: Option<i32>,
+    /// Minimum grid y coordinate, inclusive. A missing bound is unbounded
+    pub min_y: Option<i32>,
+    /// Maximum grid y coordinate, inclusive. A missing bound is unbounded
+    pub max_y: Option<i32>,
+    /// Include the LAND and PGRD records of the extracted exterior cells
+    pub land: bool,
+    /// Include the records referenced in the extracted cells
+    pub references: bool,
+}
+
+impl CellsOptions {
+    fn contains(&self, (x, y): (i32, i32)) -> bool {
+        self.min_x.map_or(true, |m| x >= m)
+            && self.max_x.map_or(true, |m| x <= m)
+            && self.min_y.map_or(true, |m| y >= m)
+            && self.max_y.map_or(true, |m| y <= m)
+    }
+}
+
+/// Extract the exterior cells in a grid range of a plugin into a new plugin.
+/// The header is copied, the output defaults to <stem>_cells.esp next to the input.
+/// Returns the number of extracted cells
+pub fn cells(
+    input: &Option<PathBuf>,
+    output: &Option<PathBuf>,
+    options: &CellsOptions,
+    dry_run: bool,
+) -> io::Result<usize> {
+    let input_path: &PathBuf;
+    // check no input
+    if let Some(i) = input {
+        input_path = i;
+    } else {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "No input path specified.",
+        ));
+    }
+    if !plugin_file_exists(input_path) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Input path does not exist",
+        ));
+    }
+
+    let plugin = match parse_plugin(input_path) {
+        Ok(p) => p,
+        Err(_) => return Err(Error::new(ErrorKind::Other, "Plugin parsing failed.")),
+    };
+
+    // the name and grid of the extracted exterior cells
+    let extracted: HashSet<(String, (i32, i32))> = plugin
+        .objects
+        .iter()
+        .filter_map(|o| match o {
+            TES3Object::Cell(cell)
+                if !cell.data.flags.contains(CellFlags::IS_INTERIOR)
+                    && options.contains(cell.data.grid) =>
+            {
+                Some((cell.name.to_lowercase(), cell.data.grid))
+            }
+            _ => None,
+        })
+        .collect();
+    let grids: HashSet<(i32, i32)> = extracted.iter().map(|(_, grid)| *grid).collect();
+
+    // base ids placed in the extracted cells
+    let mut referenced: HashSet<String> = HashSet::new();
+    let mut count = 0;
+    let mut cells_plugin = Plugin::new();
+    for object in &plugin.objects {
+        let keep = match object {
+            TES3Object::Header(_) => true,
+            TES3Object::Cell(cell) => {
+                let keep = !cell.data.flags.contains(CellFlags::IS_INTERIOR)
+                    && options.contains(cell.data.grid);
+                if keep {
+                    count += 1;
+                    referenced.extend(cell.references.values().map(|r| r.id.to_lowercase()));
+                }
+                keep
+            }
+            TES3Object::Landscape(land) => options.land && grids.contains(&land.grid),
+            // interior path grids have a grid too, match them by cell name
+            TES3Object::PathGrid(grid) => {
+                options.land && extracted.contains(&(grid.cell.to_lowercase(), grid.data.grid))
+            }
+            _ => false,
+        };
+        if keep {
+            cells_plugin.objects.push(object.clone());
+        }
+    }
+
+    if options.references {
+        // referenced records are inserted after the header, in plugin order
+        let records: Vec<TES3Object> = plugin
+            .objects
+            .iter()
+            .filter(|o| {
+                !matches!(o, TES3Object::Header(_) | TES3Object::Cell(_))
+                    && referenced.contains(&o.editor_id().to_lowercase())
+            })
+            .cloned()
+            .collect();
+        progress!("{} referenced records", records.len());
+        let index = cells_plugin
+            .objects
+            .iter()
+            .take_while(|o| matches!(o, TES3Object::Header(_)))
+            .count();
+        cells_plugin.objects.splice(index..index, records);
+    }
+    println!("{} cells in range", count);
+
+    let output_path = match output {
+        Some(o) => o.to_owned(),
+        None => {
+            let stem = input_path.file_stem().unwrap_or_default().to_string_lossy();
+            append_ext("esp", input_path.with_file_name(format!("{}_cells", stem)))
+        }
+    };
+    save_plugin(&cells_plugin, &output_path, dry_run)?;
+
+    Ok(count)
+}
@@ -0,0 +1,116 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    io::{self, Error, ErrorKind},
+    path::PathBuf,
+};
+
+use tes3::esp::{EditorId, TES3Object, TypeInfo};
+
+use crate::{parse_plugin, save_plugin, TaskSummary};
+
+/// Remove all records of the given tags from a plugin, the header is always kept.
+/// If a master is given, records identical to the master's version are removed too.
+/// The cleaned plugin is written next to the input as <name>_clean.<ext> by default
+pub fn clean(
+    input: &Option<PathBuf>,
+    output: &Option<PathBuf>,
+    remove: &[String],
+    master: &Option<PathBuf>,
+    dry_run: bool,
+) -> io::Result<TaskSummary> {
+    let input_path: &PathBuf;
+    // check no input
+    if let Some(i) = input {
+        input_path = i;
+    } else {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "No input path specified.",
+        ));
+    }
+    if !input_path.is_file() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Input path does not exist",
+        ));
+    }
+
+    let mut plugin = match parse_plugin(input_path) {
+        Ok(p) => p,
+        Err(_) => return Err(Error::new(ErrorKind::Other, "Plugin parsing failed.")),
+    };
+
+    let remove: HashSet<String> = remove.iter().map(|t| t.to_uppercase()).collect();
+    let mut removed: BTreeMap<String, usize> = BTreeMap::new();
+    plugin.objects.retain(|object| {
+        if matches!(object, TES3Object::Header(_)) || !remove.contains(object.tag_str()) {
+            return true;
+        }
+        *removed.entry(object.tag_str().to_owned()).or_default() += 1;
+        false
+    });
+
+    for (tag, count) in &removed {
+        progress!("Removed {} {} records", count, tag);
+    }
+
+    let mut identical = 0;
+    if let Some(master_path) = master {
+        let master = match parse_plugin(master_path) {
+            Ok(p) => p,
+            Err(_) => return Err(Error::new(ErrorKind::Other, "Master parsing failed.")),
+        };
+        let master_records: HashMap<(String, String), String> = master
+            .objects
+            .iter()
+            .filter(|o| !o.editor_id().is_empty())
+            .map(|o| (record_key(o), canonical(o)))
+            .collect();
+
+        plugin.objects.retain(|object| {
+            if matches!(object, TES3Object::Header(_)) || object.editor_id().is_empty() {
+                return true;
+            }
+            match master_records.get(&record_key(object)) {
+                Some(text) if *text == canonical(object) => {
+                    progress!(
+                        "Removed {} {}: identical to master",
+                        object.tag_str(),
+                        object.editor_id()
+                    );
+                    identical += 1;
+                    false
+                }
+                _ => true,
+            }
+        });
+        progress!("Removed {} records identical to master", identical);
+    }
+    progress!(
+        "Removed {} records",
+        removed.values().sum::<usize>() + identical
+    );
+
+    // save
+    let output_path = match output {
+        Some(o) => o.to_owned(),
+        None => {
+            let stem = input_path.file_stem().unwrap_or_default().to_string_lossy();
+            let ext = input_path.extension().unwrap_or_default().to_string_lossy();
+            input_path.with_file_name(format!("{}_clean.{}", stem, ext))
+        }
+    };
+    save_plugin(&plugin, &output_path, dry_run)
+}
+
+fn record_key(object: &TES3Object) -> (String, String) {
+    (
+        object.tag_str().to_owned(),
+        object.editor_id().to_lowercase(),
+    )
+}
+
+/// Serialize a record to a canonical string for comparison
+fn canonical(object: &TES3Object) -> String {
+    serde_json::to_string(object).unwrap_or_default()
+}
@@ -0,0 +1,106 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Error, ErrorKind},
+    path::PathBuf,
+};
+
+use tes3::esp::TES3Object;
+
+use crate::{
+    deserialize_bytes, format_from_path, serialize_objects, serialize_plugin_bytes, ESerializedType,
+};
+
+/// Convert a serialized plugin or a list of records from one format to another.
+/// Formats are inferred from the file extensions if not given.
+/// The output defaults to the input with the extension of the target format
+pub fn convert(
+    input: &Option<PathBuf>,
+    output: &Option<PathBuf>,
+    from: &Option<ESerializedType>,
+    to: &Option<ESerializedType>,
+) -> io::Result<usize> {
+    let input_path: &PathBuf;
+    // check no input
+    if let Some(i) = input {
+        input_path = i;
+    } else {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "No input path specified.",
+        ));
+    }
+    if !input_path.is_file() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Input path does not exist",
+        ));
+    }
+
+    let Some(from) = from.clone().or_else(|| format_from_path(input_path)) else {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Could not infer the input format, use --from",
+        ));
+    };
+    let Some(to) = to
+        .clone()
+        .or_else(|| output.as_ref().and_then(|o| format_from_path(o)))
+    else {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Could not infer the output format, use --to",
+        ));
+    };
+    let output_path = match output {
+        Some(o) => o.to_owned(),
+        None => input_path.with_extension(to.to_string()),
+    };
+    if output_path == *input_path {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Output path is the input path",
+        ));
+    }
+
+    let data = fs::read(input_path)?;
+    // whole plugins are written as plugins, record lists as record lists
+    let (count, converted) = match deserialize_bytes(&data, &from) {
+        Ok(plugin) => (
+            plugin.objects.len(),
+            serialize_plugin_bytes(&plugin, &to, false, false)?,
+        ),
+        Err(plugin_error) => match deserialize_records(&data, &from) {
+            Some(records) => (
+                records.len(),
+                serialize_objects(&to, &records.iter().collect::<Vec<_>>(), false)?,
+            ),
+            None => return Err(plugin_error),
+        },
+    };
+    fs::write(&output_path, converted)?;
+    println!(
+        "Converted {} records from {} to {}",
+        count,
+        from,
+        output_path.display()
+    );
+
+    Ok(count)
+}
+
+/// Parse a list of records as written by a single file dump
+fn deserialize_records(data: &[u8], format: &ESerializedType) -> Option<Vec<TES3Object>> {
+    match format {
+        ESerializedType::Yaml => serde_yaml::from_slice(data).ok(),
+        ESerializedType::Json => serde_json::from_slice(data).ok(),
+        ESerializedType::Msgpack => rmp_serde::from_slice(data).ok(),
+        ESerializedType::Toml => {
+            let text = std::str::from_utf8(data).ok()?;
+            let mut map: HashMap<String, Vec<TES3Object>> = toml::from_str(text).ok()?;
+            map.remove("records")
+        }
+        // json lines are always read as a plugin
+        ESerializedType::Jsonl => None,
+    }
+}
@@ -0,0 +1,122 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    env,
+    fs::{self, File},
+    io::{self, Error, ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+
+use serde_json::{Map, Value};
+use tes3::esp::TypeInfo;
+
+use crate::{append_ext, parse_plugin, plugin_file_exists, TaskSummary};
+
+/// Export the records of a plugin to one csv file per tag.
+/// Top-level fields become columns, nested fields are written as json.
+/// Only the given tags are exported, all tags if none are given
+pub fn export_csv(
+    input: &Option<PathBuf>,
+    output: &Option<PathBuf>,
+    tags: &[String],
+) -> io::Result<TaskSummary> {
+    let input_path: &PathBuf;
+    // check no input
+    if let Some(i) = input {
+        input_path = i;
+    } else {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "No input path specified.",
+        ));
+    }
+    if !plugin_file_exists(input_path) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Input path does not exist",
+        ));
+    }
+
+    // check output path, default is cwd
+    let mut out_dir_path = env::current_dir()?;
+    if let Some(p) = output {
+        p.clone_into(&mut out_dir_path);
+    }
+
+    let plugin = match parse_plugin(input_path) {
+        Ok(p) => p,
+        Err(_) => return Err(Error::new(ErrorKind::Other, "Plugin parsing failed.")),
+    };
+
+    // group the serialized records by tag
+    let tags: HashSet<String> = tags.iter().map(|t| t.to_uppercase()).collect();
+    let mut groups: BTreeMap<&str, Vec<Map<String, Value>>> = BTreeMap::new();
+    for object in &plugin.objects {
+        if !tags.is_empty() && !tags.contains(object.tag_str()) {
+            continue;
+        }
+        if let Value::Object(fields) = serde_json::to_value(object)? {
+            groups.entry(object.tag_str()).or_default().push(fields);
+        }
+    }
+
+    fs::create_dir_all(&out_dir_path)?;
+    let mut summary = TaskSummary::default();
+    for (tag, records) in &groups {
+        let output_path = append_ext("csv", out_dir_path.join(tag));
+        write_csv(&output_path, records)?;
+        progress!(
+            "{} {} records written to {}",
+            records.len(),
+            tag,
+            output_path.display()
+        );
+        summary.records += records.len();
+        summary.outputs.push(output_path);
+    }
+
+    Ok(summary)
+}
+
+/// Write records as csv, the header is the union of all keys in first-seen order
+fn write_csv(path: &Path, records: &[Map<String, Value>]) -> io::Result<()> {
+    let mut columns: Vec<String> = vec![];
+    for record in records {
+        for key in record.keys() {
+            if !columns.contains(key) {
+                columns.push(key.clone());
+            }
+        }
+    }
+
+    let mut file = File::create(path)?;
+    writeln!(file, "{}", to_csv_line(&columns))?;
+    for record in records {
+        let row: Vec<String> = columns
+            .iter()
+            .map(|c| match record.get(c) {
+                None | Some(Value::Null) => String::new(),
+                Some(Value::String(s)) => s.clone(),
+                // numbers and bools as is, nested values as json
+                Some(v) => v.to_string(),
+            })
+            .collect();
+        writeln!(file, "{}", to_csv_line(&row))?;
+    }
+
+    Ok(())
+}
+
+/// Join values to a csv line, fields that contain separators, quotes or newlines are quoted
+pub(crate) fn to_csv_line(values: &[String]) -> String {
+    values
+        .iter()
+        .map(|v| {
+            if v.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", v.replace('"', "\"\""))
+            } else {
+                v.to_owned()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
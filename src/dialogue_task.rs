@@ -0,0 +1,111 @@
+use std::{
+    fmt::Write,
+    fs,
+    io::{self, Error, ErrorKind},
+    path::PathBuf,
+};
+
+use tes3::esp::{Dialogue, DialogueInfo, Plugin, TES3Object};
+
+use crate::{parse_plugin, plugin_file_exists};
+
+/// Group the INFO records of a plugin under their DIAL topic.
+/// INFO records belong to the DIAL record preceding them in object order
+pub fn get_topics(plugin: &Plugin) -> Vec<(&Dialogue, Vec<&DialogueInfo>)> {
+    let mut topics: Vec<(&Dialogue, Vec<&DialogueInfo>)> = vec![];
+    for object in &plugin.objects {
+        match object {
+            TES3Object::Dialogue(dialogue) => topics.push((dialogue, vec![])),
+            TES3Object::DialogueInfo(info) => match topics.last_mut() {
+                Some((_, infos)) => infos.push(info),
+                None => log::warn!("Skipping INFO without topic: {}", info.id),
+            },
+            _ => {}
+        }
+    }
+    topics
+}
+
+/// Write the dialogue of a plugin as a markdown transcript with one section per topic.
+/// The output defaults to the input with a md extension.
+/// Returns the number of topics
+pub fn dialogue(input: &Option<PathBuf>, output: &Option<PathBuf>) -> io::Result<usize> {
+    let input_path: &PathBuf;
+    // check no input
+    if let Some(i) = input {
+        input_path = i;
+    } else {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "No input path specified.",
+        ));
+    }
+    if !plugin_file_exists(input_path) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Input path does not exist",
+        ));
+    }
+    let output_path = match output {
+        Some(o) => o.to_owned(),
+        None => input_path.with_extension("md"),
+    };
+
+    let plugin = match parse_plugin(input_path) {
+        Ok(p) => p,
+        Err(_) => return Err(Error::new(ErrorKind::Other, "Plugin parsing failed.")),
+    };
+
+    let topics = get_topics(&plugin);
+    let name = input_path.file_name().unwrap_or_default().to_string_lossy();
+    let mut text = format!("# Dialogue of {}\n", name);
+    for (dialogue, infos) in &topics {
+        let _ = write!(
+            text,
+            "\n## {} ({:?})\n\n",
+            dialogue.id, dialogue.dialogue_type
+        );
+        if infos.is_empty() {
+            text.push_str("No responses.\n");
+        }
+        for (i, info) in infos.iter().enumerate() {
+            write_info(&mut text, i + 1, info);
+        }
+    }
+    fs::write(&output_path, text)?;
+
+    let responses: usize = topics.iter().map(|(_, infos)| infos.len()).sum();
+    println!(
+        "Wrote {} topics with {} responses to {}",
+        topics.len(),
+        responses,
+        output_path.display()
+    );
+    Ok(topics.len())
+}
+
+/// Write a numbered response with its speaker filters and result script
+fn write_info(text: &mut String, number: usize, info: &DialogueInfo) {
+    let _ = writeln!(text, "{}. {}", number, info.text.trim());
+    let filters = [
+        ("Speaker", &info.speaker_id),
+        ("Race", &info.speaker_race),
+        ("Class", &info.speaker_class),
+        ("Faction", &info.speaker_faction),
+        ("Cell", &info.speaker_cell),
+        ("Player faction", &info.player_faction),
+    ];
+    for (label, value) in filters {
+        if !value.is_empty() {
+            let _ = writeln!(text, "   - {}: {}", label, value);
+        }
+    }
+    let script = info.script_text.trim();
+    if !script.is_empty() {
+        text.push_str("   - Result:\n\n");
+        for line in script.lines() {
+            let _ = writeln!(text, "         {}", line.trim_end());
+        }
+        text.push('\n');
+    }
+}
@@ -0,0 +1,247 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::File,
+    io::{self, Error, ErrorKind, Write},
+    path::PathBuf,
+};
+
+use serde_json::{json, Value};
+use similar::TextDiff;
+use tes3::esp::{EditorId, Plugin, TES3Object, TypeInfo};
+
+use crate::{
+    is_quiet, parse_plugin, plugin_file_exists, read_serialized_plugin, serialize_object,
+    ESerializedType,
+};
+
+/// Compare two plugins and report added, removed and changed records.
+/// Changed records are serialized to the chosen format and emitted as a unified diff.
+/// Returns true if the plugins differ
+pub fn diff(
+    old: &Option<PathBuf>,
+    new: &Option<PathBuf>,
+    output: &Option<PathBuf>,
+    format: &Option<ESerializedType>,
+) -> io::Result<bool> {
+    let (old_path, new_path) = match (old, new) {
+        (Some(a), Some(b)) => (a, b),
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Two input paths must be specified.",
+            ))
+        }
+    };
+    if !plugin_file_exists(old_path) || !plugin_file_exists(new_path) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Input path does not exist",
+        ));
+    }
+
+    let old_plugin = match parse_plugin(old_path) {
+        Ok(p) => p,
+        Err(_) => return Err(Error::new(ErrorKind::Other, "Plugin parsing failed.")),
+    };
+    let new_plugin = match parse_plugin(new_path) {
+        Ok(p) => p,
+        Err(_) => return Err(Error::new(ErrorKind::Other, "Plugin parsing failed.")),
+    };
+
+    let format = match format {
+        Some(f) => f,
+        None => &ESerializedType::Yaml,
+    };
+
+    let mut text = String::new();
+    let mut count = 0;
+    for ((tag, id), a, b) in pair_objects(&old_plugin, &new_plugin) {
+        match (a, b) {
+            (Some(_), None) => text.push_str(&format!("- {} {}\n", tag, id)),
+            (None, Some(_)) => text.push_str(&format!("+ {} {}\n", tag, id)),
+            (Some(a), Some(b)) => {
+                let a = serialize_object(a, format)?;
+                let b = serialize_object(b, format)?;
+                if a == b {
+                    continue;
+                }
+                text.push_str(&format!("~ {} {}\n", tag, id));
+                let diff = TextDiff::from_lines(&a, &b);
+                text.push_str(
+                    &diff
+                        .unified_diff()
+                        .header(&old_path.to_string_lossy(), &new_path.to_string_lossy())
+                        .to_string(),
+                );
+            }
+            (None, None) => continue,
+        }
+        count += 1;
+    }
+
+    write_text(&text, output)?;
+    if count == 0 {
+        println!("Plugins are identical.");
+    } else {
+        println!("{} records differ", count);
+    }
+    Ok(count > 0)
+}
+
+/// Report the record and field level differences of an edited serialized plugin
+/// relative to the original binary plugin
+pub fn diff_against_binary(
+    input: &Option<PathBuf>,
+    original: &PathBuf,
+    output: &Option<PathBuf>,
+    format: &Option<ESerializedType>,
+) -> io::Result<()> {
+    let input_path: &PathBuf;
+    // check no input
+    if let Some(i) = input {
+        input_path = i;
+    } else {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "No input path specified.",
+        ));
+    }
+    if !input_path.is_file() || !plugin_file_exists(original) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Input path does not exist",
+        ));
+    }
+
+    let edited = read_serialized_plugin(input_path)?;
+    let original = match parse_plugin(original) {
+        Ok(p) => p,
+        Err(_) => return Err(Error::new(ErrorKind::Other, "Plugin parsing failed.")),
+    };
+
+    let diffs = diff_plugins(&original, &edited);
+    println!("{} records differ", diffs.len());
+
+    let format = match format {
+        Some(f) => f,
+        None => &ESerializedType::Yaml,
+    };
+    write_report(&Value::Array(diffs), format, output)
+}
+
+/// Index records by tag and editor id
+fn index_objects(objects: &[TES3Object]) -> BTreeMap<(String, String), &TES3Object> {
+    objects
+        .iter()
+        .map(|o| ((o.tag_str().to_owned(), o.editor_id().to_lowercase()), o))
+        .collect()
+}
+
+/// A record of both plugins, matched by tag and editor id
+type RecordPair<'a> = (
+    (String, String),
+    Option<&'a TES3Object>,
+    Option<&'a TES3Object>,
+);
+
+/// Match the records of two plugins by tag and editor id
+fn pair_objects<'a>(old: &'a Plugin, new: &'a Plugin) -> Vec<RecordPair<'a>> {
+    let old_map = index_objects(&old.objects);
+    let new_map = index_objects(&new.objects);
+    let keys: BTreeSet<_> = old_map.keys().chain(new_map.keys()).cloned().collect();
+
+    keys.into_iter()
+        .map(|key| {
+            let a = old_map.get(&key).copied();
+            let b = new_map.get(&key).copied();
+            (key, a, b)
+        })
+        .collect()
+}
+
+/// Compare two plugins record by record.
+/// Returns one entry per added, removed or changed record, changed records list their field deltas
+pub fn diff_plugins(old: &Plugin, new: &Plugin) -> Vec<Value> {
+    let mut diffs = vec![];
+    for ((tag, id), a, b) in pair_objects(old, new) {
+        let entry = match (a, b) {
+            (Some(_), None) => json!({ "tag": tag, "id": id, "status": "removed" }),
+            (None, Some(_)) => json!({ "tag": tag, "id": id, "status": "added" }),
+            (Some(a), Some(b)) => {
+                let mut fields = vec![];
+                diff_values(
+                    "",
+                    &serde_json::to_value(a).unwrap_or_default(),
+                    &serde_json::to_value(b).unwrap_or_default(),
+                    &mut fields,
+                );
+                if fields.is_empty() {
+                    continue;
+                }
+                json!({ "tag": tag, "id": id, "status": "changed", "fields": fields })
+            }
+            (None, None) => continue,
+        };
+        diffs.push(entry);
+    }
+
+    diffs
+}
+
+/// Recursively collect the differing fields of two values
+fn diff_values(path: &str, old: &Value, new: &Value, fields: &mut Vec<Value>) {
+    match (old, new) {
+        (Value::Object(a), Value::Object(b)) => {
+            let keys: BTreeSet<_> = a.keys().chain(b.keys()).collect();
+            for key in keys {
+                diff_values(
+                    &format!("{}/{}", path, key),
+                    a.get(key).unwrap_or(&Value::Null),
+                    b.get(key).unwrap_or(&Value::Null),
+                    fields,
+                );
+            }
+        }
+        (Value::Array(a), Value::Array(b)) if a.len() == b.len() => {
+            for (i, (x, y)) in a.iter().zip(b).enumerate() {
+                diff_values(&format!("{}/{}", path, i), x, y, fields);
+            }
+        }
+        _ if old != new => fields.push(json!({ "path": path, "old": old, "new": new })),
+        _ => {}
+    }
+}
+
+/// Write a report to a file or to stdout
+fn write_report(
+    report: &Value,
+    format: &ESerializedType,
+    output: &Option<PathBuf>,
+) -> io::Result<()> {
+    let result = match format {
+        ESerializedType::Yaml => serde_yaml::to_string(report).map_err(|e| e.to_string()),
+        ESerializedType::Toml => {
+            // toml needs a table at the root
+            toml::to_string_pretty(&json!({ "records": report })).map_err(|e| e.to_string())
+        }
+        ESerializedType::Json => serde_json::to_string_pretty(report).map_err(|e| e.to_string()),
+        ESerializedType::Jsonl => serde_json::to_string(report).map_err(|e| e.to_string()),
+        ESerializedType::Msgpack => Err(String::from("Reports can't be written as msgpack")),
+    };
+    let text = match result {
+        Ok(t) => t,
+        Err(e) => return Err(Error::new(ErrorKind::Other, e)),
+    };
+
+    write_text(&text, output)
+}
+
+/// Write text to a file or to stdout
+fn write_text(text: &str, output: &Option<PathBuf>) -> io::Result<()> {
+    match output {
+        Some(path) => File::create(path)?.write_all(text.as_bytes()),
+        // quiet runs only print the number of differing records
+        None if is_quiet() => Ok(()),
+        None => io::stdout().write_all(text.as_bytes()),
+    }
+}
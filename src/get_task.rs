@@ -0,0 +1,75 @@
+use std::{
+    io::{self, Error, ErrorKind},
+    path::PathBuf,
+};
+
+use tes3::esp::{EditorId, TES3Object, TypeInfo};
+
+use crate::{parse_plugin, plugin_file_exists, serialize_object, ESerializedType};
+
+/// Print the records of a plugin with the given editor id to stdout.
+/// Ids are matched case-insensitively, a tag restricts the match to one record type.
+/// Returns the number of printed records
+pub fn get(
+    input: &Option<PathBuf>,
+    id: &str,
+    tag: &Option<String>,
+    format: &Option<ESerializedType>,
+) -> io::Result<usize> {
+    let input_path: &PathBuf;
+    // check no input
+    if let Some(i) = input {
+        input_path = i;
+    } else {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "No input path specified.",
+        ));
+    }
+    if !plugin_file_exists(input_path) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Input path does not exist",
+        ));
+    }
+
+    let plugin = match parse_plugin(input_path) {
+        Ok(p) => p,
+        Err(_) => return Err(Error::new(ErrorKind::Other, "Plugin parsing failed.")),
+    };
+
+    let matches: Vec<&TES3Object> = plugin
+        .objects
+        .iter()
+        .filter(|o| o.editor_id().eq_ignore_ascii_case(id))
+        .filter(|o| {
+            tag.as_ref()
+                .map_or(true, |t| o.tag_str().eq_ignore_ascii_case(t))
+        })
+        .collect();
+    if matches.is_empty() {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            format!("No record found with id {}", id),
+        ));
+    }
+    if matches.len() > 1 {
+        let tags: Vec<&str> = matches.iter().map(|o| o.tag_str()).collect();
+        log::warn!(
+            "{} records share the id {}: {}, use --tag to select one",
+            matches.len(),
+            id,
+            tags.join(", ")
+        );
+    }
+
+    let format = match format {
+        Some(f) => f,
+        None => &ESerializedType::Yaml,
+    };
+    for object in &matches {
+        println!("{}", serialize_object(object, format)?);
+    }
+
+    Ok(matches.len())
+}
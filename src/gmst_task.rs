@@ -0,0 +1,166 @@
+use std::{
+    fs,
+    io::{self, Error, ErrorKind},
+    path::{Path, PathBuf},
+};
+
+use tes3::esp::{GameSetting, GameSettingValue, GlobalValue, GlobalVariable, TES3Object};
+
+use crate::{parse_plugin, save_plugin};
+
+/// Set the values of game settings and globals in a plugin from a NAME=VALUE config file and NAME=VALUE pairs.
+/// Values are parsed as the type of the existing record. Missing records are created:
+/// ids with a game setting prefix (fName, iName, sName) as game settings, all other ids as float globals.
+/// The plugin is saved in place by default. Returns the number of set values
+pub fn set_gmst(
+    input: &Option<PathBuf>,
+    output: &Option<PathBuf>,
+    config: &Option<PathBuf>,
+    sets: &[String],
+    dry_run: bool,
+) -> io::Result<usize> {
+    let input_path: &PathBuf;
+    // check no input
+    if let Some(i) = input {
+        input_path = i;
+    } else {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "No input path specified.",
+        ));
+    }
+    if !input_path.is_file() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Input path does not exist",
+        ));
+    }
+
+    // values on the command line override the config file
+    let mut values = vec![];
+    if let Some(config) = config {
+        values.extend(read_config(config)?);
+    }
+    for set in sets {
+        values.push(parse_pair(set)?);
+    }
+    if values.is_empty() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "No values specified, use --config or --set",
+        ));
+    }
+
+    let mut plugin = match parse_plugin(input_path) {
+        Ok(p) => p,
+        Err(_) => return Err(Error::new(ErrorKind::Other, "Plugin parsing failed.")),
+    };
+
+    for (id, value) in &values {
+        let existing = plugin.objects.iter_mut().find(|o| match o {
+            TES3Object::GameSetting(s) => s.id.eq_ignore_ascii_case(id),
+            TES3Object::GlobalVariable(s) => s.id.eq_ignore_ascii_case(id),
+            _ => false,
+        });
+        match existing {
+            Some(TES3Object::GameSetting(s)) => s.value = parse_setting_value(&s.value, id, value)?,
+            Some(TES3Object::GlobalVariable(s)) => {
+                s.value = parse_global_value(&s.value, id, value)?
+            }
+            _ => {
+                progress!("Creating {}", id);
+                plugin.objects.push(new_record(id, value)?);
+            }
+        }
+    }
+    println!("Set {} values", values.len());
+
+    let output_path = match output {
+        Some(o) => o,
+        None => input_path,
+    };
+    save_plugin(&plugin, output_path, dry_run)?;
+
+    Ok(values.len())
+}
+
+/// Read NAME=VALUE lines, empty lines and lines starting with # or ; are skipped
+fn read_config(path: &Path) -> io::Result<Vec<(String, String)>> {
+    let text = fs::read_to_string(path)?;
+    text.lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#') && !l.starts_with(';'))
+        .map(parse_pair)
+        .collect()
+}
+
+fn parse_pair(text: &str) -> io::Result<(String, String)> {
+    match text.split_once('=') {
+        Some((id, value)) if !id.trim().is_empty() => {
+            Ok((id.trim().to_owned(), value.trim().to_owned()))
+        }
+        _ => Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("Expected NAME=VALUE: {}", text),
+        )),
+    }
+}
+
+fn parse_setting_value(
+    current: &GameSettingValue,
+    id: &str,
+    value: &str,
+) -> io::Result<GameSettingValue> {
+    let parsed = match current {
+        GameSettingValue::Float(_) => value.parse().ok().map(GameSettingValue::Float),
+        GameSettingValue::Integer(_) => value.parse().ok().map(GameSettingValue::Integer),
+        GameSettingValue::String(_) => Some(GameSettingValue::String(value.to_owned())),
+    };
+    parsed.ok_or_else(|| invalid_value(id, value))
+}
+
+fn parse_global_value(current: &GlobalValue, id: &str, value: &str) -> io::Result<GlobalValue> {
+    let parsed = match current {
+        GlobalValue::Float(_) => value.parse().ok().map(GlobalValue::Float),
+        GlobalValue::Short(_) => value.parse().ok().map(GlobalValue::Short),
+        GlobalValue::Long(_) => value.parse().ok().map(GlobalValue::Long),
+    };
+    parsed.ok_or_else(|| invalid_value(id, value))
+}
+
+/// Create a game setting or a global, game setting ids start with f, i or s and an uppercase letter
+fn new_record(id: &str, value: &str) -> io::Result<TES3Object> {
+    let mut chars = id.chars();
+    let prefix = chars.next();
+    let is_setting = chars.next().is_some_and(|c| c.is_ascii_uppercase());
+    let record = match prefix {
+        Some('f') if is_setting => TES3Object::from(GameSetting {
+            id: id.to_owned(),
+            value: parse_setting_value(&GameSettingValue::Float(0.0), id, value)?,
+            ..Default::default()
+        }),
+        Some('i') if is_setting => TES3Object::from(GameSetting {
+            id: id.to_owned(),
+            value: parse_setting_value(&GameSettingValue::Integer(0), id, value)?,
+            ..Default::default()
+        }),
+        Some('s') if is_setting => TES3Object::from(GameSetting {
+            id: id.to_owned(),
+            value: GameSettingValue::String(value.to_owned()),
+            ..Default::default()
+        }),
+        _ => TES3Object::from(GlobalVariable {
+            id: id.to_owned(),
+            value: parse_global_value(&GlobalValue::Float(0.0), id, value)?,
+            ..Default::default()
+        }),
+    };
+    Ok(record)
+}
+
+fn invalid_value(id: &str, value: &str) -> Error {
+    Error::new(
+        ErrorKind::InvalidInput,
+        format!("Invalid value for {}: {}", id, value),
+    )
+}
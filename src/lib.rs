@@ -1,19 +1,56 @@
 use std::{
-    collections::HashMap,
+    borrow::Borrow,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     env, fmt,
     fs::{self, File},
-    io::{self, Error, ErrorKind, Read, Write},
+    io::{self, Error, ErrorKind, IsTerminal, Read, Write},
     path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::{SystemTime, UNIX_EPOCH},
 };
 
 use clap::ValueEnum;
+use flate2::read::GzDecoder;
+use log::{Level, LevelFilter, Log, Metadata, Record};
 use rayon::iter::IntoParallelRefIterator;
 use rayon::prelude::*;
-use tes3::esp::{EditorId, Plugin, Script, TES3Object};
+use regex::Regex;
+use tes3::esp::{EditorId, FileType, Plugin, Script, TES3Object};
 use tes3::{esp::TypeInfo, nif};
 use walkdir::WalkDir;
 
+/// Print a progress message to stdout, unless quiet
+macro_rules! progress {
+    ($($arg:tt)*) => {
+        if !crate::is_quiet() {
+            println!($($arg)*);
+        }
+    };
+}
+
+pub mod assets_task;
+pub mod cells_task;
+pub mod clean_task;
+pub mod convert_task;
+pub mod csv_task;
+pub mod dialogue_task;
+pub mod diff_task;
+pub mod get_task;
+pub mod gmst_task;
+pub mod masters_task;
+pub mod merge_task;
+pub mod remaster_task;
+pub mod rename_task;
+pub mod script_lint;
+pub mod scripts_task;
+pub mod split_task;
 pub mod sql_task;
+pub mod stats_task;
+pub mod validate_task;
+pub mod verify_task;
 
 #[macro_export]
 macro_rules! as_option {
@@ -33,12 +70,87 @@ macro_rules! as_json {
     };
 }
 
-#[derive(Default, Clone, ValueEnum)]
+/// Set by a quiet logger, progress messages are not printed
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Check if progress messages are suppressed
+pub(crate) fn is_quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+/// Logs to a file and to stderr
+pub struct SimpleLogger {
+    log_file: Mutex<File>,
+    level: LevelFilter,
+    quiet: bool,
+}
+
+impl Log for SimpleLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let msg = format!("{} - {}", record.level(), record.args());
+        if let Ok(mut file) = self.log_file.lock() {
+            let _ = writeln!(file, "{}", msg);
+        }
+        if self.quiet {
+            return;
+        }
+
+        // colorize by level on terminals
+        if io::stderr().is_terminal() {
+            let color = match record.level() {
+                Level::Error => "31",
+                Level::Warn => "33",
+                Level::Info => "32",
+                Level::Debug => "34",
+                Level::Trace => "90",
+            };
+            eprintln!("\x1b[{}m{}\x1b[0m", color, msg);
+        } else {
+            eprintln!("{}", msg);
+        }
+    }
+
+    fn flush(&self) {
+        if let Ok(mut file) = self.log_file.lock() {
+            let _ = file.flush();
+        }
+    }
+}
+
+/// Initialize the global logger, writing records up to the given level to a file and to stderr.
+/// If quiet is set only the file is written
+pub fn init_logger(file_name: &Path, level: LevelFilter, quiet: bool) -> io::Result<()> {
+    QUIET.store(quiet, Ordering::Relaxed);
+    let logger = SimpleLogger {
+        log_file: Mutex::new(File::create(file_name)?),
+        level,
+        quiet,
+    };
+    match log::set_boxed_logger(Box::new(logger)) {
+        Ok(_) => {
+            log::set_max_level(level);
+            Ok(())
+        }
+        Err(e) => Err(Error::new(ErrorKind::Other, e.to_string())),
+    }
+}
+
+#[derive(Default, Clone, PartialEq, ValueEnum)]
 pub enum ESerializedType {
     #[default]
     Yaml,
     Toml,
     Json,
+    Msgpack,
+    /// One compact json record per line
+    Jsonl,
 }
 impl fmt::Display for ESerializedType {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -46,9 +158,89 @@ impl fmt::Display for ESerializedType {
             ESerializedType::Yaml => write!(f, "yaml"),
             ESerializedType::Toml => write!(f, "toml"),
             ESerializedType::Json => write!(f, "json"),
+            ESerializedType::Msgpack => write!(f, "mpk"),
+            ESerializedType::Jsonl => write!(f, "jsonl"),
+        }
+    }
+}
+
+/// Text encoding of dumped script files.
+/// Serialized records are always utf-8, so they can be read back by pack
+#[derive(Default, Clone, PartialEq, ValueEnum)]
+pub enum EEncoding {
+    #[default]
+    Utf8,
+    /// Windows-1252, the encoding of the game
+    Cp1252,
+}
+
+/// Encode text, characters that can't be encoded are replaced and logged
+fn encode_text(text: &str, encoding: &EEncoding) -> Vec<u8> {
+    match encoding {
+        EEncoding::Utf8 => text.as_bytes().to_vec(),
+        EEncoding::Cp1252 => {
+            let (bytes, _, unmappable) = encoding_rs::WINDOWS_1252.encode(text);
+            if unmappable {
+                log::warn!("Replaced characters that can't be encoded as Windows-1252");
+            }
+            bytes.into_owned()
+        }
+    }
+}
+
+/// Summary of a task that writes records
+#[derive(Default, Debug)]
+pub struct TaskSummary {
+    /// Number of records written
+    pub records: usize,
+    /// Files or folders written to
+    pub outputs: Vec<PathBuf>,
+    /// Number of records skipped because they could not be written
+    pub skipped: usize,
+}
+impl TaskSummary {
+    fn new(records: usize, output: &Path) -> Self {
+        TaskSummary {
+            records,
+            outputs: vec![output.to_path_buf()],
+            skipped: 0,
+        }
+    }
+
+    /// Render the summary as a sentence, or serialized in the given format
+    pub fn render(&self, format: &Option<ESerializedType>) -> io::Result<String> {
+        match format {
+            Some(format) => {
+                let value = serde_json::json!({
+                    "records": self.records,
+                    "outputs": self.outputs,
+                    "skipped": self.skipped,
+                });
+                report_to_string(format, &value)
+            }
+            None => Ok(self.to_string()),
         }
     }
 }
+impl fmt::Display for TaskSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let outputs: Vec<String> = self
+            .outputs
+            .iter()
+            .map(|o| o.display().to_string())
+            .collect();
+        write!(
+            f,
+            "{} records written to {}",
+            self.records,
+            outputs.join(", ")
+        )?;
+        if self.skipped > 0 {
+            write!(f, ", {} records skipped", self.skipped)?;
+        }
+        Ok(())
+    }
+}
 
 fn is_extension(path: &Path, extension: &str) -> bool {
     match path.extension() {
@@ -60,6 +252,180 @@ fn is_extension(path: &Path, extension: &str) -> bool {
     }
 }
 
+/// Get the extension appended to serialized files, defaults to the format.
+/// Warns if the extension would not be detected as the format by deserialize
+fn get_out_ext(format: &ESerializedType, out_ext: &Option<String>) -> String {
+    let ext = match out_ext {
+        Some(e) => e.trim_start_matches('.').to_owned(),
+        None => return format.to_string(),
+    };
+
+    let detected = Path::new("file").with_extension(&ext);
+    if !is_extension(&detected, format.to_string().as_str()) {
+        log::warn!(
+            "Files with extension .{} will not be detected as {} by deserialize",
+            ext,
+            format
+        );
+    }
+
+    ext
+}
+
+/// Check if a path is a plugin, gzip-compressed plugins (.esp.gz) are included
+fn is_plugin(path: &Path) -> bool {
+    let path = if is_extension(path, "gz") {
+        path.with_extension("")
+    } else {
+        path.to_path_buf()
+    };
+    is_extension(&path, "esp") || is_extension(&path, "esm") || is_omw_plugin(&path)
+}
+
+/// Check if a path is an OpenMW plugin (.omwaddon, .omwscripts)
+fn is_omw_plugin(path: &Path) -> bool {
+    is_extension(path, "omwaddon") || is_extension(path, "omwscripts")
+}
+
+/// Check if a path is an OpenMW script list (.omwscripts), also if gzip-compressed
+fn is_omwscripts(path: &Path) -> bool {
+    let path = if is_extension(path, "gz") {
+        path.with_extension("")
+    } else {
+        path.to_path_buf()
+    };
+    is_extension(&path, "omwscripts")
+}
+
+/// Get all plugins (.esp, .esm, .omwaddon and their .gz) in a folder non-recursively, in directory order.
+/// OpenMW script lists (.omwscripts) are only included if use_omw_plugins is set
+pub fn get_plugins_in_folder(folder: &Path, use_omw_plugins: bool) -> io::Result<Vec<PathBuf>> {
+    let mut results = vec![];
+    for entry in fs::read_dir(folder)?.flatten() {
+        let path = entry.path();
+        if !path.is_file() {
+            continue;
+        }
+        if is_plugin(&path) && (use_omw_plugins || !is_omwscripts(&path)) {
+            results.push(path);
+        }
+    }
+    Ok(results)
+}
+
+/// Keep the plugins whose file name matches a glob pattern, case-insensitively
+pub(crate) fn filter_plugins_by_glob(
+    plugins: Vec<PathBuf>,
+    pattern: &Option<String>,
+) -> io::Result<Vec<PathBuf>> {
+    let Some(pattern) = pattern else {
+        return Ok(plugins);
+    };
+    let pattern = match glob::Pattern::new(pattern) {
+        Ok(p) => p,
+        Err(e) => return Err(Error::new(ErrorKind::InvalidInput, e.to_string())),
+    };
+    let options = glob::MatchOptions {
+        case_sensitive: false,
+        ..Default::default()
+    };
+    Ok(plugins
+        .into_iter()
+        .filter(|p| {
+            p.file_name()
+                .is_some_and(|n| pattern.matches_with(&n.to_string_lossy(), options))
+        })
+        .collect())
+}
+
+/// The error of a task pointed at a folder without plugins, so that it doesn't silently do nothing
+pub(crate) fn no_plugins_error(folder: &Path) -> Error {
+    let message = format!("No plugins found in {}", folder.display());
+    log::warn!("{}", message);
+    Error::new(ErrorKind::NotFound, message)
+}
+
+/// Get all plugins in a folder in load order, masters first and then by file modified time.
+/// Plugins whose modified time can't be read sort first.
+/// If a config file (openmw.cfg or Morrowind.ini) is given, its load order takes precedence
+pub fn get_plugins_sorted(
+    folder: &Path,
+    use_omw_plugins: bool,
+    cfg: &Option<PathBuf>,
+) -> io::Result<Vec<PathBuf>> {
+    // fetch modified times up front
+    let mut timed: Vec<(PathBuf, SystemTime)> = get_plugins_in_folder(folder, use_omw_plugins)?
+        .into_iter()
+        .map(|path| {
+            let time = fs::metadata(&path)
+                .and_then(|m| m.modified())
+                .unwrap_or(UNIX_EPOCH);
+            (path, time)
+        })
+        .collect();
+    timed.sort_by(|(a, a_time), (b, b_time)| {
+        (!is_extension(a, "esm"), a_time).cmp(&(!is_extension(b, "esm"), b_time))
+    });
+    let mut plugins: Vec<PathBuf> = timed.into_iter().map(|(path, _)| path).collect();
+
+    if let Some(cfg) = cfg {
+        plugins = apply_load_order(plugins, &read_load_order(cfg)?);
+    }
+    Ok(plugins)
+}
+
+/// Read the load order from an openmw.cfg (content= lines) or a Morrowind.ini (GameFile lines)
+pub fn read_load_order(cfg: &Path) -> io::Result<Vec<String>> {
+    // Morrowind.ini is usually not utf8
+    let text = String::from_utf8_lossy(&fs::read(cfg)?).to_string();
+
+    let mut game_files = vec![];
+    for line in text.lines() {
+        let Some((key, value)) = line.trim().split_once('=') else {
+            continue;
+        };
+        let key = key.trim().to_lowercase();
+        let value = value.trim().to_owned();
+        if key == "content" {
+            // openmw.cfg, in order of appearance
+            game_files.push((game_files.len(), value));
+        } else if let Some(index) = key.strip_prefix("gamefile") {
+            // Morrowind.ini, GameFile0=Morrowind.esm
+            if let Ok(i) = index.parse::<usize>() {
+                game_files.push((i, value));
+            }
+        }
+    }
+
+    game_files.sort_by_key(|(i, _)| *i);
+    Ok(game_files.into_iter().map(|(_, name)| name).collect())
+}
+
+/// Order plugins by a load order list of file names.
+/// Plugins not in the load order are appended at the end, missing files in the load order are skipped
+pub fn apply_load_order(plugins: Vec<PathBuf>, load_order: &[String]) -> Vec<PathBuf> {
+    let mut remaining = plugins;
+    let mut sorted = vec![];
+    for name in load_order {
+        let position = remaining.iter().position(|p| {
+            p.file_name()
+                .is_some_and(|f| f.to_string_lossy().eq_ignore_ascii_case(name))
+        });
+        match position {
+            Some(i) => sorted.push(remaining.remove(i)),
+            None => log::warn!("Plugin in load order not found, skipping: {}", name),
+        }
+    }
+
+    sorted.extend(remaining);
+    sorted
+}
+
+/// CRC32 of the file contents, identical plugins have the same crc regardless of their name
+pub fn file_crc(path: &Path) -> io::Result<u32> {
+    Ok(crc32fast::hash(&fs::read(path)?))
+}
+
 // https://internals.rust-lang.org/t/pathbuf-has-set-extension-but-no-add-extension-cannot-cleanly-turn-tar-to-tar-gz/14187/11
 pub fn append_ext(ext: impl AsRef<std::ffi::OsStr>, path: PathBuf) -> PathBuf {
     let mut os_string: std::ffi::OsString = path.into();
@@ -71,9 +437,16 @@ pub fn append_ext(ext: impl AsRef<std::ffi::OsStr>, path: PathBuf) -> PathBuf {
 /// Parse the contents of the given path into a TES3 Plugin.
 /// Whether to parse as JSON or binary is inferred from first character.
 /// taken from: https://github.com/Greatness7/tes3conv
+/// Plugins in zip archives are read from paths of the form archive.zip!inner/plugin.esp
 fn parse_plugin(path: &PathBuf) -> io::Result<Plugin> {
-    let mut raw_data = vec![];
-    File::open(path)?.read_to_end(&mut raw_data)?;
+    let mut raw_data = read_plugin_bytes(path)?;
+
+    // decompress gzip-compressed plugins
+    if raw_data.starts_with(&[0x1f, 0x8b]) {
+        let mut decompressed = vec![];
+        GzDecoder::new(raw_data.as_slice()).read_to_end(&mut decompressed)?;
+        raw_data = decompressed;
+    }
 
     let mut plugin = Plugin::new();
 
@@ -88,21 +461,108 @@ fn parse_plugin(path: &PathBuf) -> io::Result<Plugin> {
         }
     }
 
-    // sort objects so that diffs are a little more useful
-    //plugin.sort();    //TODO
-
     Ok(plugin)
 }
 
+/// Read the bytes of a plugin file or of a plugin in a zip archive
+fn read_plugin_bytes(path: &Path) -> io::Result<Vec<u8>> {
+    let mut data = vec![];
+    match split_archive_path(path) {
+        Some((archive_path, entry)) => {
+            let mut archive = zip::ZipArchive::new(File::open(archive_path)?)?;
+            // mods are often packed on windows, match the entry case-insensitively
+            let name = archive
+                .file_names()
+                .find(|n| n.eq_ignore_ascii_case(&entry))
+                .map(str::to_owned)
+                .unwrap_or(entry);
+            archive.by_name(&name)?.read_to_end(&mut data)?;
+        }
+        None => {
+            File::open(path)?.read_to_end(&mut data)?;
+        }
+    }
+    Ok(data)
+}
+
+/// Split a path of the form archive.zip!inner/plugin.esp into the archive path and the entry name
+pub fn split_archive_path(path: &Path) -> Option<(PathBuf, String)> {
+    let text = path.to_string_lossy();
+    let index = text.to_ascii_lowercase().find(".zip!")? + ".zip".len();
+    let (archive, entry) = text.split_at(index);
+    Some((PathBuf::from(archive), entry[1..].replace('\\', "/")))
+}
+
+/// Check if a path is an existing file, or an entry in an existing zip archive
+pub fn plugin_file_exists(path: &Path) -> bool {
+    match split_archive_path(path) {
+        Some((archive_path, _)) => archive_path.is_file(),
+        None => path.is_file(),
+    }
+}
+
+/// Sort objects so that diffs are a little more useful.
+/// Records are grouped by tag and sorted by editor id, the header always stays first.
+/// INFO records stay behind their DIAL topic in their original order
+pub fn sort_objects(objects: &mut Vec<TES3Object>) {
+    let mut groups = group_topics(objects);
+    groups.sort_by_cached_key(|group| {
+        let object = &group[0];
+        (
+            object.tag_str() != "TES3",
+            object.tag_str().to_owned(),
+            object.editor_id().to_lowercase(),
+        )
+    });
+    objects.extend(groups.into_iter().flatten());
+}
+
+/// Drain objects into groups of one record, or of a DIAL record and the INFO records behind it
+fn group_topics(objects: &mut Vec<TES3Object>) -> Vec<Vec<TES3Object>> {
+    let mut groups: Vec<Vec<TES3Object>> = vec![];
+    for object in objects.drain(..) {
+        let is_info = matches!(object, TES3Object::DialogueInfo(_));
+        match groups.last_mut() {
+            Some(group) if is_info && matches!(group[0], TES3Object::Dialogue(_)) => {
+                group.push(object)
+            }
+            _ => groups.push(vec![object]),
+        }
+    }
+    groups
+}
+
 ///////////////////////////////////////////////////////////////////////////
 // Serialize
 
+/// Options for serializing a plugin
+#[derive(Default)]
+pub struct SerializeOptions {
+    /// The format to serialize to, inferred from the output extension or yaml
+    pub format: Option<ESerializedType>,
+    /// Override the extension of the serialized file
+    pub out_ext: Option<String>,
+    /// Emit a json object keyed by "TAG:id" instead of a list of records
+    pub keyed: bool,
+    /// Sort records by tag and editor id
+    pub sorted: bool,
+    /// Write json without whitespace
+    pub compact: bool,
+    /// Only serialize the first n records after the header
+    pub limit: Option<usize>,
+    /// Serialize records one by one and skip the ones that fail.
+    /// This is also done if serializing the whole plugin fails
+    pub lenient: bool,
+    /// Write arrays of small tables as inline tables, only available for toml
+    pub toml_inline: bool,
+}
+
 /// Serialize a plugin to a human-readable format
 pub fn serialize_plugin(
     input: &Option<PathBuf>,
     output: &Option<PathBuf>,
-    cformat: &Option<ESerializedType>,
-) -> io::Result<()> {
+    options: &SerializeOptions,
+) -> io::Result<TaskSummary> {
     let input_path: &PathBuf;
     // check no input
     if let Some(i) = input {
@@ -114,84 +574,550 @@ pub fn serialize_plugin(
         ));
     }
     // check input path exists and check if file or directory
-    if !input_path.exists()
-        || (input_path.exists()
-            && (!input_path.is_file()
-                || !(is_extension(input_path, "esp")
-                    || is_extension(input_path, "esm")
-                    || is_extension(input_path, "omwaddon"))))
-    {
+    if !plugin_file_exists(input_path) || !is_plugin(input_path) {
         return Err(Error::new(
             ErrorKind::InvalidInput,
             "Input path does not exist",
         ));
     }
 
-    let format = match cformat {
-        Some(f) => f,
-        None => &ESerializedType::Yaml,
+    // an explicit output with a serialization extension determines the format
+    let output_format = output
+        .as_ref()
+        .filter(|o| o.as_os_str() != "-")
+        .and_then(|o| format_from_path(o));
+    let format = match (&output_format, &options.format) {
+        (Some(o), Some(f)) if o != f => {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Output extension {} conflicts with format {}", o, f),
+            ))
+        }
+        (Some(o), _) => o.clone(),
+        (None, Some(f)) => f.clone(),
+        (None, None) => ESerializedType::Yaml,
     };
+    if options.keyed && !matches!(format, ESerializedType::Json) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Keyed output is only available for json",
+        ));
+    }
+    if options.toml_inline && !matches!(format, ESerializedType::Toml) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Inline tables are only available for toml",
+        ));
+    }
+
+    // an output of - writes to stdout
+    let to_stdout = output.as_ref().is_some_and(|o| o.as_os_str() == "-");
 
     let mut output_path = PathBuf::from(input_path.clone().to_str().unwrap());
     // check no input
     if let Some(i) = output {
         output_path = i.to_path_buf();
     }
-    output_path = append_ext(format.to_string(), output_path);
+    if output_format.is_none() {
+        output_path = append_ext(get_out_ext(&format, &options.out_ext), output_path);
+    }
 
     let plugin_or_error = parse_plugin(input_path);
     // parse plugin
     // write
     match plugin_or_error {
-        Ok(plugin) => {
-            let text = match format {
-                ESerializedType::Yaml => {
-                    let result = serde_yaml::to_string(&plugin);
-                    match result {
-                        Ok(t) => t,
-                        Err(e) => {
-                            return Err(Error::new(ErrorKind::Other, e.to_string()));
-                        }
+        Ok(mut plugin) => {
+            if options.sorted {
+                sort_objects(&mut plugin.objects);
+            }
+            if let Some(limit) = options.limit {
+                truncate_records(&mut plugin.objects, limit);
+            }
+
+            let (mut data, skipped) = if options.lenient {
+                serialize_plugin_lenient(&mut plugin, &format, options.keyed, options.compact)?
+            } else {
+                match serialize_plugin_bytes(&plugin, &format, options.keyed, options.compact) {
+                    Ok(d) => (d, 0),
+                    Err(e) => {
+                        log::warn!("{}, serializing records one by one", e);
+                        serialize_plugin_lenient(
+                            &mut plugin,
+                            &format,
+                            options.keyed,
+                            options.compact,
+                        )?
                     }
                 }
-                ESerializedType::Toml => {
-                    let result = toml::to_string_pretty(&plugin);
-                    match result {
-                        Ok(t) => t,
-                        Err(e) => {
-                            return Err(Error::new(ErrorKind::Other, e.to_string()));
-                        }
-                    }
+            };
+            if options.toml_inline {
+                data = inline_toml_tables(&data)?;
+            }
+
+            if to_stdout {
+                io::stdout().write_all(&data)?;
+            } else {
+                File::create(&output_path)?.write_all(&data)?;
+            }
+            let mut summary = TaskSummary::new(plugin.objects.len(), &output_path);
+            summary.skipped = skipped;
+            Ok(summary)
+        }
+        Err(_) => Err(Error::new(ErrorKind::Other, "Plugin parsing failed.")),
+    }
+}
+
+/// Serialize a plugin without the records that fail to serialize on their own.
+/// The document is assembled from the records serialized one by one,
+/// the skipped records are logged and removed from the plugin.
+/// Returns the document and the number of skipped records
+fn serialize_plugin_lenient(
+    plugin: &mut Plugin,
+    format: &ESerializedType,
+    keyed: bool,
+    compact: bool,
+) -> io::Result<(Vec<u8>, usize)> {
+    let count = plugin.objects.len();
+    let mut records = vec![];
+    plugin
+        .objects
+        .retain(|object| match serialize_bytes(format, object, compact) {
+            Ok(data) => {
+                records.push(data);
+                true
+            }
+            Err(e) => {
+                log::warn!(
+                    "Skipping {} {}: {}",
+                    object.type_name(),
+                    object.editor_id(),
+                    e
+                );
+                false
+            }
+        });
+    let skipped = count - plugin.objects.len();
+    if skipped > 0 {
+        log::warn!("Skipped {} of {} records", skipped, count);
+    }
+
+    let data = if keyed {
+        let mut map = serde_json::Map::new();
+        for (object, record) in plugin.objects.iter().zip(&records) {
+            insert_keyed(&mut map, object, serde_json::from_slice(record)?);
+        }
+        let result = if compact {
+            serde_json::to_vec(&map)
+        } else {
+            serde_json::to_vec_pretty(&map)
+        };
+        result?
+    } else {
+        assemble_records(format, &records, compact)?
+    };
+    Ok((data, skipped))
+}
+
+/// Assemble records serialized one by one into the document of a plugin.
+/// An empty plugin is serialized to find where the records go
+fn assemble_records(
+    format: &ESerializedType,
+    records: &[Vec<u8>],
+    compact: bool,
+) -> io::Result<Vec<u8>> {
+    let layout_error = || {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("Unexpected {} plugin layout", format),
+        )
+    };
+    if let ESerializedType::Jsonl = format {
+        let mut data = vec![];
+        for record in records {
+            data.extend_from_slice(record);
+            data.push(b'\n');
+        }
+        return Ok(data);
+    }
+    let empty = serialize_plugin_bytes(&Plugin::new(), format, false, compact)?;
+    if records.is_empty() {
+        return Ok(empty);
+    }
+
+    if let ESerializedType::Msgpack = format {
+        // the empty record array is the last byte
+        let Some((&0x90, wrapper)) = empty.split_last() else {
+            return Err(layout_error());
+        };
+        let mut data = wrapper.to_vec();
+        let len = records.len();
+        if len < 16 {
+            data.push(0x90 | len as u8);
+        } else if let Ok(len) = u16::try_from(len) {
+            data.push(0xdc);
+            data.extend_from_slice(&len.to_be_bytes());
+        } else {
+            data.push(0xdd);
+            data.extend_from_slice(&(len as u32).to_be_bytes());
+        }
+        for record in records {
+            data.extend_from_slice(record);
+        }
+        return Ok(data);
+    }
+
+    let mut text = String::from_utf8(empty).map_err(|_| layout_error())?;
+    let mut records_text = vec![];
+    for record in records {
+        match std::str::from_utf8(record) {
+            Ok(t) => records_text.push(t.trim_end()),
+            Err(e) => return Err(Error::new(ErrorKind::InvalidData, e.to_string())),
+        }
+    }
+
+    if let ESerializedType::Toml = format {
+        let mut document = text
+            .parse::<toml_edit::DocumentMut>()
+            .map_err(|_| layout_error())?;
+        let Some(key) = document
+            .iter()
+            .find(|(_, item)| item.is_array())
+            .map(|(key, _)| key.to_owned())
+        else {
+            return Err(layout_error());
+        };
+        let mut tables = toml_edit::ArrayOfTables::new();
+        for record in records_text {
+            match record.parse::<toml_edit::DocumentMut>() {
+                Ok(d) => tables.push(d.as_table().clone()),
+                Err(e) => return Err(Error::new(ErrorKind::InvalidData, e.to_string())),
+            }
+        }
+        document[key.as_str()] = toml_edit::Item::ArrayOfTables(tables);
+        return Ok(document.to_string().into_bytes());
+    }
+
+    // json and yaml write the empty record array as []
+    let Some(pos) = text.find("[]") else {
+        return Err(layout_error());
+    };
+    let line_start = text[..pos].rfind('\n').map_or(0, |i| i + 1);
+    let line = &text[line_start..pos];
+    let indent = " ".repeat(line.len() - line.trim_start().len());
+    let (range, array) = match format {
+        ESerializedType::Json if compact => (pos..pos + 2, format!("[{}]", records_text.join(","))),
+        ESerializedType::Json => {
+            let items: Vec<String> = records_text
+                .iter()
+                .map(|r| {
+                    r.lines()
+                        .map(|l| format!("{}  {}", indent, l))
+                        .collect::<Vec<_>>()
+                        .join("\n")
+                })
+                .collect();
+            (
+                pos..pos + 2,
+                format!("[\n{}\n{}]", items.join(",\n"), indent),
+            )
+        }
+        _ => {
+            let items: Vec<String> = records_text
+                .iter()
+                .map(|r| {
+                    r.lines()
+                        .enumerate()
+                        .map(|(i, l)| {
+                            let marker = if i == 0 { "- " } else { "  " };
+                            format!("{}{}{}", indent, marker, l)
+                        })
+                        .collect::<Vec<_>>()
+                        .join("\n")
+                })
+                .collect();
+            if line.trim().is_empty() {
+                // a sequence at the root
+                (line_start..pos + 2, items.join("\n"))
+            } else {
+                // a sequence in a mapping starts on the next line
+                let start = if line.ends_with(' ') { pos - 1 } else { pos };
+                (start..pos + 2, format!("\n{}", items.join("\n")))
+            }
+        }
+    };
+    text.replace_range(range, &array);
+    Ok(text.into_bytes())
+}
+
+/// Keep only the first n records after the header, the skipped records are logged
+fn truncate_records<T: Borrow<TES3Object>>(objects: &mut Vec<T>, limit: usize) {
+    let has_header = objects
+        .first()
+        .is_some_and(|o| matches!(o.borrow(), TES3Object::Header(_)));
+    let keep = limit + usize::from(has_header);
+    if objects.len() <= keep {
+        return;
+    }
+    log::info!(
+        "Limit of {} records reached, skipping {} records",
+        limit,
+        objects.len() - keep
+    );
+    for object in &objects[keep..] {
+        let object = object.borrow();
+        log::debug!("Skipping {} {}", object.tag_str(), object.editor_id());
+    }
+    objects.truncate(keep);
+}
+
+/// Serialize a whole plugin to one document, json is written without whitespace if compact is set
+pub(crate) fn serialize_plugin_bytes(
+    plugin: &Plugin,
+    format: &ESerializedType,
+    keyed: bool,
+    compact: bool,
+) -> io::Result<Vec<u8>> {
+    let data = match format {
+        ESerializedType::Yaml => {
+            let result = serde_yaml::to_string(plugin);
+            match result {
+                Ok(t) => t.into_bytes(),
+                Err(e) => {
+                    return Err(Error::new(ErrorKind::Other, e.to_string()));
                 }
-                ESerializedType::Json => {
-                    let result = serde_json::to_string_pretty(&plugin);
-                    match result {
-                        Ok(t) => t,
-                        Err(e) => {
-                            return Err(Error::new(ErrorKind::Other, e.to_string()));
-                        }
-                    }
+            }
+        }
+        ESerializedType::Toml => {
+            let result = toml::to_string_pretty(plugin);
+            match result {
+                Ok(t) => t.into_bytes(),
+                Err(e) => {
+                    // name the first record toml can't represent
+                    let record = plugin
+                        .objects
+                        .iter()
+                        .find(|o| serialize_object(o, format).is_err())
+                        .map(|o| format!(" ({} {})", o.type_name(), o.editor_id()))
+                        .unwrap_or_default();
+                    return Err(Error::new(
+                        ErrorKind::Other,
+                        format!(
+                            "{}{}, serialize this plugin to yaml or json instead",
+                            e, record
+                        ),
+                    ));
+                }
+            }
+        }
+        ESerializedType::Json => {
+            let result = match (keyed, compact) {
+                (true, true) => {
+                    to_keyed_json(&plugin.objects).and_then(|map| serde_json::to_string(&map))
                 }
+                (true, false) => to_keyed_json(&plugin.objects)
+                    .and_then(|map| serde_json::to_string_pretty(&map)),
+                (false, true) => serde_json::to_string(plugin),
+                (false, false) => serde_json::to_string_pretty(plugin),
             };
+            match result {
+                Ok(t) => t.into_bytes(),
+                Err(e) => {
+                    return Err(Error::new(ErrorKind::Other, e.to_string()));
+                }
+            }
+        }
+        ESerializedType::Jsonl => to_jsonl(plugin.objects.iter())?,
+        ESerializedType::Msgpack => {
+            let result = rmp_serde::to_vec_named(plugin);
+            match result {
+                Ok(b) => b,
+                Err(e) => {
+                    return Err(Error::new(ErrorKind::Other, e.to_string()));
+                }
+            }
+        }
+    };
+    Ok(data)
+}
 
-            File::create(output_path)?.write_all(text.as_bytes())
+/// Maximum length of a table that is written inline
+const TOML_INLINE_WIDTH: usize = 80;
+
+/// Rewrite arrays of small tables in a toml document as arrays of inline tables, one table per line.
+/// The toml serializer always writes arrays of tables as [[table]] sections
+fn inline_toml_tables(data: &[u8]) -> io::Result<Vec<u8>> {
+    let text = match std::str::from_utf8(data) {
+        Ok(t) => t,
+        Err(e) => return Err(Error::new(ErrorKind::InvalidData, e.to_string())),
+    };
+    let mut document = match text.parse::<toml_edit::DocumentMut>() {
+        Ok(d) => d,
+        Err(e) => return Err(Error::new(ErrorKind::InvalidData, e.to_string())),
+    };
+    inline_toml_table(document.as_table_mut());
+    Ok(document.to_string().into_bytes())
+}
+
+fn inline_toml_table(table: &mut toml_edit::Table) {
+    for (_, item) in table.iter_mut() {
+        match item {
+            toml_edit::Item::Table(t) => inline_toml_table(t),
+            toml_edit::Item::ArrayOfTables(tables) => match to_inline_array(tables) {
+                Some(array) => *item = toml_edit::Item::Value(toml_edit::Value::Array(array)),
+                None => tables.iter_mut().for_each(inline_toml_table),
+            },
+            _ => {}
         }
-        Err(_) => Err(Error::new(ErrorKind::Other, "Plugin parsing failed.")),
     }
 }
 
+/// Convert an array of tables to an inline array if all tables are short and only hold values
+fn to_inline_array(tables: &toml_edit::ArrayOfTables) -> Option<toml_edit::Array> {
+    let mut array = toml_edit::Array::new();
+    for table in tables.iter() {
+        if !table.iter().all(|(_, item)| item.is_value()) {
+            return None;
+        }
+        let mut inline = table.clone().into_inline_table();
+        inline.fmt();
+        if inline.to_string().trim().len() > TOML_INLINE_WIDTH {
+            return None;
+        }
+        let mut value = toml_edit::Value::InlineTable(inline);
+        value.decor_mut().set_prefix("\n    ");
+        array.push_formatted(value);
+    }
+    array.set_trailing_comma(true);
+    array.set_trailing("\n");
+    Some(array)
+}
+
+/// Map records to a json object keyed by "TAG:id".
+/// The tag prefix avoids collisions between records of different types,
+/// duplicate ids of the same type are disambiguated with a "#n" suffix in order of appearance
+fn to_keyed_json(
+    objects: &[TES3Object],
+) -> serde_json::Result<serde_json::Map<String, serde_json::Value>> {
+    let mut map = serde_json::Map::new();
+    for object in objects {
+        insert_keyed(&mut map, object, serde_json::to_value(object)?);
+    }
+    Ok(map)
+}
+
+/// Insert a serialized record keyed by "TAG:id", duplicate keys get a "#n" suffix
+fn insert_keyed(
+    map: &mut serde_json::Map<String, serde_json::Value>,
+    object: &TES3Object,
+    value: serde_json::Value,
+) {
+    let key = format!("{}:{}", object.tag_str(), object.editor_id());
+    let mut unique_key = key.clone();
+    let mut n = 2;
+    while map.contains_key(&unique_key) {
+        unique_key = format!("{}#{}", key, n);
+        n += 1;
+    }
+    map.insert(unique_key, value);
+}
+
 ///////////////////////////////////////////////////////////////////////////
 // Dump
 
+/// Options for dumping records from a plugin
+#[derive(Default)]
+pub struct DumpOptions {
+    /// Create folder with plugin name, only available if input is a file
+    pub create: bool,
+    /// Include specific records
+    pub include: Vec<String>,
+    /// Exclude specific records
+    pub exclude: Vec<String>,
+    /// The format to serialize to, default is yaml
+    pub format: Option<ESerializedType>,
+    /// Override the extension of serialized files
+    pub out_ext: Option<String>,
+    /// Sort records by tag and editor id
+    pub sorted: bool,
+    /// Config file to read the load order of a folder from (openmw.cfg or Morrowind.ini)
+    pub cfg: Option<PathBuf>,
+    /// Find plugins in subfolders of the input folder
+    pub recursive: bool,
+    /// Only include records whose editor id matches this regex
+    pub id_include: Option<String>,
+    /// Exclude records whose editor id matches this regex
+    pub id_exclude: Option<String>,
+    /// Write all records of a plugin into one file named after the plugin
+    pub single_file: bool,
+    /// Include OpenMW script lists (.omwscripts) when dumping a folder
+    pub omw: bool,
+    /// Check the structure of dumped scripts and log warnings
+    pub lint_scripts: bool,
+    /// Only dump records that are new or changed relative to this plugin
+    pub baseline: Option<PathBuf>,
+    /// Write records directly into the output folder instead of one subfolder per type.
+    /// Records of different types with the same id are prefixed with their tag
+    pub flatten: bool,
+    /// Write json without whitespace
+    pub compact: bool,
+    /// Write a manifest.yaml listing the dumped files to the output folder of each plugin.
+    /// Not available with single_file
+    pub manifest: bool,
+    /// Only dump the first n records after the header of each plugin
+    pub limit: Option<usize>,
+    /// Write arrays of small tables as inline tables, only available for toml
+    pub toml_inline: bool,
+    /// The encoding of the .mwscript files, default is utf-8
+    pub encoding: EEncoding,
+    /// Only dump plugins of a folder whose file name matches this glob pattern
+    pub glob: Option<String>,
+}
+
+/// Editor id filters compiled from the dump options
+struct IdFilters {
+    include: Option<Regex>,
+    exclude: Option<Regex>,
+}
+
+impl IdFilters {
+    fn new(options: &DumpOptions) -> io::Result<Self> {
+        Ok(IdFilters {
+            include: compile_regex(&options.id_include)?,
+            exclude: compile_regex(&options.id_exclude)?,
+        })
+    }
+
+    /// Check if an editor id passes the filters, exclusion wins over inclusion
+    fn matches(&self, id: &str) -> bool {
+        if let Some(exclude) = &self.exclude {
+            if exclude.is_match(id) {
+                return false;
+            }
+        }
+        match &self.include {
+            Some(include) => include.is_match(id),
+            None => true,
+        }
+    }
+}
+
+/// Compile a regex, empty patterns mean no filtering
+fn compile_regex(pattern: &Option<String>) -> io::Result<Option<Regex>> {
+    match pattern {
+        Some(p) if !p.is_empty() => match Regex::new(p) {
+            Ok(r) => Ok(Some(r)),
+            Err(e) => Err(Error::new(ErrorKind::InvalidInput, e.to_string())),
+        },
+        _ => Ok(None),
+    }
+}
+
 /// Dump data from an esp into files
 pub fn dump(
     input: &Option<PathBuf>,
     out_dir: &Option<PathBuf>,
-    create: bool,
-    include: &[String],
-    exclude: &[String],
-    serialized_type: &Option<ESerializedType>,
-) -> io::Result<()> {
+    options: &DumpOptions,
+) -> io::Result<TaskSummary> {
     let mut is_file = false;
     let mut is_dir = false;
 
@@ -206,21 +1132,15 @@ pub fn dump(
         ));
     }
     // check input path exists and check if file or directory
-    if !input_path.exists() {
+    if plugin_file_exists(input_path) {
+        is_file = is_plugin(input_path);
+    } else if input_path.is_dir() {
+        is_dir = true;
+    } else {
         return Err(Error::new(
             ErrorKind::InvalidInput,
             "Input path does not exist",
         ));
-    } else if input_path.is_file() {
-        let ext = input_path.extension();
-        if let Some(e) = ext {
-            let e_str = e.to_str().unwrap().to_lowercase();
-            if e_str == "esp" || e_str == "esm" || e_str == "omwaddon" {
-                is_file = true;
-            }
-        }
-    } else if input_path.is_dir() {
-        is_dir = true;
     }
 
     // check output path, default is cwd
@@ -231,29 +1151,39 @@ pub fn dump(
 
     // check serialized type, default is yaml
     let mut stype = &ESerializedType::Yaml;
-    if let Some(t) = serialized_type {
+    if let Some(t) = &options.format {
         stype = t;
     }
+    if options.toml_inline && !matches!(stype, ESerializedType::Toml) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Inline tables are only available for toml",
+        ));
+    }
+    let ext = get_out_ext(stype, &options.out_ext);
+    let filters = IdFilters::new(options)?;
+    let baseline = match &options.baseline {
+        Some(path) => match parse_plugin(path) {
+            Ok(p) => Some(get_baseline_records(&p)),
+            Err(_) => return Err(Error::new(ErrorKind::Other, "Baseline parsing failed.")),
+        },
+        None => None,
+    };
+    let mut summary = TaskSummary::default();
 
     // dump plugin file
     if is_file {
-        if create {
-            match dump_plugin(
-                input_path,
-                &out_dir_path.join(input_path.file_stem().unwrap()),
-                include,
-                exclude,
-                stype,
-            ) {
-                Ok(_) => {}
-                Err(e) => return Err(e),
-            }
+        let out_path = if options.create {
+            out_dir_path.join(input_path.file_stem().unwrap())
         } else {
-            match dump_plugin(input_path, out_dir_path, include, exclude, stype) {
-                Ok(_) => {}
-                Err(e) => return Err(e),
-            }
-        }
+            out_dir_path.to_owned()
+        };
+        let (records, skipped) = dump_plugin(
+            input_path, &out_path, options, &filters, &baseline, stype, &ext,
+        )?;
+        summary.records += records;
+        summary.skipped += skipped;
+        summary.outputs.push(out_path);
     }
 
     // dump folder
@@ -261,47 +1191,104 @@ pub fn dump(
     // dumps scripts into cwd/a/ and cwd/b
     // check if already exists?
     if is_dir {
-        // get all plugins non-recursively
-        let paths = fs::read_dir(input_path).unwrap();
-        for entry in paths.flatten() {
-            let path = entry.path();
-            if path.is_file() && path.exists() {
-                let ext = path.extension();
-                if let Some(e) = ext {
-                    let e_str = e.to_str().unwrap().to_lowercase();
-
-                    if e_str == "esp" || e_str == "esm" || e_str == "omwaddon" {
-                        // dump scripts into folders named after the plugin name
-                        let plugin_name = path.file_stem().unwrap();
-                        let out_path = &out_dir_path.join(plugin_name);
-
-                        match dump_plugin(&path, out_path, include, exclude, stype) {
-                            Ok(_) => {}
-                            Err(e) => return Err(e),
-                        }
-                    }
+        let mut plugins = vec![];
+        if options.recursive {
+            // get all plugins recursively
+            for entry in WalkDir::new(input_path).into_iter().filter_map(|e| e.ok()) {
+                let path = entry.path();
+                if entry.file_type().is_file()
+                    && is_plugin(path)
+                    && (options.omw || !is_omwscripts(path))
+                {
+                    plugins.push(path.to_path_buf());
                 }
             }
+        } else {
+            // get all plugins non-recursively
+            plugins = get_plugins_in_folder(input_path, options.omw)?;
+        }
+        if let Some(cfg) = &options.cfg {
+            plugins = apply_load_order(plugins, &read_load_order(cfg)?);
+        }
+        plugins = filter_plugins_by_glob(plugins, &options.glob)?;
+        if plugins.is_empty() {
+            return Err(no_plugins_error(input_path));
+        }
+
+        for path in plugins {
+            // dump scripts into folders named after the plugin name
+            // subfolders of the input folder are kept
+            let plugin_name = path.file_stem().unwrap();
+            let relative_dir = path
+                .parent()
+                .and_then(|p| p.strip_prefix(input_path).ok())
+                .unwrap_or(Path::new(""));
+            let out_path = &out_dir_path.join(relative_dir).join(plugin_name);
+
+            let (records, skipped) =
+                dump_plugin(&path, out_path, options, &filters, &baseline, stype, &ext)?;
+            summary.records += records;
+            summary.skipped += skipped;
+            summary.outputs.push(out_path.to_owned());
         }
     }
 
-    Ok(())
+    Ok(summary)
+}
+
+/// Serialized records of a baseline plugin by tag and lowercase editor id
+type BaselineRecords = HashMap<(String, String), String>;
+
+fn get_baseline_records(plugin: &Plugin) -> BaselineRecords {
+    plugin
+        .objects
+        .iter()
+        .filter(|o| !o.editor_id().is_empty())
+        .map(|o| {
+            let key = (o.tag_str().to_owned(), o.editor_id().to_lowercase());
+            (key, serde_json::to_string(o).unwrap_or_default())
+        })
+        .collect()
 }
 
-/// Dumps one plugin
+/// Check if a record exists unchanged in the baseline, records without id are never skipped
+fn is_in_baseline(baseline: &BaselineRecords, object: &TES3Object) -> bool {
+    if object.editor_id().is_empty() {
+        return false;
+    }
+    let key = (
+        object.tag_str().to_owned(),
+        object.editor_id().to_lowercase(),
+    );
+    baseline
+        .get(&key)
+        .is_some_and(|text| *text == serde_json::to_string(object).unwrap_or_default())
+}
+
+/// Dumps one plugin, returns the number of written records and of records that failed to write
 fn dump_plugin(
     input: &PathBuf,
     out_dir_path: &Path,
-    include: &[String],
-    exclude: &[String],
+    options: &DumpOptions,
+    filters: &IdFilters,
+    baseline: &Option<BaselineRecords>,
     typ: &ESerializedType,
-) -> Result<(), Error> {
+    ext: &str,
+) -> Result<(usize, usize), Error> {
+    let include = &options.include;
+    let exclude = &options.exclude;
+
     let plugin = parse_plugin(input);
     // parse plugin
     // write
     match plugin {
-        Ok(p) => {
-            for object in p.objects {
+        Ok(mut p) => {
+            if options.sorted {
+                sort_objects(&mut p.objects);
+            }
+
+            let mut objects = vec![];
+            for object in &p.objects {
                 // if (!include.is_empty() && include.contains(&object.tag_str().to_owned()))
                 //     && !exclude.contains(&object.tag_str().to_owned())
                 // first check for exclusion
@@ -311,35 +1298,178 @@ fn dump_plugin(
                 if !include.is_empty() && !include.contains(&object.tag_str().to_owned()) {
                     continue;
                 }
+                if !filters.matches(&object.editor_id()) {
+                    continue;
+                }
+                if baseline.as_ref().is_some_and(|b| is_in_baseline(b, object)) {
+                    log::debug!(
+                        "Skipping {} {}: identical to baseline",
+                        object.tag_str(),
+                        object.editor_id()
+                    );
+                    continue;
+                }
 
-                write_object(&object, out_dir_path, typ);
+                objects.push(object);
+            }
+            if let Some(limit) = options.limit {
+                truncate_records(&mut objects, limit);
+            }
+
+            if options.lint_scripts {
+                for object in &objects {
+                    if let TES3Object::Script(script) = object {
+                        for warning in script_lint::lint_script(&script.text) {
+                            log::warn!(
+                                "Script {} line {}: {}",
+                                script.id,
+                                warning.line,
+                                warning.message
+                            );
+                        }
+                    }
+                }
+            }
+
+            if options.single_file {
+                // one document per plugin, written in record order
+                let mut data = serialize_objects(typ, &objects, options.compact)?;
+                if options.toml_inline {
+                    data = inline_toml_tables(&data)?;
+                }
+                let name = format!("{}.{}", input.file_stem().unwrap().to_string_lossy(), ext);
+                write_bytes(out_dir_path, &name, &data)?;
+                Ok((objects.len(), 0))
+            } else {
+                // every record is written to its own file, so order doesn't matter
+                let collisions = options.flatten.then(|| get_id_collisions(&objects));
+                // records that share a file are written sequentially afterwards,
+                // so the last one wins like in the plugin
+                let shared = get_shared_paths(&objects, out_dir_path, ext, collisions.as_ref());
+                let shared_count = shared.iter().filter(|s| **s).count();
+                if shared_count > 0 {
+                    log::warn!(
+                        "{} records share a file with another record and overwrite each other",
+                        shared_count
+                    );
+                }
+                let mut written: Vec<(&TES3Object, Option<PathBuf>)> = objects
+                    .par_iter()
+                    .zip(&shared)
+                    .map(|(object, shared)| {
+                        if *shared {
+                            return (*object, None);
+                        }
+                        let path = write_object(
+                            object,
+                            out_dir_path,
+                            typ,
+                            ext,
+                            collisions.as_ref(),
+                            options,
+                        );
+                        (*object, path)
+                    })
+                    .collect();
+                for ((object, path), _) in written.iter_mut().zip(&shared).filter(|(_, s)| **s) {
+                    *path =
+                        write_object(object, out_dir_path, typ, ext, collisions.as_ref(), options);
+                }
+                if options.manifest {
+                    write_manifest(input, out_dir_path, &written)?;
+                }
+                let records = written.iter().filter(|(_, path)| path.is_some()).count();
+                Ok((records, written.len() - records))
             }
         }
-        Err(_) => {
-            return Err(Error::new(ErrorKind::Other, "Plugin parsing failed."));
-        }
+        Err(_) => Err(Error::new(ErrorKind::Other, "Plugin parsing failed.")),
     }
-    Ok(())
 }
 
-fn write_object(object: &TES3Object, out_dir_path: &Path, serialized_type: &ESerializedType) {
-    match object {
-        TES3Object::Header(_) => {
-            let name = format!("{}.{}", "Header", serialized_type);
-            write_generic(object, &name, &out_dir_path.join("Header"), serialized_type)
-                .unwrap_or_else(|e| println!("Writing failed: {}, {}", name, e));
+/// Get the lowercase ids that are used by records of more than one type
+fn get_id_collisions(objects: &[&TES3Object]) -> HashSet<String> {
+    let mut tags_by_id: HashMap<String, &str> = HashMap::new();
+    let mut collisions = HashSet::new();
+    for object in objects {
+        let id = object.editor_id().to_lowercase();
+        match tags_by_id.get(&id) {
+            Some(tag) if *tag != object.tag_str() => {
+                collisions.insert(id);
+            }
+            Some(_) => {}
+            None => {
+                tags_by_id.insert(id, object.tag_str());
+            }
         }
+    }
+    collisions
+}
 
-        TES3Object::Script(script) => {
-            let nam = object.editor_id().to_string();
-            let typ = object.type_name().to_string();
+/// Get whether each record is dumped to the same file as another record.
+/// Paths are compared case-insensitively, as on Windows
+fn get_shared_paths(
+    objects: &[&TES3Object],
+    out_dir_path: &Path,
+    ext: &str,
+    collisions: Option<&HashSet<String>>,
+) -> Vec<bool> {
+    let paths: Vec<String> = objects
+        .iter()
+        .map(|object| {
+            let (dir, name) = get_object_path(object, out_dir_path, ext, collisions);
+            dir.join(name).to_string_lossy().to_lowercase()
+        })
+        .collect();
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for path in &paths {
+        *counts.entry(path).or_default() += 1;
+    }
+    paths.iter().map(|path| counts[path.as_str()] > 1).collect()
+}
 
-            let name = format!("{}.{}", nam, serialized_type);
-            write_generic(object, &name, &out_dir_path.join(typ), serialized_type)
-                .unwrap_or_else(|e| println!("Writing failed: {}, {}", name, e));
+/// Get the folder and file name of a dumped record.
+/// Records are nested in a folder per type, unless the dump is flattened
+fn get_object_path(
+    object: &TES3Object,
+    out_dir_path: &Path,
+    ext: &str,
+    collisions: Option<&HashSet<String>>,
+) -> (PathBuf, String) {
+    let (nam, typ) = match object {
+        TES3Object::Header(_) => (String::from("Header"), String::from("Header")),
+        _ => (
+            object.editor_id().to_string(),
+            object.type_name().to_string(),
+        ),
+    };
+    match collisions {
+        None => (out_dir_path.join(typ), format!("{}.{}", nam, ext)),
+        Some(ids) if ids.contains(&nam.to_lowercase()) => (
+            out_dir_path.to_path_buf(),
+            format!("{}_{}.{}", object.tag_str(), nam, ext),
+        ),
+        Some(_) => (out_dir_path.to_path_buf(), format!("{}.{}", nam, ext)),
+    }
+}
+
+fn write_object(
+    object: &TES3Object,
+    out_dir_path: &Path,
+    serialized_type: &ESerializedType,
+    ext: &str,
+    collisions: Option<&HashSet<String>>,
+    options: &DumpOptions,
+) -> Option<PathBuf> {
+    let (dir, name) = get_object_path(object, out_dir_path, ext, collisions);
+    let result = match object {
+        TES3Object::Header(_) => write_generic(object, &name, &dir, serialized_type, options),
+
+        TES3Object::Script(script) => {
+            let result = write_generic(object, &name, &dir, serialized_type, options);
 
-            write_script(script, &out_dir_path.join("Script"))
-                .unwrap_or_else(|_| panic!("Writing failed: {}", script.id));
+            write_script(script, &dir, &options.encoding)
+                .unwrap_or_else(|e| log::error!("Writing script {} failed: {}", script.id, e));
+            result
         }
         TES3Object::GameSetting(_)
         | TES3Object::Skill(_)
@@ -382,30 +1512,118 @@ fn write_object(object: &TES3Object, out_dir_path: &Path, serialized_type: &ESer
         | TES3Object::Landscape(_)
         | TES3Object::PathGrid(_)
         | TES3Object::DialogueInfo(_) => {
-            let nam = object.editor_id().to_string();
-            let typ = object.type_name().to_string();
+            write_generic(object, &name, &dir, serialized_type, options)
+        }
+    };
 
-            let name = format!("{}.{}", nam, serialized_type);
-            write_generic(object, &name, &out_dir_path.join(typ), serialized_type)
-                .unwrap_or_else(|e| println!("Writing failed: {}, {}", name, e));
+    match result {
+        Ok(_) => Some(dir.join(name)),
+        Err(e) => {
+            log_write_error(object, serialized_type, &e);
+            None
         }
     }
 }
 
-/// Write a tes3object script to a file
-fn write_script(script: &Script, out_dir: &Path) -> io::Result<()> {
-    if !out_dir.exists() {
-        // create directory
-        match fs::create_dir_all(out_dir) {
-            Ok(_) => {}
-            Err(_) => {
-                return Err(Error::new(
-                    ErrorKind::Other,
-                    "Failed to create output directory.",
-                ));
-            }
+/// The name of the manifest written by dump and read by pack
+const MANIFEST_FILE_NAME: &str = "manifest.yaml";
+
+/// Write a manifest.yaml listing the written record files of a plugin,
+/// with the tag and editor id of each record, the plugin name and the unix time of the dump
+fn write_manifest(
+    input: &Path,
+    out_dir_path: &Path,
+    written: &[(&TES3Object, Option<PathBuf>)],
+) -> io::Result<()> {
+    let files: Vec<serde_json::Value> = written
+        .iter()
+        .filter_map(|(object, path)| {
+            let relative = path.as_ref()?.strip_prefix(out_dir_path).ok()?;
+            Some(serde_json::json!({
+                "file": relative.to_string_lossy().replace('\\', "/"),
+                "tag": object.tag_str(),
+                "id": object.editor_id(),
+            }))
+        })
+        .collect();
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let manifest = serde_json::json!({
+        "plugin": input.file_name().map(|n| n.to_string_lossy()).unwrap_or_default(),
+        "timestamp": timestamp,
+        "files": files,
+    });
+
+    let text = report_to_string(&ESerializedType::Yaml, &manifest)?;
+    write_bytes(out_dir_path, MANIFEST_FILE_NAME, text.as_bytes())
+}
+
+/// Read the record files listed in a manifest.yaml, in the order of the manifest
+fn read_manifest(input_path: &Path) -> io::Result<Vec<(PathBuf, ESerializedType)>> {
+    let text = fs::read_to_string(input_path.join(MANIFEST_FILE_NAME))?;
+    let manifest: serde_json::Value = match serde_yaml::from_str(&text) {
+        Ok(m) => m,
+        Err(e) => return Err(Error::new(ErrorKind::InvalidData, e.to_string())),
+    };
+
+    let mut files = vec![];
+    for entry in manifest["files"].as_array().into_iter().flatten() {
+        let Some(file) = entry["file"].as_str() else {
+            continue;
+        };
+        let path = input_path.join(file);
+        if !path.is_file() {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!("File in manifest does not exist: {}", path.display()),
+            ));
+        }
+        match format_from_path(&path) {
+            Some(format) => files.push((path, format)),
+            None => progress!("Skipping file with unknown format: {}", path.display()),
         }
     }
+    Ok(files)
+}
+
+/// Log a record that could not be written, the other records are still written
+fn log_write_error(object: &TES3Object, serialized_type: &ESerializedType, e: &Error) {
+    log::error!(
+        "Writing {} {} failed: {}",
+        object.type_name(),
+        object.editor_id(),
+        e
+    );
+    if let ESerializedType::Toml = serialized_type {
+        log::error!("Toml can't represent some records, use yaml or json for this record");
+    }
+}
+
+/// Create an output directory.
+/// Records are written in parallel, so another thread creating the directory first is fine
+fn create_dir(out_dir: &Path) -> io::Result<()> {
+    if out_dir.exists() {
+        return Ok(());
+    }
+    match fs::create_dir_all(out_dir) {
+        Ok(_) => Ok(()),
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => Ok(()),
+        Err(_) => Err(Error::new(
+            ErrorKind::Other,
+            "Failed to create output directory.",
+        )),
+    }
+}
+
+/// Write a tes3object script to a file
+pub(crate) fn write_script(
+    script: &Script,
+    out_dir: &Path,
+    encoding: &EEncoding,
+) -> io::Result<()> {
+    create_dir(out_dir)?;
 
     // get name
     let name = format!("{}.mwscript", script.id);
@@ -414,15 +1632,17 @@ fn write_script(script: &Script, out_dir: &Path) -> io::Result<()> {
     let output_path = out_dir.join(name);
     let file_or_error = File::create(output_path);
     match file_or_error {
-        Ok(mut file) => match file.write_all(script.text.as_bytes()) {
-            Ok(_) => {
-                // todo verbosity
-                //println!("SCPT written to: {}", output_path.display());
-            }
-            Err(_) => {
-                return Err(Error::new(ErrorKind::Other, "File write failed"));
+        Ok(mut file) => {
+            match file.write_all(&encode_text(&script.text, encoding)) {
+                Ok(_) => {
+                    // todo verbosity
+                    //println!("SCPT written to: {}", output_path.display());
+                }
+                Err(_) => {
+                    return Err(Error::new(ErrorKind::Other, "File write failed"));
+                }
             }
-        },
+        }
         Err(_) => {
             return Err(Error::new(ErrorKind::Other, "File create failed"));
         }
@@ -437,69 +1657,103 @@ fn write_generic(
     name: &String,
     out_dir: &Path,
     typ: &ESerializedType,
+    options: &DumpOptions,
 ) -> io::Result<()> {
-    let text = match serialize(typ, object) {
-        Ok(value) => value,
-        Err(value) => return value,
+    let mut data = serialize_bytes(typ, object, options.compact)?;
+    if options.toml_inline {
+        data = inline_toml_tables(&data)?;
+    }
+    write_bytes(out_dir, name, &data)
+}
+
+/// Serialize a TES3Object to text in a human-readable format, msgpack is an error
+pub fn serialize_object(object: &TES3Object, typ: &ESerializedType) -> io::Result<String> {
+    let result = match typ {
+        ESerializedType::Yaml => serde_yaml::to_string(object).map_err(|e| e.to_string()),
+        ESerializedType::Toml => toml::to_string_pretty(object).map_err(|e| e.to_string()),
+        ESerializedType::Json => serde_json::to_string_pretty(object).map_err(|e| e.to_string()),
+        ESerializedType::Jsonl => serde_json::to_string(object).map_err(|e| e.to_string()),
+        ESerializedType::Msgpack => {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "msgpack is a binary format",
+            ));
+        }
     };
+    result.map_err(|e| Error::new(ErrorKind::Other, e))
+}
 
-    write_to_file(out_dir, name, text)
+/// Serialize a TES3Object to bytes, binary formats are supported.
+/// Json is written without whitespace if compact is set
+fn serialize_bytes(
+    typ: &ESerializedType,
+    object: &TES3Object,
+    compact: bool,
+) -> io::Result<Vec<u8>> {
+    match typ {
+        ESerializedType::Json if compact => match serde_json::to_vec(object) {
+            Ok(b) => Ok(b),
+            Err(e) => Err(Error::new(ErrorKind::Other, e.to_string())),
+        },
+        ESerializedType::Msgpack => match rmp_serde::to_vec_named(object) {
+            Ok(b) => Ok(b),
+            Err(e) => Err(Error::new(ErrorKind::Other, e.to_string())),
+        },
+        _ => serialize_object(object, typ).map(String::into_bytes),
+    }
 }
 
-/// Serialize a TES3Object to text
-fn serialize(typ: &ESerializedType, object: &TES3Object) -> Result<String, Result<(), Error>> {
-    let text = match typ {
-        ESerializedType::Yaml => {
-            let result = serde_yaml::to_string(object);
-            match result {
-                Ok(t) => t,
-                Err(e) => {
-                    return Err(Err(Error::new(ErrorKind::Other, e.to_string())));
-                }
-            }
-        }
+/// Serialize a list of TES3Objects to one document.
+/// Yaml, json and msgpack are a sequence of records, toml is an array of tables named records.
+/// Json is written without whitespace if compact is set
+fn serialize_objects(
+    typ: &ESerializedType,
+    objects: &[&TES3Object],
+    compact: bool,
+) -> io::Result<Vec<u8>> {
+    let result = match typ {
+        ESerializedType::Yaml => serde_yaml::to_string(objects)
+            .map(String::into_bytes)
+            .map_err(|e| e.to_string()),
         ESerializedType::Toml => {
-            let result = toml::to_string_pretty(&object);
-            match result {
-                Ok(t) => t,
-                Err(e) => {
-                    return Err(Err(Error::new(ErrorKind::Other, e.to_string())));
-                }
-            }
-        }
-        ESerializedType::Json => {
-            let result = serde_json::to_string_pretty(&object);
-            match result {
-                Ok(t) => t,
-                Err(e) => {
-                    return Err(Err(Error::new(ErrorKind::Other, e.to_string())));
-                }
-            }
+            let mut map = HashMap::new();
+            map.insert("records", objects);
+            toml::to_string_pretty(&map)
+                .map(String::into_bytes)
+                .map_err(|e| e.to_string())
         }
+        ESerializedType::Json if compact => serde_json::to_vec(objects).map_err(|e| e.to_string()),
+        ESerializedType::Json => serde_json::to_string_pretty(objects)
+            .map(String::into_bytes)
+            .map_err(|e| e.to_string()),
+        ESerializedType::Msgpack => rmp_serde::to_vec_named(objects).map_err(|e| e.to_string()),
+        ESerializedType::Jsonl => return to_jsonl(objects.iter().copied()),
     };
-    Ok(text)
+    match result {
+        Ok(t) => Ok(t),
+        Err(e) => Err(Error::new(ErrorKind::Other, e)),
+    }
 }
 
-/// Convenience function to write TES3Object text to a file
-fn write_to_file(out_dir: &Path, name: &String, text: String) -> Result<(), Error> {
-    // create directory
-    if !out_dir.exists() {
-        match fs::create_dir_all(out_dir) {
-            Ok(_) => {}
-            Err(_) => {
-                return Err(Error::new(
-                    ErrorKind::Other,
-                    "Failed to create output directory.",
-                ));
-            }
-        }
+/// Serialize records as json lines, one compact record per line
+fn to_jsonl<'a>(objects: impl Iterator<Item = &'a TES3Object>) -> io::Result<Vec<u8>> {
+    let mut data = vec![];
+    for object in objects {
+        serde_json::to_writer(&mut data, object)?;
+        data.push(b'\n');
     }
+    Ok(data)
+}
+
+/// Convenience function to write serialized TES3Object bytes to a file
+fn write_bytes(out_dir: &Path, name: &String, data: &[u8]) -> Result<(), Error> {
+    create_dir(out_dir)?;
 
     // write to file
     let output_path = out_dir.join(name);
     let file_or_error = File::create(output_path);
     match file_or_error {
-        Ok(mut file) => match file.write_all(text.as_bytes()) {
+        Ok(mut file) => match file.write_all(data) {
             Ok(_) => {
                 // todo verbosity
                 //println!("MISC writen to: {}", output_path.display());
@@ -514,12 +1768,15 @@ fn write_to_file(out_dir: &Path, name: &String, text: String) -> Result<(), Erro
 ///////////////////////////////////////////////////////////////////////////
 // Deserialize
 
-/// Deserialize a human-readable file to esp
+/// Deserialize a human-readable file to esp.
+/// An input of - reads from stdin, the format must be given in that case
 pub fn deserialize_plugin(
     input: &Option<PathBuf>,
     output: &Option<PathBuf>,
     overwrite: bool,
-) -> io::Result<()> {
+    format: &Option<ESerializedType>,
+    dry_run: bool,
+) -> io::Result<TaskSummary> {
     let input_path: &PathBuf;
     // check no input
     if let Some(i) = input {
@@ -530,6 +1787,27 @@ pub fn deserialize_plugin(
             "No input path specified.",
         ));
     }
+
+    // read from stdin
+    if input_path.as_os_str() == "-" {
+        let Some(format) = format else {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "A format must be specified when reading from stdin.",
+            ));
+        };
+        let Some(output_path) = output else {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "An output path must be specified when reading from stdin.",
+            ));
+        };
+        let mut data = vec![];
+        io::stdin().read_to_end(&mut data)?;
+        let plugin = deserialize_bytes(&data, format)?;
+        return save_plugin(&plugin, output_path, dry_run);
+    }
+
     // check input path exists and check if file or directory
     if !input_path.exists() {
         return Err(Error::new(
@@ -543,7 +1821,9 @@ pub fn deserialize_plugin(
         ));
     } else if !(is_extension(input_path, "json")
         || is_extension(input_path, "toml")
-        || is_extension(input_path, "yaml"))
+        || is_extension(input_path, "yaml")
+        || is_extension(input_path, "mpk")
+        || is_extension(input_path, "jsonl"))
     {
         return Err(Error::new(
             ErrorKind::InvalidInput,
@@ -553,17 +1833,10 @@ pub fn deserialize_plugin(
 
     let mut output_path = PathBuf::from(input_path.clone().to_str().unwrap());
     if overwrite {
-        if let Some(path_str) = input_path.to_str() {
-            let path_str = path_str.to_owned().to_lowercase();
-            if let Some(stem) = path_str.strip_suffix(".esp.yaml") {
-                output_path = PathBuf::from(stem.to_string()).with_extension("esp");
-            } else if let Some(stem) = path_str.strip_suffix(".esp.toml") {
-                output_path = PathBuf::from(stem.to_string()).with_extension("esp");
-            } else if let Some(stem) = path_str.strip_suffix(".esp.json") {
-                output_path = PathBuf::from(stem.to_string()).with_extension("esp");
-            } else {
-                output_path = input_path.with_extension("esp");
-            }
+        // a.esp.yaml or a.omwscripts.json are written back to the plugin
+        let stem = input_path.with_extension("");
+        if is_plugin(&stem) {
+            output_path = stem;
         } else {
             output_path = input_path.with_extension("esp");
         }
@@ -576,157 +1849,500 @@ pub fn deserialize_plugin(
         output_path = i.to_path_buf();
     }
 
-    let mut plugin = Plugin::new();
-    if let Ok(text) = fs::read_to_string(input_path) {
-        if is_extension(input_path, "toml") {
-            let deserialized: Result<_, _> = toml::from_str(&text);
+    let plugin = read_serialized_plugin(input_path)?;
+    save_plugin(&plugin, &output_path, dry_run)
+}
+
+/// Save a plugin and return its summary, a dry run only prints what would be written
+pub(crate) fn save_plugin(plugin: &Plugin, path: &Path, dry_run: bool) -> io::Result<TaskSummary> {
+    if dry_run {
+        progress!(
+            "Dry run: would write {} records to {}",
+            plugin.objects.len(),
+            path.display()
+        );
+    } else {
+        plugin.save_path(path)?;
+    }
+    Ok(TaskSummary::new(plugin.objects.len(), path))
+}
+
+/// Parse a plugin from a serialized file, the format is inferred from the extension
+fn read_serialized_plugin(input_path: &Path) -> io::Result<Plugin> {
+    let Some(format) = format_from_path(input_path) else {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("Unknown serialized format: {}", input_path.display()),
+        ));
+    };
+
+    if let Ok(data) = fs::read(input_path) {
+        deserialize_bytes(&data, &format)
+    } else {
+        Err(Error::new(
+            ErrorKind::Other,
+            "Failed to read the input file",
+        ))
+    }
+}
+
+/// Get the serialized format of a file from its extension
+fn format_from_path(path: &Path) -> Option<ESerializedType> {
+    [
+        ESerializedType::Yaml,
+        ESerializedType::Toml,
+        ESerializedType::Json,
+        ESerializedType::Msgpack,
+        ESerializedType::Jsonl,
+    ]
+    .into_iter()
+    .find(|f| is_extension(path, &f.to_string()))
+}
+
+/// Read a single record from a serialized file
+fn deserialize_record(path: &Path, format: &ESerializedType) -> Option<TES3Object> {
+    let data = fs::read(path).ok()?;
+    match format {
+        ESerializedType::Msgpack => rmp_serde::from_slice(&data).ok(),
+        ESerializedType::Yaml => serde_yaml::from_slice(&data).ok(),
+        ESerializedType::Toml => toml::from_str(std::str::from_utf8(&data).ok()?).ok(),
+        ESerializedType::Json | ESerializedType::Jsonl => serde_json::from_slice(&data).ok(),
+    }
+}
+
+/// Parse a plugin from serialized bytes, text formats must be utf8
+fn deserialize_bytes(data: &[u8], format: &ESerializedType) -> io::Result<Plugin> {
+    if let ESerializedType::Msgpack = format {
+        return match rmp_serde::from_slice(data) {
+            Ok(t) => Ok(t),
+            Err(_) => Err(Error::new(
+                ErrorKind::Other,
+                "Failed to convert from msgpack",
+            )),
+        };
+    }
+
+    match std::str::from_utf8(data) {
+        Ok(text) => deserialize_text(text, format),
+        Err(_) => Err(Error::new(
+            ErrorKind::InvalidData,
+            "Input is not valid utf8",
+        )),
+    }
+}
+
+/// Parse a plugin from text in a human-readable format
+fn deserialize_text(text: &str, format: &ESerializedType) -> io::Result<Plugin> {
+    match format {
+        ESerializedType::Toml => {
+            let deserialized: Result<_, _> = toml::from_str(text);
             if let Ok(t) = deserialized {
-                plugin = t;
+                Ok(t)
             } else {
-                return Err(Error::new(ErrorKind::Other, "Failed to convert from toml"));
+                Err(Error::new(ErrorKind::Other, "Failed to convert from toml"))
             }
-        } else if is_extension(input_path, "json") {
-            let deserialized: Result<_, _> = serde_json::from_str(&text);
+        }
+        ESerializedType::Json => {
+            let deserialized: Result<_, _> = serde_json::from_str(text);
             if let Ok(t) = deserialized {
-                plugin = t;
+                Ok(t)
             } else {
-                return Err(Error::new(ErrorKind::Other, "Failed to convert from json"));
+                Err(Error::new(ErrorKind::Other, "Failed to convert from json"))
             }
-        } else if is_extension(input_path, "yaml") {
-            let deserialized: Result<_, _> = serde_yaml::from_str(&text);
+        }
+        ESerializedType::Yaml => {
+            let deserialized: Result<_, _> = serde_yaml::from_str(text);
             match deserialized {
-                Ok(t) => {
-                    plugin = t;
-                }
+                Ok(t) => Ok(t),
                 Err(e) => {
                     println!("{}", e);
-                    return Err(Error::new(ErrorKind::Other, "Failed to convert from yaml"));
+                    Err(Error::new(ErrorKind::Other, "Failed to convert from yaml"))
                 }
             }
         }
-
-        plugin.save_path(output_path)
-    } else {
-        Err(Error::new(
-            ErrorKind::Other,
-            "Failed to read the input file",
-        ))
+        ESerializedType::Jsonl => {
+            let mut plugin = Plugin::new();
+            for (i, line) in text.lines().enumerate() {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                match serde_json::from_str(line) {
+                    Ok(object) => plugin.objects.push(object),
+                    Err(e) => {
+                        return Err(Error::new(
+                            ErrorKind::Other,
+                            format!("Failed to convert from jsonl, line {}: {}", i + 1, e),
+                        ))
+                    }
+                }
+            }
+            Ok(plugin)
+        }
+        ESerializedType::Msgpack => Err(Error::new(
+            ErrorKind::InvalidInput,
+            "msgpack is a binary format",
+        )),
     }
 }
 
 ///////////////////////////////////////////////////////////////////////////
 // Pack
 
+/// Options for packing a folder into a plugin
+#[derive(Default)]
+pub struct PackOptions {
+    /// Only pack files of this format, all serialized files are packed if not set
+    pub format: Option<ESerializedType>,
+    /// Pack exactly the files listed in the manifest.yaml of the folder, in order
+    pub use_manifest: bool,
+    /// Mark the header as a master file, this is also done if the output is an .esm
+    pub master: bool,
+    /// Don't write the plugin
+    pub dry_run: bool,
+}
+
 /// Pack a folder of serialized files into a plugin
 pub fn pack(
     cinput_path: &Option<PathBuf>,
     output_path: &Option<PathBuf>,
-    cformat: &Option<ESerializedType>,
-) -> Result<(), Error> {
+    options: &PackOptions,
+) -> Result<TaskSummary, Error> {
     // check input path, default is cwd
     let mut input_path = env::current_dir()?;
     if let Some(p) = cinput_path {
         input_path.clone_from(p);
     }
 
-    let format = match cformat {
-        Some(f) => f,
-        None => &ESerializedType::Yaml,
-    };
+    let mut plugin = pack_plugin(&input_path, &options.format, options.use_manifest)?;
+
+    // save
+    let nam = input_path
+        .file_name()
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_string();
+    let mut i = input_path.join(nam);
+    i = append_ext(if options.master { "esm" } else { "esp" }, i);
+    let mut output = i.as_path();
+    if let Some(o) = output_path {
+        output = o;
+    }
+
+    if options.master || is_extension(output, "esm") {
+        if let Some(TES3Object::Header(header)) = plugin.objects.first_mut() {
+            header.file_type = FileType::Esm;
+        }
+    }
 
+    save_plugin(&plugin, output, options.dry_run)
+}
+
+/// Read the serialized files of a folder into a plugin in memory, the header is the first record
+pub(crate) fn pack_plugin(
+    input_path: &Path,
+    cformat: &Option<ESerializedType>,
+    use_manifest: bool,
+) -> io::Result<Plugin> {
     let mut files = vec![];
-    // get all files
-    for entry in fs::read_dir(&input_path).unwrap().flatten() {
-        let path = entry.path();
-        if path.is_dir() && path.exists() {
-            // match folder name with type_name
-            //let folder_name = path.file_name().unwrap().to_str().unwrap();
-            for file_entry in fs::read_dir(path).unwrap().flatten() {
-                let file = file_entry.path();
-                if file.is_file() && file.exists() {
-                    if let Some(e) = file.extension() {
-                        if e == format.to_string().as_str() {
-                            files.push(file);
-                        }
+    if use_manifest {
+        // pack exactly the files of the manifest, in order
+        files = read_manifest(input_path)?;
+    } else {
+        // get all files, records are nested in type folders or flat in the input folder
+        for entry in fs::read_dir(input_path)?.flatten() {
+            let path = entry.path();
+            if path.is_file() && !path.ends_with(MANIFEST_FILE_NAME) {
+                // other files such as packed plugins and scripts are skipped
+                match (format_from_path(&path), cformat) {
+                    (Some(f), Some(c)) if f == *c => files.push((path, f)),
+                    (Some(f), None) => files.push((path, f)),
+                    _ => log::debug!("Skipping file: {}", path.display()),
+                }
+            } else if path.is_dir() {
+                // match folder name with type_name
+                //let folder_name = path.file_name().unwrap().to_str().unwrap();
+                for file_entry in fs::read_dir(path)?.flatten() {
+                    let file = file_entry.path();
+                    if !file.is_file() {
+                        continue;
+                    }
+                    // the format is detected per file unless a format is given
+                    match (format_from_path(&file), cformat) {
+                        (Some(f), Some(c)) if f == *c => files.push((file, f)),
+                        (Some(f), None) => files.push((file, f)),
+                        _ => log::debug!("Skipping file: {}", file.display()),
                     }
                 }
             }
         }
+
+        // read_dir order depends on the filesystem
+        files.sort();
     }
 
     // Deserialize records from files
     let mut records = vec![];
-    for file_path in files {
-        let result = fs::read_to_string(&file_path);
-        if let Ok(text) = result {
-            match format {
-                ESerializedType::Yaml => {
-                    let deserialized: Result<TES3Object, _> = serde_yaml::from_str(&text);
-                    if let Ok(object) = deserialized {
-                        records.push(object);
-                    } else {
-                        println!("failed deserialization for {}", file_path.display());
-                    }
-                }
-                ESerializedType::Toml => {
-                    let deserialized: Result<TES3Object, _> = toml::from_str(&text);
-                    if let Ok(object) = deserialized {
-                        records.push(object);
-                    } else {
-                        println!("failed deserialization for {}", file_path.display());
-                    }
-                }
-                ESerializedType::Json => {
-                    let deserialized: Result<TES3Object, _> = serde_json::from_str(&text);
-                    if let Ok(object) = deserialized {
-                        records.push(object);
-                    } else {
-                        println!("failed deserialization for {}", file_path.display());
-                    }
+    for (file_path, format) in files {
+        match deserialize_record(&file_path, &format) {
+            Some(object) => records.push(object),
+            None => log::warn!(
+                "Skipping record, deserialization failed: {}",
+                file_path.display()
+            ),
+        }
+    }
+
+    // the header must be the first record, create a default one if missing
+    let header = match records.iter().position(|e| e.tag_str() == "TES3") {
+        Some(pos) => records.remove(pos),
+        None => {
+            progress!("No header record found, creating a default header");
+            match create_from_tag("TES3") {
+                Some(h) => h,
+                None => {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        "No header record found.",
+                    ))
                 }
             }
         }
+    };
+    if !use_manifest {
+        sort_records(&mut records);
     }
-
-    let pos = records.iter().position(|e| e.tag_str() == "TES3").unwrap();
-    let header = records.remove(pos);
     records.insert(0, header);
+    validate_records(&records)?;
 
     // make plugin
     let mut plugin = Plugin::new();
     plugin.objects = records;
+    Ok(plugin)
+}
 
-    // save
-    let nam = input_path
-        .file_name()
-        .unwrap()
-        .to_str()
-        .unwrap()
-        .to_string();
-    let mut i = input_path.join(nam);
-    i = append_ext("esp", i);
-    let mut output = i.as_path();
-    if let Some(o) = output_path {
-        output = o;
+/// Sort records by tag in canonical order, then by editor id, so packing is reproducible.
+/// INFO records stay behind their DIAL topic in their original order
+fn sort_records(records: &mut Vec<TES3Object>) {
+    let tags = get_all_tags();
+    let tag_index = |r: &TES3Object| {
+        tags.iter()
+            .position(|t| t == r.tag_str())
+            .unwrap_or(tags.len())
+    };
+    let mut groups = group_topics(records);
+
+    // INFO records of a folder without a manifest don't follow their topic
+    let orphans = groups
+        .iter()
+        .filter(|g| matches!(g[0], TES3Object::DialogueInfo(_)))
+        .count();
+    if orphans > 0 {
+        progress!(
+            "{} INFO records without a topic, pack with a manifest to keep the dialogue order",
+            orphans
+        );
+    }
+
+    // orphaned INFO records keep their order
+    groups.sort_by_cached_key(|g| {
+        let id = match g[0] {
+            TES3Object::DialogueInfo(_) => String::new(),
+            _ => g[0].editor_id().to_lowercase(),
+        };
+        (tag_index(&g[0]), id)
+    });
+    records.extend(groups.into_iter().flatten());
+}
+
+/// Check packed records before saving: there must be exactly one header and it must be first.
+/// Duplicate ids are reported and the record counts by tag are printed
+fn validate_records(records: &[TES3Object]) -> io::Result<()> {
+    let headers = records.iter().filter(|r| r.tag_str() == "TES3").count();
+    if headers != 1 || records.first().map(|r| r.tag_str()) != Some("TES3") {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("Expected exactly one header record, found {}", headers),
+        ));
+    }
+
+    let mut seen = HashSet::new();
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for record in records {
+        *counts.entry(record.tag_str()).or_default() += 1;
+        let id = record.editor_id().to_lowercase();
+        if !id.is_empty() && !seen.insert((record.tag_str(), id)) {
+            log::warn!(
+                "Duplicate record {} {}",
+                record.tag_str(),
+                record.editor_id()
+            );
+        }
+    }
+
+    for (tag, count) in &counts {
+        log::info!("Packed {} {} records", count, tag);
+    }
+    Ok(())
+}
+
+/// Remove records with duplicate ids, only the last occurrence of a (tag, id) pair is kept.
+/// Records with empty ids are never removed. Returns the number of removed records
+pub fn dedupe_objects(objects: &mut Vec<TES3Object>) -> usize {
+    // the index of the last occurrence of each record
+    let mut last: HashMap<(&str, String), usize> = HashMap::new();
+    for (i, object) in objects.iter().enumerate() {
+        if !object.editor_id().is_empty() {
+            last.insert((object.tag_str(), object.editor_id().to_lowercase()), i);
+        }
+    }
+
+    let keep: Vec<bool> = objects
+        .iter()
+        .enumerate()
+        .map(|(i, object)| {
+            let id = object.editor_id().to_lowercase();
+            if id.is_empty() || last[&(object.tag_str(), id)] == i {
+                return true;
+            }
+            log::info!(
+                "Removed duplicate {} {}",
+                object.tag_str(),
+                object.editor_id()
+            );
+            false
+        })
+        .collect();
+
+    let before = objects.len();
+    let mut keep = keep.into_iter();
+    objects.retain(|_| keep.next().unwrap_or(true));
+    before - objects.len()
+}
+
+/// Remove duplicate records from a plugin, the last occurrence of each id wins.
+/// The plugin is saved in place by default
+pub fn dedupe(
+    input: &Option<PathBuf>,
+    output: &Option<PathBuf>,
+    dry_run: bool,
+) -> io::Result<TaskSummary> {
+    let input_path: &PathBuf;
+    // check no input
+    if let Some(i) = input {
+        input_path = i;
+    } else {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "No input path specified.",
+        ));
+    }
+    if !input_path.is_file() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Input path does not exist",
+        ));
     }
 
-    plugin.save_path(output)
+    let mut plugin = match parse_plugin(input_path) {
+        Ok(p) => p,
+        Err(_) => return Err(Error::new(ErrorKind::Other, "Plugin parsing failed.")),
+    };
+    let removed = dedupe_objects(&mut plugin.objects);
+    progress!("Removed {} duplicate records", removed);
+
+    let output_path = match output {
+        Some(o) => o,
+        None => input_path,
+    };
+    save_plugin(&plugin, output_path, dry_run)
 }
 
 ///////////////////////////////////////////////////////////////////////////
 // AtlasCoverage
 
-fn read_file_contents(file_path: &String) -> io::Result<(String, Vec<String>)> {
+/// Read the textures of a nif, the path is returned with the result so failures can be reported
+fn read_file_contents(file_path: &Path) -> (PathBuf, io::Result<Vec<String>>) {
     // load nif
-    let path = PathBuf::from(&file_path);
-    if let Ok(list) = get_textures_from_nif(&path.clone()) {
-        return Ok((file_path.clone(), list));
+    (file_path.to_path_buf(), get_textures_from_nif(file_path))
+}
+
+/// Convert the path keys of a map to strings for serialization, non-utf8 characters are replaced
+fn to_string_keys<T>(map: &HashMap<PathBuf, T>) -> HashMap<String, &T> {
+    map.iter()
+        .map(|(path, value)| (path.to_string_lossy().into_owned(), value))
+        .collect()
+}
+
+/// Options for the atlas coverage
+#[derive(Default)]
+pub struct AtlasOptions {
+    /// Texture path prefix of atlas textures, defaults to textures/atl
+    pub prefix: Option<String>,
+    /// Additional mesh extensions to scan besides nif, e.g. kf
+    pub extensions: Vec<String>,
+    /// The format of the output files, default is yaml
+    pub format: Option<ESerializedType>,
+    /// Data folder to check referenced textures against, textures are resolved under textures/
+    pub assets_root: Option<PathBuf>,
+    /// Drop internal textures from the lists, meshes with only internal textures are not counted
+    pub exclude_internal: bool,
+}
+
+/// Normalize a texture path so that matching is separator-agnostic
+fn normalize_texture_path(path: &str) -> String {
+    path.replace('\\', "/").to_lowercase()
+}
+
+/// Get all dds and tga textures under the textures folder of an assets root.
+/// Paths are normalized, relative to the textures folder and without extension
+fn get_texture_files(assets_root: &Path) -> HashSet<String> {
+    let textures_path = assets_root.join("textures");
+    let mut textures = HashSet::new();
+    for entry in WalkDir::new(&textures_path)
+        .into_iter()
+        .filter_map(|e| e.ok())
+    {
+        let path = entry.path();
+        if !entry.file_type().is_file() || !(is_extension(path, "dds") || is_extension(path, "tga"))
+        {
+            continue;
+        }
+        if let Ok(relative) = path.with_extension("").strip_prefix(&textures_path) {
+            textures.insert(normalize_texture_path(&relative.to_string_lossy()));
+        }
     }
+    textures
+}
+
+/// Check if a texture referenced by a nif exists as dds or tga
+fn texture_exists(textures: &HashSet<String>, texture: &str) -> bool {
+    let texture = normalize_texture_path(texture);
+    let texture = texture.trim_start_matches("textures/");
+    let stem = Path::new(texture).with_extension("");
+    textures.contains(&*stem.to_string_lossy())
+}
 
-    Err(Error::new(ErrorKind::Other, "Failed to read file contents"))
+/// Serialize a report to text, toml needs a table at the root
+fn report_to_string(format: &ESerializedType, report: &serde_json::Value) -> io::Result<String> {
+    let result = match format {
+        ESerializedType::Yaml => serde_yaml::to_string(report).map_err(|e| e.to_string()),
+        ESerializedType::Toml => toml::to_string_pretty(report).map_err(|e| e.to_string()),
+        ESerializedType::Json => serde_json::to_string_pretty(report).map_err(|e| e.to_string()),
+        ESerializedType::Jsonl => serde_json::to_string(report).map_err(|e| e.to_string()),
+        ESerializedType::Msgpack => Err(String::from("Reports can't be written as msgpack")),
+    };
+    match result {
+        Ok(t) => Ok(t),
+        Err(e) => Err(Error::new(ErrorKind::Other, e)),
+    }
 }
 
-pub fn atlas_coverage(input: &Option<PathBuf>, output: &Option<PathBuf>) -> io::Result<()> {
+pub fn atlas_coverage(
+    input: &Option<PathBuf>,
+    output: &Option<PathBuf>,
+    options: &AtlasOptions,
+) -> io::Result<()> {
     // check output path, default is cwd
     let mut out_dir_path = env::current_dir()?;
     if let Some(p) = output {
@@ -739,21 +2355,52 @@ pub fn atlas_coverage(input: &Option<PathBuf>, output: &Option<PathBuf>) -> io::
         p.clone_into(&mut input_path);
     }
 
+    // check atlas prefix, default is textures/atl
+    let prefix = match &options.prefix {
+        Some(p) => normalize_texture_path(p),
+        None => String::from("textures/atl"),
+    };
+
+    // check output format, default is yaml
+    let format = options.format.clone().unwrap_or_default();
+
     // map of textures by nif file
-    let mut map_none: HashMap<String, Vec<String>> = HashMap::new();
-    let mut map_some: HashMap<String, Vec<String>> = HashMap::new();
+    let mut map_none: HashMap<PathBuf, Vec<String>> = HashMap::new();
+    let mut map_some: HashMap<PathBuf, Vec<String>> = HashMap::new();
+    let mut map_missing: HashMap<PathBuf, Vec<String>> = HashMap::new();
+
+    // textures on disk, only if an assets root is given
+    let textures = match &options.assets_root {
+        Some(root) => {
+            if !root.is_dir() {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "Assets root does not exist",
+                ));
+            }
+            progress!("Collecting textures in: {}", root.display());
+            Some(get_texture_files(root))
+        }
+        None => None,
+    };
 
     // log parse nif files
-    println!("Parsing nif files in: {}", input_path.display());
+    progress!("Parsing nif files in: {}", input_path.display());
+
+    // mesh extensions to scan, nif is always included
+    let mut extensions = vec![String::from("nif")];
+    for e in &options.extensions {
+        let e = e.trim_start_matches('.').to_lowercase();
+        if !extensions.contains(&e) {
+            extensions.push(e);
+        }
+    }
 
-    // get all .nif or .NIF files in the input folder recursively in a list
+    // get all mesh files in the input folder recursively in a list, case-insensitive
     let mut nif_files = Vec::new();
     for entry in WalkDir::new(input_path).into_iter().filter_map(|e| e.ok()) {
-        if entry.file_type().is_file() {
-            let path = entry.path().to_owned();
-            if is_extension(&path, "nif") {
-                nif_files.push(entry.path().to_string_lossy().into_owned());
-            }
+        if entry.file_type().is_file() && extensions.iter().any(|e| is_extension(entry.path(), e)) {
+            nif_files.push(entry.into_path());
         }
     }
 
@@ -761,23 +2408,51 @@ pub fn atlas_coverage(input: &Option<PathBuf>, output: &Option<PathBuf>) -> io::
     // Read file contents in parallel
     let contents: Vec<_> = nif_files
         .par_iter() // Parallel iterator
-        .map(read_file_contents) // Read file contents
+        .map(|path| read_file_contents(path)) // Read file contents
         .collect::<Vec<_>>();
 
+    // nif files that failed to parse with the error message
+    let mut errors: Vec<(PathBuf, String)> = vec![];
+    // meshes with embedded textures
+    let mut internal_meshes = 0;
+
     // iterate over results
-    for result in contents {
+    for (file, result) in contents {
         match result {
-            Ok((file, list)) => {
-                // if any entries in the list have "textures\atl" in them, add to map_some
+            Ok(mut list) => {
+                if list.iter().any(|t| t == "internal") {
+                    internal_meshes += 1;
+                    // internal textures can never be atlased
+                    if options.exclude_internal {
+                        list.retain(|t| t != "internal");
+                        if list.is_empty() {
+                            continue;
+                        }
+                    }
+                }
+
+                // if any entries in the list have the atlas prefix in them, add to map_some
                 // else add to map_none
                 let mut found = false;
                 for texture in &list {
-                    if texture.contains("textures\\atl") {
+                    if normalize_texture_path(texture).contains(&prefix) {
                         found = true;
                         break;
                     }
                 }
 
+                // textures that are not on disk
+                if let Some(textures) = &textures {
+                    let missing: Vec<String> = list
+                        .iter()
+                        .filter(|t| t.as_str() != "internal" && !texture_exists(textures, t))
+                        .cloned()
+                        .collect();
+                    if !missing.is_empty() {
+                        map_missing.insert(file.clone(), missing);
+                    }
+                }
+
                 if found {
                     map_some.insert(file, list);
                 } else {
@@ -785,44 +2460,76 @@ pub fn atlas_coverage(input: &Option<PathBuf>, output: &Option<PathBuf>) -> io::
                 }
             }
             Err(e) => {
-                println!("Error: {}", e);
+                log::error!("Failed to read {}: {}", file.display(), e);
+                errors.push((file, e.to_string()));
             }
         }
     }
+    errors.sort();
 
     // print maps count
-    println!(
-        "Nif files with textures in textures\\atl: {}",
-        map_some.len()
-    );
-    println!(
-        "Nif files without textures in textures\\atl: {}",
+    progress!("Nif files with textures in {}: {}", prefix, map_some.len());
+    progress!(
+        "Nif files without textures in {}: {}",
+        prefix,
         map_none.len()
     );
 
     // serialize map to output folder
     {
-        println!("Serializing to: {}", out_dir_path.display());
+        progress!("Serializing to: {}", out_dir_path.display());
         // create output folder
         if !out_dir_path.exists() {
             fs::create_dir_all(&out_dir_path)?;
         }
         let mut output_path = out_dir_path.join("atlas_coverage");
-        output_path = append_ext("yaml", output_path);
-        // serialize to yaml
-        // make a new object with the two maps
-        let mut map = HashMap::new();
-        map.insert("with_atl", &map_some);
-        map.insert("without_atl", &map_none);
-
-        let text = serde_yaml::to_string(&map).unwrap();
+        output_path = append_ext(&format.to_string(), output_path);
+        // make a new object with the maps and the parse errors
+        let mut map = serde_json::Map::new();
+        // paths are only converted to strings for the output
+        map.insert(
+            "with_atl".into(),
+            serde_json::to_value(to_string_keys(&map_some))?,
+        );
+        map.insert(
+            "without_atl".into(),
+            serde_json::to_value(to_string_keys(&map_none))?,
+        );
+        if textures.is_some() {
+            map.insert(
+                "missing".into(),
+                serde_json::to_value(to_string_keys(&map_missing))?,
+            );
+        }
+        let errors: Vec<(String, &String)> = errors
+            .iter()
+            .map(|(path, e)| (path.to_string_lossy().into_owned(), e))
+            .collect();
+        map.insert("errors".into(), serde_json::to_value(&errors)?);
+
+        let value = serde_json::Value::Object(map);
+        let text = report_to_string(&format, &value)?;
+        let mut file = File::create(output_path)?;
+        file.write_all(text.as_bytes())?;
+    }
+
+    // serialize the number of meshes using each texture, most used first
+    {
+        let (internal, usage) = get_texture_usage(map_some.values().chain(map_none.values()));
+        let usage: Vec<serde_json::Value> = usage
+            .into_iter()
+            .map(|(texture, count)| serde_json::json!({ "texture": texture, "count": count }))
+            .collect();
+        let value = serde_json::json!({ "internal": internal, "textures": usage });
+        let text = report_to_string(&format, &value)?;
+        let output_path = append_ext(&format.to_string(), out_dir_path.join("texture_usage"));
         let mut file = File::create(output_path)?;
         file.write_all(text.as_bytes())?;
     }
 
     // serialize some statistics
     {
-        println!("Serializing stats to: {}", out_dir_path.display());
+        progress!("Serializing stats to: {}", out_dir_path.display());
         let mut stats = HashMap::new();
         stats.insert("with_atl", map_some.len().to_string());
         stats.insert("without_atl", map_none.len().to_string());
@@ -830,16 +2537,81 @@ pub fn atlas_coverage(input: &Option<PathBuf>, output: &Option<PathBuf>) -> io::
         let total = map_some.len() + map_none.len();
         let coverage = (map_some.len() as f32 / total as f32) * 100.0;
         stats.insert("coverage", coverage.to_string());
+        // coverage weighted by usage, over all external texture references of all meshes
+        let references: Vec<String> = map_some
+            .values()
+            .chain(map_none.values())
+            .flatten()
+            .map(|t| normalize_texture_path(t))
+            .filter(|t| t != "internal")
+            .collect();
+        let atlas_references = references.iter().filter(|t| t.contains(&prefix)).count();
+        // meshes without external textures have no coverage
+        let reference_coverage = if references.is_empty() {
+            0.0
+        } else {
+            (atlas_references as f32 / references.len() as f32) * 100.0
+        };
+        progress!(
+            "Texture references in {}: {} of {}",
+            prefix,
+            atlas_references,
+            references.len()
+        );
+        stats.insert("references", references.len().to_string());
+        stats.insert("atlas_references", atlas_references.to_string());
+        stats.insert("reference_coverage", reference_coverage.to_string());
+        stats.insert("internal_meshes", internal_meshes.to_string());
+        stats.insert("extensions", extensions.join(", "));
+        stats.insert("errors", errors.len().to_string());
+        if textures.is_some() {
+            let missing: HashSet<&String> = map_missing.values().flatten().collect();
+            progress!("Missing textures: {}", missing.len());
+            stats.insert("missing", missing.len().to_string());
+        }
 
-        let text = serde_yaml::to_string(&stats).unwrap();
-        let mut file = File::create(out_dir_path.join("atlas_coverage_stats.yaml"))?;
+        let value = serde_json::to_value(&stats)?;
+        let text = report_to_string(&format, &value)?;
+        let output_path = append_ext(
+            &format.to_string(),
+            out_dir_path.join("atlas_coverage_stats"),
+        );
+        let mut file = File::create(output_path)?;
         file.write_all(text.as_bytes())?;
     }
 
+    println!(
+        "Atlas coverage of {} nif files written to {}",
+        map_some.len() + map_none.len(),
+        out_dir_path.display()
+    );
     Ok(())
 }
 
-fn get_textures_from_nif(path: &PathBuf) -> Result<Vec<String>, Error> {
+/// Count the meshes referencing each texture, sorted by count descending.
+/// Internal textures are counted separately
+fn get_texture_usage<'a>(
+    lists: impl Iterator<Item = &'a Vec<String>>,
+) -> (usize, Vec<(String, usize)>) {
+    let mut internal = 0;
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for list in lists {
+        let textures: HashSet<String> = list.iter().map(|t| normalize_texture_path(t)).collect();
+        for texture in textures {
+            if texture == "internal" {
+                internal += 1;
+            } else {
+                *counts.entry(texture).or_default() += 1;
+            }
+        }
+    }
+
+    let mut usage: Vec<(String, usize)> = counts.into_iter().collect();
+    usage.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    (internal, usage)
+}
+
+fn get_textures_from_nif(path: &Path) -> Result<Vec<String>, Error> {
     let mut list = Vec::new();
 
     let mut stream = nif::NiStream::new();
@@ -906,9 +2678,23 @@ pub enum ERecordType {
     WEAP,
 }
 
-impl From<&str> for ERecordType {
-    fn from(value: &str) -> Self {
-        match value {
+/// A record tag that has no matching record type
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnknownTagError(pub String);
+
+impl fmt::Display for UnknownTagError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Unknown record tag: {}", self.0)
+    }
+}
+
+impl std::error::Error for UnknownTagError {}
+
+impl TryFrom<&str> for ERecordType {
+    type Error = UnknownTagError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let e = match value {
             "TES3" => ERecordType::TES3,
             "GMST" => ERecordType::GMST,
             "GLOB" => ERecordType::GLOB,
@@ -952,11 +2738,28 @@ impl From<&str> for ERecordType {
             "PGRD" => ERecordType::PGRD,
             "DIAL" => ERecordType::DIAL,
             "INFO" => ERecordType::INFO,
-            _ => {
-                panic!("ArgumentException")
-            }
-        }
+            _ => return Err(UnknownTagError(value.to_owned())),
+        };
+        Ok(e)
+    }
+}
+
+/// Get the distinct record tags of a plugin
+pub fn plugin_tags(plugin: &Plugin) -> BTreeSet<String> {
+    plugin
+        .objects
+        .iter()
+        .map(|o| o.tag_str().to_owned())
+        .collect()
+}
+
+/// Count the records of a plugin by tag
+pub fn plugin_tag_counts(plugin: &Plugin) -> BTreeMap<String, usize> {
+    let mut counts = BTreeMap::new();
+    for object in &plugin.objects {
+        *counts.entry(object.tag_str().to_owned()).or_default() += 1;
     }
+    counts
 }
 
 /// super dumb but I can't be bothered to mess around with enums now
@@ -971,9 +2774,9 @@ pub fn get_all_tags() -> Vec<String> {
 }
 
 // Refactor this after e3
-/// Create a new record of the given tag
+/// Create a new record of the given tag, returns None for unknown tags
 pub fn create_from_tag(tag: &str) -> Option<TES3Object> {
-    create(ERecordType::from(tag))
+    create(ERecordType::try_from(tag).ok()?)
 }
 
 /// Create a new record of the given type
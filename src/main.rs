@@ -1,7 +1,12 @@
-use clap::{Parser, Subcommand};
-use std::path::PathBuf;
+use clap::{ArgAction, Parser, Subcommand};
+use log::LevelFilter;
+use std::path::{Path, PathBuf};
 use tes3util::{
-    atlas_coverage, deserialize_plugin, dump, pack, serialize_plugin, sql_task, ESerializedType,
+    assets_task, atlas_coverage, cells_task, clean_task, convert_task, csv_task, dedupe,
+    deserialize_plugin, dialogue_task, diff_task, dump, get_task, gmst_task, init_logger,
+    masters_task, merge_task, pack, remaster_task, rename_task, scripts_task, serialize_plugin,
+    split_task, sql_task, stats_task, validate_task, verify_task, AtlasOptions, DumpOptions,
+    EEncoding, ESerializedType, PackOptions, SerializeOptions, TaskSummary,
 };
 
 #[derive(Parser)]
@@ -10,6 +15,31 @@ use tes3util::{
 struct Cli {
     #[command(subcommand)]
     commands: Commands,
+
+    /// Log file path, defaults to log.txt
+    #[arg(long, global = true)]
+    log_file: Option<PathBuf>,
+
+    /// Increase the log level, may be repeated (-v info, -vv debug, -vvv trace)
+    #[arg(short, long, action = ArgAction::Count, global = true)]
+    verbose: u8,
+
+    /// Don't log to the console and only print the summary of a command, the log file is still written
+    #[arg(long, global = true)]
+    quiet: bool,
+
+    /// Print the summary of dump, pack and serialize in this format instead of a sentence
+    #[arg(long, value_enum, global = true)]
+    summary_format: Option<ESerializedType>,
+
+    /// Number of threads for parallel tasks, 0 uses one thread per core
+    #[arg(long, global = true, default_value_t = 0)]
+    threads: usize,
+
+    /// Parse and validate but don't write output plugins, the output paths are printed instead.
+    /// Commands writing other files don't support a dry run and fail
+    #[arg(long, global = true)]
+    dry_run: bool,
 }
 
 #[derive(Subcommand)]
@@ -38,6 +68,75 @@ enum Commands {
         /// Exclude specific records
         #[arg(short, long)]
         exclude: Vec<String>,
+
+        /// Override the extension of serialized files, defaults to the format
+        #[arg(long)]
+        out_ext: Option<String>,
+
+        /// Sort records by tag and editor id
+        #[arg(long)]
+        sorted: bool,
+
+        /// Load order config for folder input (openmw.cfg or Morrowind.ini)
+        #[arg(long)]
+        cfg: Option<PathBuf>,
+
+        /// Dump plugins in subfolders of the input folder too
+        #[arg(short, long)]
+        recursive: bool,
+
+        /// Only include records whose editor id matches this regex
+        #[arg(long)]
+        id_include: Option<String>,
+
+        /// Exclude records whose editor id matches this regex, wins over --id-include
+        #[arg(long)]
+        id_exclude: Option<String>,
+
+        /// Write all records of a plugin into one file instead of one file per record
+        #[arg(long)]
+        single_file: bool,
+
+        /// Include OpenMW script lists (.omwscripts) in folder input
+        #[arg(long)]
+        omw: bool,
+
+        /// Check the structure of scripts and log warnings with the script id and line
+        #[arg(long)]
+        lint_scripts: bool,
+
+        /// Only dump records that are new or changed relative to this plugin
+        #[arg(long)]
+        baseline: Option<PathBuf>,
+
+        /// Write all records into the output folder without type subfolders.
+        /// Ids used by more than one record type are prefixed with the tag, e.g. WEAP_foo.yaml
+        #[arg(long, conflicts_with = "single_file")]
+        flatten: bool,
+
+        /// Write json without whitespace
+        #[arg(long)]
+        compact: bool,
+
+        /// Write a manifest.yaml listing the dumped files, for pack --manifest
+        #[arg(long, conflicts_with = "single_file")]
+        manifest: bool,
+
+        /// Only dump the first n records after the header of each plugin
+        #[arg(long)]
+        limit: Option<usize>,
+
+        /// Write arrays of small tables as inline tables, only available for toml
+        #[arg(long)]
+        toml_inline: bool,
+
+        /// The encoding of the .mwscript files, serialized records are always utf8
+        #[arg(long, value_enum)]
+        encoding: Option<EEncoding>,
+
+        /// Only dump plugins of a folder whose file name matches this pattern, e.g. "Tamriel_Data*.esm"
+        #[arg(long)]
+        glob: Option<String>,
     },
 
     /// Packs records from a folder into a plugin
@@ -48,9 +147,17 @@ enum Commands {
         /// output path, may be a plugin
         output: Option<PathBuf>,
 
-        /// The extension to serialize from, default is yaml
+        /// Only pack files of this format, by default the format is detected from each extension
         #[arg(short, long, value_enum)]
         format: Option<ESerializedType>,
+
+        /// Pack the files listed in the manifest.yaml of the folder in the manifest order
+        #[arg(long)]
+        manifest: bool,
+
+        /// Write a master file: the default output is an .esm and the header is marked as master
+        #[arg(long)]
+        master: bool,
     },
 
     /// Serialize a plugin to a human-readable format
@@ -58,18 +165,51 @@ enum Commands {
         /// input path, may be a plugin or a folder
         input: Option<PathBuf>,
 
-        /// output directory, defaults to cwd
+        /// output directory, defaults to cwd. Use - to write to stdout
         #[arg(short, long)]
         output: Option<PathBuf>,
 
         /// The extension to serialize to, default is yaml
         #[arg(short, long, value_enum)]
         format: Option<ESerializedType>,
+
+        /// Write the serialized plugin to stdout, same as --output -
+        #[arg(long)]
+        stdout: bool,
+
+        /// Override the extension of the serialized file, defaults to the format
+        #[arg(long)]
+        out_ext: Option<String>,
+
+        /// Emit a json object keyed by "TAG:id" instead of a list of records,
+        /// duplicate ids get a "#n" suffix. Only available for json
+        #[arg(long)]
+        keyed: bool,
+
+        /// Sort records by tag and editor id
+        #[arg(long)]
+        sorted: bool,
+
+        /// Write json without whitespace
+        #[arg(long)]
+        compact: bool,
+
+        /// Only serialize the first n records after the header
+        #[arg(long)]
+        limit: Option<usize>,
+
+        /// Serialize records one by one and skip the ones that fail
+        #[arg(long)]
+        lenient: bool,
+
+        /// Write arrays of small tables as inline tables, only available for toml
+        #[arg(long)]
+        toml_inline: bool,
     },
 
     /// Deserialize a text file from a human-readable format to a plugin
     Deserialize {
-        /// input path, may be a file or a folder
+        /// input path, may be a file or a folder. Use - to read from stdin
         input: Option<PathBuf>,
 
         /// output file name, defaults to cwd
@@ -79,6 +219,14 @@ enum Commands {
         /// Overwrite existing plugin
         #[arg(short = 'y', long)]
         overwrite: bool,
+
+        /// Report record and field differences to this original plugin instead of writing a plugin
+        #[arg(long)]
+        diff_against_binary: Option<PathBuf>,
+
+        /// The format of stdin input, or of the difference report where the default is yaml
+        #[arg(short, long, value_enum)]
+        format: Option<ESerializedType>,
     },
 
     /// Atlas coverage of all meshes
@@ -89,6 +237,26 @@ enum Commands {
         /// output directory, defaults to cwd
         #[arg(short, long)]
         output: Option<PathBuf>,
+
+        /// Texture path prefix of atlas textures, defaults to textures/atl
+        #[arg(short, long)]
+        prefix: Option<String>,
+
+        /// Additional mesh extensions to scan besides nif, e.g. --ext kf
+        #[arg(long = "ext")]
+        extensions: Vec<String>,
+
+        /// The format of the output files, default is yaml
+        #[arg(short, long, value_enum)]
+        format: Option<ESerializedType>,
+
+        /// Data folder to check that all referenced textures exist, e.g. Data Files
+        #[arg(long)]
+        assets_root: Option<PathBuf>,
+
+        /// Drop internal textures, meshes with only internal textures are left out of the coverage
+        #[arg(long)]
+        exclude_internal: bool,
     },
 
     /// Sql
@@ -99,11 +267,463 @@ enum Commands {
         /// output directory, defaults to cwd
         #[arg(short, long)]
         output: Option<PathBuf>,
+
+        /// Run a query against the database after it is built and print the result
+        #[arg(short, long)]
+        query: Option<String>,
+
+        /// Print the query result as csv instead of a table
+        #[arg(long)]
+        csv: bool,
+
+        /// Load order config for folder input (openmw.cfg or Morrowind.ini)
+        #[arg(long)]
+        cfg: Option<PathBuf>,
+
+        /// Include OpenMW script lists (.omwscripts) in folder input
+        #[arg(long)]
+        omw: bool,
+
+        /// Show progress bars on stderr while building the database
+        #[arg(long)]
+        progress: bool,
+
+        /// Only create and populate tables of these tags, e.g. --include WEAP
+        #[arg(short, long)]
+        include: Vec<String>,
+
+        /// Don't create and populate tables of these tags
+        #[arg(short, long)]
+        exclude: Vec<String>,
+
+        /// Replace an existing database
+        #[arg(short = 'y', long)]
+        overwrite: bool,
+
+        /// Update the records of the input plugins in an existing database
+        #[arg(long, conflicts_with = "overwrite")]
+        append: bool,
+
+        /// Don't create indexes on the mod and foreign key columns
+        #[arg(long)]
+        no_index: bool,
+
+        /// Write the sql schema to this file and exit without building the database
+        #[arg(long)]
+        schema_only: Option<PathBuf>,
+
+        /// Report records edited by more than one plugin in a _duplicates table
+        #[arg(long)]
+        dedup_report: bool,
+
+        /// Fail if records reference missing records or plugins
+        #[arg(long)]
+        strict: bool,
+
+        /// Don't use write-ahead logging, for databases that are only read after the build
+        #[arg(long)]
+        readonly: bool,
+
+        /// Only load plugins of a folder whose file name matches this pattern, e.g. "Tamriel_Data*.esm"
+        #[arg(long)]
+        glob: Option<String>,
+    },
+
+    /// Print record counts and statistics about a plugin
+    Stats {
+        /// input path, may be a plugin or a folder
+        input: Option<PathBuf>,
+
+        /// List the N largest records by serialized size
+        #[arg(short, long)]
+        top: Option<usize>,
+
+        /// Print the statistics in this format instead of a table
+        #[arg(short, long, value_enum)]
+        format: Option<ESerializedType>,
+
+        /// Include OpenMW script lists (.omwscripts) in folder input
+        #[arg(long)]
+        omw: bool,
+    },
+
+    /// Print script counts and sizes of a plugin
+    ScriptStats {
+        /// input plugin
+        input: Option<PathBuf>,
+
+        /// Flag scripts with more bytes of text, the default is the limit of the vanilla script editor
+        #[arg(long, default_value_t = 32767)]
+        max_bytes: usize,
+
+        /// Print the statistics in this format instead of a summary
+        #[arg(short, long, value_enum)]
+        format: Option<ESerializedType>,
+    },
+
+    /// Count the placements of every base object in the cells of a plugin
+    RefCount {
+        /// input plugin
+        input: Option<PathBuf>,
+
+        /// Print the counts in this format instead of a table
+        #[arg(short, long, value_enum)]
+        format: Option<ESerializedType>,
+    },
+
+    /// Merge multiple plugins into one, later plugins override earlier ones
+    Merge {
+        /// input plugins, in load order
+        inputs: Vec<PathBuf>,
+
+        /// output plugin, defaults to merged.esp in cwd
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Interleave the responses of shared dialogue topics instead of overwriting the topic
+        #[arg(long)]
+        merge_dialogue: bool,
+
+        /// Earlier plugins override later ones instead
+        #[arg(long)]
+        first_wins: bool,
+    },
+
+    /// Report references to ids that are not defined in a plugin or its masters
+    Validate {
+        /// input path, may be a plugin
+        input: Option<PathBuf>,
+
+        /// Folder with the master files, ids defined in masters count as resolved
+        #[arg(short, long)]
+        masters_dir: Option<PathBuf>,
+
+        /// output file for the report, defaults to printing to stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// The format of the report file, default is yaml
+        #[arg(short, long, value_enum)]
+        format: Option<ESerializedType>,
+    },
+
+    /// Remove all records of specific types from a plugin
+    Clean {
+        /// input path, may be a plugin
+        input: Option<PathBuf>,
+
+        /// output plugin, defaults to <name>_clean next to the input
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Remove records with this tag, e.g. --remove GMST
+        #[arg(short, long)]
+        remove: Vec<String>,
+
+        /// Remove records that are identical to the ones in this master
+        #[arg(short, long)]
+        master: Option<PathBuf>,
+    },
+
+    /// Export records to one csv file per tag
+    ExportCsv {
+        /// input path, may be a plugin
+        input: Option<PathBuf>,
+
+        /// output directory, defaults to cwd
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Only export records with this tag, e.g. --tag WEAP. Defaults to all tags
+        #[arg(short, long)]
+        tag: Vec<String>,
+    },
+
+    /// Compare two plugins, exits with code 1 if they differ and 2 on errors
+    Diff {
+        /// the original plugin
+        old: Option<PathBuf>,
+
+        /// the changed plugin
+        new: Option<PathBuf>,
+
+        /// output file, defaults to stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// The format changed records are compared in, default is yaml
+        #[arg(short, long, value_enum)]
+        format: Option<ESerializedType>,
+    },
+
+    /// List the master files of a plugin
+    Masters {
+        /// input path, may be a plugin or a folder
+        input: Option<PathBuf>,
+
+        /// Print the masters in this format instead of a list
+        #[arg(short, long, value_enum)]
+        format: Option<ESerializedType>,
+
+        /// Include OpenMW script lists (.omwscripts) in folder input
+        #[arg(long)]
+        omw: bool,
+    },
+
+    /// Rename master files in the header of a plugin
+    Remaster {
+        /// input plugin
+        input: Option<PathBuf>,
+
+        /// output plugin, defaults to the input plugin
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Master to rename, may be repeated
+        #[arg(long, required = true)]
+        from: Vec<String>,
+
+        /// New name of the master at the same position in --from
+        #[arg(long, required = true)]
+        to: Vec<String>,
+
+        /// Update the recorded size from the renamed master next to the plugin
+        #[arg(long)]
+        update_size: bool,
+    },
+
+    /// Write the text of all scripts in a plugin to .mwscript files
+    ExtractScripts {
+        /// input plugin
+        input: Option<PathBuf>,
+
+        /// output directory, defaults to cwd
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Replace the text of scripts in a plugin with edited .mwscript files
+    InjectScripts {
+        /// input plugin
+        input: Option<PathBuf>,
+
+        /// folder with <id>.mwscript files
+        #[arg(short, long)]
+        scripts: PathBuf,
+
+        /// output plugin, defaults to the input plugin
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Convert a serialized plugin or record list to another format
+    Convert {
+        /// input file
+        input: Option<PathBuf>,
+
+        /// output file, defaults to the input with the extension of the target format
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// The format to convert from, inferred from the input extension by default
+        #[arg(long, value_enum)]
+        from: Option<ESerializedType>,
+
+        /// The format to convert to, inferred from the output extension by default
+        #[arg(long, value_enum)]
+        to: Option<ESerializedType>,
+    },
+
+    /// Split a plugin into one plugin per record tag
+    Split {
+        /// input plugin
+        input: Option<PathBuf>,
+
+        /// output directory, defaults to the folder of the input plugin
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Only split out these tags, the remaining records are written to <name>_rest.esp
+        #[arg(long)]
+        by: Vec<String>,
+    },
+
+    /// Remove duplicate records from a plugin, the last occurrence of each id is kept
+    Dedupe {
+        /// input plugin
+        input: Option<PathBuf>,
+
+        /// output plugin, defaults to the input plugin
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Print the records with an editor id to stdout
+    Get {
+        /// input plugin
+        input: Option<PathBuf>,
+
+        /// Editor id of the record, case-insensitive
+        #[arg(long)]
+        id: String,
+
+        /// Only match records with this tag, e.g. WEAP
+        #[arg(long)]
+        tag: Option<String>,
+
+        /// The format to print the records in, default is yaml
+        #[arg(short, long, value_enum)]
+        format: Option<ESerializedType>,
+    },
+
+    /// Check that a dumped folder or serialized file packs back into the original plugin,
+    /// exits with code 1 if they differ and 2 on errors
+    Verify {
+        /// original plugin
+        original: Option<PathBuf>,
+
+        /// dumped folder or serialized file
+        dumped: Option<PathBuf>,
+    },
+
+    /// Extract the exterior cells in a grid range into a new plugin
+    Cells {
+        /// input plugin
+        input: Option<PathBuf>,
+
+        /// output plugin, defaults to <name>_cells.esp next to the input
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Minimum grid x coordinate, inclusive
+        #[arg(long, allow_negative_numbers = true)]
+        min_x: Option<i32>,
+
+        /// Maximum grid x coordinate, inclusive
+        #[arg(long, allow_negative_numbers = true)]
+        max_x: Option<i32>,
+
+        /// Minimum grid y coordinate, inclusive
+        #[arg(long, allow_negative_numbers = true)]
+        min_y: Option<i32>,
+
+        /// Maximum grid y coordinate, inclusive
+        #[arg(long, allow_negative_numbers = true)]
+        max_y: Option<i32>,
+
+        /// Include the landscape and path grids of the cells
+        #[arg(long)]
+        land: bool,
+
+        /// Include the records placed in the cells
+        #[arg(long)]
+        references: bool,
+    },
+
+    /// Rename editor ids of records and update the references to them
+    Rename {
+        /// input plugin
+        input: Option<PathBuf>,
+
+        /// output plugin, defaults to the input plugin
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Mapping of old to new ids, a json object or a csv file with old_id,new_id lines
+        #[arg(short, long)]
+        mapping: PathBuf,
+    },
+
+    /// Rewrite all editor ids and the references to them to lowercase
+    NormalizeIds {
+        /// input plugin
+        input: Option<PathBuf>,
+
+        /// output plugin, defaults to the input plugin
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Use the casing of the first record with an id instead of lowercase
+        #[arg(long)]
+        preserve: bool,
+    },
+
+    /// List the meshes, icons, sounds and textures referenced by a plugin
+    Assets {
+        /// input plugin
+        input: Option<PathBuf>,
+
+        /// output file, defaults to stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Write the list in this format instead of one path per line
+        #[arg(short, long, value_enum)]
+        format: Option<ESerializedType>,
+    },
+
+    /// Write the dialogue of a plugin as a markdown transcript, one section per topic
+    Dialogue {
+        /// input plugin
+        input: Option<PathBuf>,
+
+        /// output file, defaults to the input with a md extension
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Set the values of game settings and globals in a plugin
+    SetGmst {
+        /// input plugin
+        input: Option<PathBuf>,
+
+        /// output plugin, defaults to the input plugin
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Config file with one NAME=VALUE per line
+        #[arg(short, long)]
+        config: Option<PathBuf>,
+
+        /// Value to set as NAME=VALUE, may be repeated
+        #[arg(long)]
+        set: Vec<String>,
     },
 }
 
 fn main() {
-    match &Cli::parse().commands {
+    let cli = Cli::parse();
+
+    let level = match cli.verbose {
+        0 => LevelFilter::Warn,
+        1 => LevelFilter::Info,
+        2 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    };
+    let log_file = match &cli.log_file {
+        Some(path) => path.as_path(),
+        None => Path::new("log.txt"),
+    };
+    if let Err(err) = init_logger(log_file, level, cli.quiet) {
+        println!("Error initializing logger: {}", err);
+    }
+
+    // cap the global thread pool used by parallel tasks
+    if cli.threads > 0 {
+        if let Err(err) = rayon::ThreadPoolBuilder::new()
+            .num_threads(cli.threads)
+            .build_global()
+        {
+            println!("Error initializing thread pool: {}", err);
+        }
+    }
+
+    if cli.dry_run && !supports_dry_run(&cli.commands) {
+        println!("Error: --dry-run is not supported by this command");
+        std::process::exit(2);
+    }
+
+    match &cli.commands {
         Commands::Dump {
             input,
             output,
@@ -111,41 +731,431 @@ fn main() {
             include,
             exclude,
             format,
-        } => match dump(input, output, *create, include, exclude, format) {
-            Ok(_) => println!("Done."),
+            out_ext,
+            sorted,
+            cfg,
+            recursive,
+            id_include,
+            id_exclude,
+            single_file,
+            omw,
+            lint_scripts,
+            baseline,
+            flatten,
+            compact,
+            manifest,
+            limit,
+            toml_inline,
+            encoding,
+            glob,
+        } => match dump(
+            input,
+            output,
+            &DumpOptions {
+                create: *create,
+                include: include.clone(),
+                exclude: exclude.clone(),
+                format: format.clone(),
+                out_ext: out_ext.clone(),
+                sorted: *sorted,
+                cfg: cfg.clone(),
+                recursive: *recursive,
+                id_include: id_include.clone(),
+                id_exclude: id_exclude.clone(),
+                single_file: *single_file,
+                omw: *omw,
+                lint_scripts: *lint_scripts,
+                baseline: baseline.clone(),
+                flatten: *flatten,
+                compact: *compact,
+                manifest: *manifest,
+                limit: *limit,
+                toml_inline: *toml_inline,
+                encoding: encoding.clone().unwrap_or_default(),
+                glob: glob.clone(),
+            },
+        ) {
+            Ok(summary) => print_summary(&summary, &cli.summary_format),
             Err(err) => println!("Error dumping scripts: {}", err),
         },
         Commands::Pack {
             input,
             output,
             format,
-        } => match pack(input, output, format) {
-            Ok(_) => println!("Done."),
+            manifest,
+            master,
+        } => match pack(
+            input,
+            output,
+            &PackOptions {
+                format: format.clone(),
+                use_manifest: *manifest,
+                master: *master,
+                dry_run: cli.dry_run,
+            },
+        ) {
+            Ok(summary) => print_summary(&summary, &cli.summary_format),
             Err(err) => println!("Error packing plugin: {}", err),
         },
         Commands::Serialize {
             input,
             output,
             format,
-        } => match serialize_plugin(input, output, format) {
-            Ok(_) => println!("Done."),
-            Err(err) => println!("Error serializing plugin: {}", err),
-        },
+            out_ext,
+            stdout,
+            keyed,
+            sorted,
+            compact,
+            limit,
+            lenient,
+            toml_inline,
+        } => {
+            let stdout_path = Some(PathBuf::from("-"));
+            let output = if *stdout { &stdout_path } else { output };
+            // keep stdout clean for piping
+            let to_stdout = output.as_ref().is_some_and(|o| o.as_os_str() == "-");
+            let options = SerializeOptions {
+                format: format.clone(),
+                out_ext: out_ext.clone(),
+                keyed: *keyed,
+                sorted: *sorted,
+                compact: *compact,
+                limit: *limit,
+                lenient: *lenient,
+                toml_inline: *toml_inline,
+            };
+            match serialize_plugin(input, output, &options) {
+                Ok(_) if to_stdout => {}
+                Ok(summary) => print_summary(&summary, &cli.summary_format),
+                Err(err) => println!("Error serializing plugin: {}", err),
+            }
+        }
         Commands::Deserialize {
             input,
             output,
             overwrite,
-        } => match deserialize_plugin(input, output, *overwrite) {
-            Ok(_) => println!("Done."),
-            Err(err) => println!("Error deserializing file: {}", err),
-        },
-        Commands::AtlasCoverage { input, output } => match atlas_coverage(input, output) {
-            Ok(_) => println!("Done."),
+            diff_against_binary,
+            format,
+        } => {
+            let result = match diff_against_binary {
+                Some(original) => {
+                    diff_task::diff_against_binary(input, original, output, format).map(|_| None)
+                }
+                None => {
+                    deserialize_plugin(input, output, *overwrite, format, cli.dry_run).map(Some)
+                }
+            };
+            match result {
+                Ok(Some(summary)) => print_summary(&summary, &cli.summary_format),
+                Ok(None) => done(cli.quiet),
+                Err(err) => println!("Error deserializing file: {}", err),
+            }
+        }
+        Commands::AtlasCoverage {
+            input,
+            output,
+            prefix,
+            extensions,
+            format,
+            assets_root,
+            exclude_internal,
+        } => match atlas_coverage(
+            input,
+            output,
+            &AtlasOptions {
+                prefix: prefix.clone(),
+                extensions: extensions.clone(),
+                format: format.clone(),
+                assets_root: assets_root.clone(),
+                exclude_internal: *exclude_internal,
+            },
+        ) {
+            Ok(_) => done(cli.quiet),
             Err(err) => println!("Error running atlas coverage: {}", err),
         },
-        Commands::Sql { input, output } => match sql_task::sql_task(input, output) {
-            Ok(_) => println!("Done."),
+        Commands::Sql {
+            input,
+            output,
+            query,
+            csv,
+            cfg,
+            omw,
+            progress,
+            include,
+            exclude,
+            overwrite,
+            append,
+            no_index,
+            schema_only,
+            dedup_report,
+            strict,
+            readonly,
+            glob,
+        } => match sql_task::sql_task(
+            input,
+            output,
+            &sql_task::SqlOptions {
+                query: query.clone(),
+                csv: *csv,
+                cfg: cfg.clone(),
+                omw: *omw,
+                progress: *progress && !cli.quiet,
+                include: include.clone(),
+                exclude: exclude.clone(),
+                overwrite: *overwrite,
+                append: *append,
+                no_index: *no_index,
+                schema_only: schema_only.clone(),
+                dedup_report: *dedup_report,
+                strict: *strict,
+                readonly: *readonly,
+                glob: glob.clone(),
+            },
+        ) {
+            // keep stdout clean for the query results
+            Ok(_) if query.is_some() => {}
+            Ok(_) => done(cli.quiet),
             Err(err) => println!("Error running sql command: {}", err),
         },
+        Commands::Stats {
+            input,
+            top,
+            format,
+            omw,
+        } => match stats_task::stats(input, *top, format, *omw) {
+            // keep stdout clean for the formatted output
+            Ok(_) if format.is_some() => {}
+            Ok(_) => done(cli.quiet),
+            Err(err) => println!("Error running stats command: {}", err),
+        },
+        Commands::ScriptStats {
+            input,
+            max_bytes,
+            format,
+        } => match stats_task::script_stats(input, *max_bytes, format) {
+            // keep stdout clean for the formatted output
+            Ok(_) if format.is_some() => {}
+            Ok(_) => done(cli.quiet),
+            Err(err) => println!("Error running script stats command: {}", err),
+        },
+        Commands::RefCount { input, format } => match stats_task::ref_count(input, format) {
+            // keep stdout clean for the formatted output
+            Ok(_) if format.is_some() => {}
+            Ok(_) => done(cli.quiet),
+            Err(err) => println!("Error counting references: {}", err),
+        },
+        Commands::Merge {
+            inputs,
+            output,
+            merge_dialogue,
+            first_wins,
+        } => match merge_task::merge(inputs, output, *merge_dialogue, *first_wins, cli.dry_run) {
+            Ok(summary) => print_summary(&summary, &cli.summary_format),
+            Err(err) => println!("Error merging plugins: {}", err),
+        },
+        Commands::Validate {
+            input,
+            masters_dir,
+            output,
+            format,
+        } => match validate_task::validate(input, masters_dir, output, format) {
+            Ok(_) => done(cli.quiet),
+            Err(err) => println!("Error validating plugin: {}", err),
+        },
+        Commands::Clean {
+            input,
+            output,
+            remove,
+            master,
+        } => match clean_task::clean(input, output, remove, master, cli.dry_run) {
+            Ok(summary) => print_summary(&summary, &cli.summary_format),
+            Err(err) => println!("Error cleaning plugin: {}", err),
+        },
+        Commands::ExportCsv { input, output, tag } => {
+            match csv_task::export_csv(input, output, tag) {
+                Ok(summary) => print_summary(&summary, &cli.summary_format),
+                Err(err) => println!("Error exporting csv: {}", err),
+            }
+        }
+        Commands::Diff {
+            old,
+            new,
+            output,
+            format,
+        } => match diff_task::diff(old, new, output, format) {
+            Ok(false) => {}
+            Ok(true) => std::process::exit(1),
+            Err(err) => {
+                println!("Error comparing plugins: {}", err);
+                std::process::exit(2);
+            }
+        },
+        Commands::Masters { input, format, omw } => {
+            if let Err(err) = masters_task::masters(input, format, *omw) {
+                println!("Error listing masters: {}", err);
+            }
+        }
+        Commands::Remaster {
+            input,
+            output,
+            from,
+            to,
+            update_size,
+        } => match remaster_task::remaster(input, output, from, to, *update_size, cli.dry_run) {
+            Ok(summary) => print_summary(&summary, &cli.summary_format),
+            Err(err) => println!("Error renaming masters: {}", err),
+        },
+        Commands::ExtractScripts { input, output } => {
+            match scripts_task::extract_scripts(input, output) {
+                Ok(_) => done(cli.quiet),
+                Err(err) => println!("Error extracting scripts: {}", err),
+            }
+        }
+        Commands::InjectScripts {
+            input,
+            scripts,
+            output,
+        } => match scripts_task::inject_scripts(input, scripts, output, cli.dry_run) {
+            Ok(_) => done(cli.quiet),
+            Err(err) => println!("Error injecting scripts: {}", err),
+        },
+        Commands::Convert {
+            input,
+            output,
+            from,
+            to,
+        } => match convert_task::convert(input, output, from, to) {
+            Ok(_) => done(cli.quiet),
+            Err(err) => println!("Error converting: {}", err),
+        },
+        Commands::Split { input, output, by } => {
+            match split_task::split(input, output, by, cli.dry_run) {
+                Ok(_) => done(cli.quiet),
+                Err(err) => println!("Error splitting plugin: {}", err),
+            }
+        }
+        Commands::Dedupe { input, output } => match dedupe(input, output, cli.dry_run) {
+            Ok(summary) => print_summary(&summary, &cli.summary_format),
+            Err(err) => println!("Error removing duplicates: {}", err),
+        },
+        Commands::Get {
+            input,
+            id,
+            tag,
+            format,
+        } => {
+            if let Err(err) = get_task::get(input, id, tag, format) {
+                println!("Error getting record: {}", err);
+            }
+        }
+        Commands::Verify { original, dumped } => match verify_task::verify(original, dumped) {
+            Ok(false) => {}
+            Ok(true) => std::process::exit(1),
+            Err(err) => {
+                println!("Error verifying plugin: {}", err);
+                std::process::exit(2);
+            }
+        },
+        Commands::Cells {
+            input,
+            output,
+            min_x,
+            max_x,
+            min_y,
+            max_y,
+            land,
+            references,
+        } => match cells_task::cells(
+            input,
+            output,
+            &cells_task::CellsOptions {
+                min_x: *min_x,
+                max_x: *max_x,
+                min_y: *min_y,
+                max_y: *max_y,
+                land: *land,
+                references: *references,
+            },
+            cli.dry_run,
+        ) {
+            Ok(_) => done(cli.quiet),
+            Err(err) => println!("Error extracting cells: {}", err),
+        },
+        Commands::Rename {
+            input,
+            output,
+            mapping,
+        } => match rename_task::rename(input, output, mapping, cli.dry_run) {
+            Ok(_) => done(cli.quiet),
+            Err(err) => println!("Error renaming records: {}", err),
+        },
+        Commands::NormalizeIds {
+            input,
+            output,
+            preserve,
+        } => match rename_task::normalize_ids(input, output, *preserve, cli.dry_run) {
+            Ok(_) => done(cli.quiet),
+            Err(err) => println!("Error normalizing ids: {}", err),
+        },
+        Commands::Assets {
+            input,
+            output,
+            format,
+        } => match assets_task::assets(input, output, format) {
+            // keep stdout clean for piping
+            Ok(_) if output.is_none() => {}
+            Ok(_) => done(cli.quiet),
+            Err(err) => println!("Error listing assets: {}", err),
+        },
+        Commands::SetGmst {
+            input,
+            output,
+            config,
+            set,
+        } => match gmst_task::set_gmst(input, output, config, set, cli.dry_run) {
+            Ok(_) => done(cli.quiet),
+            Err(err) => println!("Error setting values: {}", err),
+        },
+        Commands::Dialogue { input, output } => match dialogue_task::dialogue(input, output) {
+            Ok(_) => done(cli.quiet),
+            Err(err) => println!("Error writing dialogue: {}", err),
+        },
+    }
+}
+
+/// Check if a command honors --dry-run, commands writing files other than plugins don't
+fn supports_dry_run(command: &Commands) -> bool {
+    !matches!(
+        command,
+        Commands::Dump { .. }
+            | Commands::Serialize { .. }
+            | Commands::Deserialize {
+                diff_against_binary: Some(_),
+                ..
+            }
+            | Commands::AtlasCoverage { .. }
+            | Commands::Sql { .. }
+            | Commands::Validate { .. }
+            | Commands::ExportCsv { .. }
+            | Commands::Diff { .. }
+            | Commands::ExtractScripts { .. }
+            | Commands::Convert { .. }
+            | Commands::Assets { .. }
+            | Commands::Dialogue { .. }
+    )
+}
+
+/// Print that a command finished, quiet runs only print the summary of the task
+fn done(quiet: bool) {
+    if !quiet {
+        println!("Done.");
+    }
+}
+
+/// Print the summary of a task, falls back to a sentence if it can't be serialized
+fn print_summary(summary: &TaskSummary, format: &Option<ESerializedType>) {
+    match summary.render(format) {
+        Ok(text) => println!("{}", text),
+        Err(err) => println!("{}\nError rendering summary: {}", summary, err),
     }
 }
@@ -0,0 +1,91 @@
+use std::{
+    io::{self, Error, ErrorKind},
+    path::PathBuf,
+};
+
+use serde_json::json;
+use tes3::esp::{Plugin, TES3Object};
+
+use crate::{
+    get_plugins_sorted, no_plugins_error, parse_plugin, plugin_file_exists, report_to_string,
+    ESerializedType,
+};
+
+/// Print the master files and their sizes of a plugin or of all plugins in a folder.
+/// Without a format a list is printed, otherwise the masters are serialized to stdout
+pub fn masters(
+    input: &Option<PathBuf>,
+    format: &Option<ESerializedType>,
+    omw: bool,
+) -> io::Result<()> {
+    let input_path: &PathBuf;
+    // check no input
+    if let Some(i) = input {
+        input_path = i;
+    } else {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "No input path specified.",
+        ));
+    }
+    if !input_path.is_dir() && !plugin_file_exists(input_path) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Input path does not exist",
+        ));
+    }
+
+    // a folder is read in load order
+    let plugin_paths = if input_path.is_dir() {
+        get_plugins_sorted(input_path, omw, &None)?
+    } else {
+        vec![input_path.to_owned()]
+    };
+    if plugin_paths.is_empty() {
+        return Err(no_plugins_error(input_path));
+    }
+
+    let mut report = vec![];
+    for path in &plugin_paths {
+        let plugin = match parse_plugin(path) {
+            Ok(p) => p,
+            Err(_) => return Err(Error::new(ErrorKind::Other, "Plugin parsing failed.")),
+        };
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let masters = get_masters(&plugin);
+
+        if format.is_none() {
+            println!("{}", name);
+            for (master, size) in &masters {
+                println!("  {} ({} bytes)", master, size);
+            }
+        }
+        report.push(json!({
+            "name": name,
+            "masters": masters
+                .iter()
+                .map(|(m, s)| json!({ "name": m, "size": s }))
+                .collect::<Vec<_>>(),
+        }));
+    }
+
+    if let Some(format) = format {
+        let report = json!({ "plugins": report });
+        println!("{}", report_to_string(format, &report)?);
+    }
+
+    Ok(())
+}
+
+/// Get the master file names and sizes from the plugin header
+pub fn get_masters(plugin: &Plugin) -> Vec<(String, u64)> {
+    for object in &plugin.objects {
+        if let TES3Object::Header(header) = object {
+            return header.masters.clone();
+        }
+    }
+    vec![]
+}
@@ -0,0 +1,290 @@
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    io::{self, Error, ErrorKind},
+    path::PathBuf,
+};
+
+use tes3::esp::{Dialogue, DialogueInfo, EditorId, Header, Plugin, TES3Object, TypeInfo};
+
+use crate::{
+    append_ext, parse_plugin, plugin_file_exists, save_plugin, serialize_object, ESerializedType,
+    TaskSummary,
+};
+
+/// A dialogue topic and its INFO records in order
+struct Topic {
+    dialogue: Dialogue,
+    infos: Vec<DialogueInfo>,
+}
+
+/// How the responses of a shared topic were combined
+#[derive(Default)]
+struct TopicReport {
+    added: usize,
+    replaced: usize,
+    duplicates: usize,
+    /// Changed responses that were not replaced because the first version wins
+    kept: usize,
+    ambiguous: usize,
+}
+
+/// Records of all merged plugins, in first-seen order
+#[derive(Default)]
+struct MergedRecords {
+    header: Option<Header>,
+    masters: Vec<(String, u64)>,
+    records: Vec<TES3Object>,
+    record_map: HashMap<(String, String), usize>,
+    topics: Vec<Topic>,
+    topic_map: HashMap<String, usize>,
+}
+
+/// Merge multiple plugins into one, later plugins override earlier ones.
+/// With first_wins earlier plugins take precedence instead
+pub fn merge(
+    inputs: &[PathBuf],
+    output: &Option<PathBuf>,
+    merge_dialogue: bool,
+    first_wins: bool,
+    dry_run: bool,
+) -> io::Result<TaskSummary> {
+    if inputs.is_empty() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "No input paths specified.",
+        ));
+    }
+
+    let mut merged = MergedRecords::default();
+    for input in inputs {
+        if !plugin_file_exists(input) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Input path does not exist: {}", input.display()),
+            ));
+        }
+
+        let plugin = match parse_plugin(input) {
+            Ok(p) => p,
+            Err(_) => return Err(Error::new(ErrorKind::Other, "Plugin parsing failed.")),
+        };
+
+        progress!("Merging {}", input.display());
+        merge_plugin(&mut merged, plugin, merge_dialogue, first_wins);
+    }
+
+    // the merged plugins are no longer masters of the output
+    let input_names: HashSet<String> = inputs
+        .iter()
+        .filter_map(|i| i.file_name())
+        .map(|n| n.to_string_lossy().to_lowercase())
+        .collect();
+    merged
+        .masters
+        .retain(|(name, _)| !input_names.contains(&name.to_lowercase()));
+
+    // assemble plugin, dialogue goes last so INFO records stay behind their topic
+    let mut plugin = Plugin::new();
+    if let Some(mut header) = merged.header {
+        header.masters = merged.masters;
+        plugin.objects.push(TES3Object::from(header));
+    }
+    plugin.objects.extend(merged.records);
+    for topic in merged.topics {
+        plugin.objects.push(TES3Object::from(topic.dialogue));
+        plugin
+            .objects
+            .extend(topic.infos.into_iter().map(TES3Object::from));
+    }
+
+    // save
+    let output_path = match output {
+        Some(o) => o.to_owned(),
+        None => append_ext("esp", env::current_dir()?.join("merged")),
+    };
+    save_plugin(&plugin, &output_path, dry_run)
+}
+
+/// Merge the records of one plugin into the merged records
+fn merge_plugin(
+    merged: &mut MergedRecords,
+    plugin: Plugin,
+    merge_dialogue: bool,
+    first_wins: bool,
+) {
+    let mut current_topic: Option<Topic> = None;
+    let mut topics = vec![];
+
+    for object in plugin.objects {
+        match object {
+            TES3Object::Header(header) => {
+                // masters of all inputs are kept in first-seen order
+                for master in &header.masters {
+                    if !merged
+                        .masters
+                        .iter()
+                        .any(|(name, _)| name.eq_ignore_ascii_case(&master.0))
+                    {
+                        merged.masters.push(master.clone());
+                    }
+                }
+                // the header is taken from the last input
+                merged.header = Some(header);
+            }
+            TES3Object::Dialogue(dialogue) => {
+                if let Some(topic) = current_topic.take() {
+                    topics.push(topic);
+                }
+                current_topic = Some(Topic {
+                    dialogue,
+                    infos: vec![],
+                });
+            }
+            TES3Object::DialogueInfo(info) => match current_topic.as_mut() {
+                Some(topic) => topic.infos.push(info),
+                None => progress!("Skipping INFO without topic: {}", info.id),
+            },
+            _ => {
+                let key = (
+                    object.tag_str().to_owned(),
+                    object.editor_id().to_lowercase(),
+                );
+                match merged.record_map.get(&key) {
+                    Some(_) if first_wins => {}
+                    Some(i) => merged.records[*i] = object,
+                    None => {
+                        merged.record_map.insert(key, merged.records.len());
+                        merged.records.push(object);
+                    }
+                }
+            }
+        }
+    }
+    if let Some(topic) = current_topic.take() {
+        topics.push(topic);
+    }
+
+    for topic in topics {
+        let key = topic.dialogue.id.to_lowercase();
+        match merged.topic_map.get(&key) {
+            Some(i) if merge_dialogue => {
+                let existing = &mut merged.topics[*i];
+                let report = merge_infos(&mut existing.infos, topic.infos, first_wins);
+                if !first_wins {
+                    existing.dialogue = topic.dialogue;
+                }
+                print_topic_report(&key, &report);
+            }
+            Some(_) if first_wins => {}
+            Some(i) => merged.topics[*i] = topic,
+            None => {
+                merged.topic_map.insert(key, merged.topics.len());
+                merged.topics.push(topic);
+            }
+        }
+    }
+}
+
+/// Interleave the INFO records of a shared topic.
+/// Each source's relative order is preserved and identical responses are deduped.
+/// New responses are inserted after the previous response of the same source.
+/// Changed responses replace the existing ones unless first_wins is set
+fn merge_infos(
+    base: &mut Vec<DialogueInfo>,
+    other: Vec<DialogueInfo>,
+    first_wins: bool,
+) -> TopicReport {
+    let mut report = TopicReport::default();
+
+    let other_ids: HashSet<String> = other.iter().map(|i| i.id.clone()).collect();
+    // new responses before the first shared one go in front of it
+    let mut cursor = other
+        .iter()
+        .find_map(|i| base.iter().position(|b| b.id == i.id))
+        .unwrap_or(base.len());
+
+    // the serialized content of each base response, in the order of base
+    let mut contents: Vec<Option<String>> = base.iter().map(info_content).collect();
+    for info in other {
+        let content = info_content(&info);
+        if let Some(pos) = base.iter().position(|b| b.id == info.id) {
+            if same_content(&contents[pos], &content) {
+                report.duplicates += 1;
+            } else if first_wins {
+                report.kept += 1;
+            } else {
+                base[pos] = info;
+                contents[pos] = content;
+                report.replaced += 1;
+            }
+            cursor = pos + 1;
+            continue;
+        }
+
+        if let Some(pos) = contents.iter().position(|c| same_content(c, &content)) {
+            // same response under a different id
+            report.duplicates += 1;
+            cursor = pos + 1;
+            continue;
+        }
+
+        // another source added responses at the same spot
+        if cursor < base.len() && !other_ids.contains(&base[cursor].id) {
+            report.ambiguous += 1;
+        }
+        base.insert(cursor, info);
+        contents.insert(cursor, content);
+        cursor += 1;
+        report.added += 1;
+    }
+
+    relink_infos(base);
+    report
+}
+
+/// Serialize a response without its id and links for comparison
+fn info_content(info: &DialogueInfo) -> Option<String> {
+    let mut info = info.clone();
+    info.id.clear();
+    info.prev_id.clear();
+    info.next_id.clear();
+    serialize_object(&TES3Object::from(info), &ESerializedType::Json).ok()
+}
+
+/// Whether two serialized responses are the same.
+/// Responses that could not be serialized are never the same
+fn same_content(a: &Option<String>, b: &Option<String>) -> bool {
+    matches!((a, b), (Some(a), Some(b)) if a == b)
+}
+
+/// Rebuild the linked list of a topic's responses
+fn relink_infos(infos: &mut [DialogueInfo]) {
+    let ids: Vec<String> = infos.iter().map(|i| i.id.clone()).collect();
+    for (i, info) in infos.iter_mut().enumerate() {
+        info.prev_id = if i > 0 {
+            ids[i - 1].clone()
+        } else {
+            String::new()
+        };
+        info.next_id = ids.get(i + 1).cloned().unwrap_or_default();
+    }
+}
+
+fn print_topic_report(topic: &str, report: &TopicReport) {
+    progress!(
+        "Merged topic {}: {} added, {} replaced, {} kept, {} duplicates",
+        topic,
+        report.added,
+        report.replaced,
+        report.kept,
+        report.duplicates
+    );
+    if report.ambiguous > 0 {
+        log::warn!(
+            "Ambiguous ordering of {} responses in topic {}",
+            report.ambiguous,
+            topic
+        );
+    }
+}
@@ -0,0 +1,90 @@
+use std::{
+    fs,
+    io::{self, Error, ErrorKind},
+    path::PathBuf,
+};
+
+use tes3::esp::TES3Object;
+
+use crate::{parse_plugin, save_plugin, TaskSummary};
+
+/// Rename master files in the header of a plugin, each from name is replaced by the to name at the same position.
+/// If update_size is set the recorded size is read from the renamed master next to the plugin.
+/// The plugin is saved in place by default
+pub fn remaster(
+    input: &Option<PathBuf>,
+    output: &Option<PathBuf>,
+    from: &[String],
+    to: &[String],
+    update_size: bool,
+    dry_run: bool,
+) -> io::Result<TaskSummary> {
+    let input_path: &PathBuf;
+    // check no input
+    if let Some(i) = input {
+        input_path = i;
+    } else {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "No input path specified.",
+        ));
+    }
+    if !input_path.is_file() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Input path does not exist",
+        ));
+    }
+    if from.len() != to.len() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Every --from master needs a --to master",
+        ));
+    }
+
+    let mut plugin = match parse_plugin(input_path) {
+        Ok(p) => p,
+        Err(_) => return Err(Error::new(ErrorKind::Other, "Plugin parsing failed.")),
+    };
+
+    let Some(header) = plugin.objects.iter_mut().find_map(|o| match o {
+        TES3Object::Header(h) => Some(h),
+        _ => None,
+    }) else {
+        return Err(Error::new(ErrorKind::InvalidData, "Plugin has no header"));
+    };
+
+    for (from, to) in from.iter().zip(to) {
+        let Some(master) = header
+            .masters
+            .iter_mut()
+            .find(|(name, _)| name.eq_ignore_ascii_case(from))
+        else {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!("Master not found in plugin: {}", from),
+            ));
+        };
+
+        if update_size {
+            let path = input_path.with_file_name(to);
+            master.1 = match fs::metadata(&path) {
+                Ok(m) => m.len(),
+                Err(e) => {
+                    return Err(Error::new(
+                        e.kind(),
+                        format!("Could not read size of {}: {}", path.display(), e),
+                    ))
+                }
+            };
+        }
+        progress!("Renamed master {} to {}", master.0, to);
+        master.0 = to.clone();
+    }
+
+    let output_path = match output {
+        Some(o) => o,
+        None => input_path,
+    };
+    save_plugin(&plugin, output_path, dry_run)
+}
@@ -0,0 +1,330 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    io::{self, Error, ErrorKind},
+    path::{Path, PathBuf},
+};
+
+use regex::Regex;
+use serde_json::Value;
+use tes3::esp::{EditorId, Plugin, TES3Object};
+
+use crate::{is_extension, parse_plugin, plugin_file_exists, save_plugin};
+
+/// Text and path fields that are never references to other records
+const TEXT_FIELDS: [&str; 9] = [
+    "name",
+    "description",
+    "author",
+    "text",
+    "mesh",
+    "icon",
+    "texture",
+    "file_name",
+    "sound_path",
+];
+
+/// Rename the editor ids of records and update all references to them in the plugin.
+/// The mapping is a json object of old to new ids, or a csv file with one old,new pair per line.
+/// Ids are matched case-insensitively. The plugin is saved in place by default.
+/// Returns the number of renamed records and changed references
+pub fn rename(
+    input: &Option<PathBuf>,
+    output: &Option<PathBuf>,
+    mapping: &Path,
+    dry_run: bool,
+) -> io::Result<(usize, usize)> {
+    let input_path: &PathBuf;
+    // check no input
+    if let Some(i) = input {
+        input_path = i;
+    } else {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "No input path specified.",
+        ));
+    }
+    if !plugin_file_exists(input_path) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Input path does not exist",
+        ));
+    }
+
+    let renames = read_mapping(mapping)?;
+    let mut plugin = match parse_plugin(input_path) {
+        Ok(p) => p,
+        Err(_) => return Err(Error::new(ErrorKind::Other, "Plugin parsing failed.")),
+    };
+
+    // a new id that is already used by a record that is not renamed collides
+    let ids: HashSet<String> = plugin
+        .objects
+        .iter()
+        .map(|o| o.editor_id().to_lowercase())
+        .filter(|id| !renames.contains_key(id))
+        .collect();
+    for new_id in renames.values() {
+        if ids.contains(&new_id.to_lowercase()) {
+            log::warn!("Renamed id already exists: {}", new_id);
+        }
+    }
+
+    let (records, references, scripts) = rename_records(&mut plugin, &renames)?;
+    println!("Renamed {} records and {} references", records, references);
+    warn_recompile(scripts);
+
+    let output_path = match output {
+        Some(o) => o,
+        None => input_path,
+    };
+    save_plugin(&plugin, output_path, dry_run)?;
+
+    Ok((records, references))
+}
+
+/// Rewrite the editor ids of all records and the references to them to one casing.
+/// Ids are lowercased, or take the casing of their first record if preserve is set.
+/// Cells and dialogue topics keep their ids since these are shown in game.
+/// Returns the number of changed records and references
+pub fn normalize_ids(
+    input: &Option<PathBuf>,
+    output: &Option<PathBuf>,
+    preserve: bool,
+    dry_run: bool,
+) -> io::Result<(usize, usize)> {
+    let input_path: &PathBuf;
+    // check no input
+    if let Some(i) = input {
+        input_path = i;
+    } else {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "No input path specified.",
+        ));
+    }
+    if !plugin_file_exists(input_path) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Input path does not exist",
+        ));
+    }
+
+    let mut plugin = match parse_plugin(input_path) {
+        Ok(p) => p,
+        Err(_) => return Err(Error::new(ErrorKind::Other, "Plugin parsing failed.")),
+    };
+
+    // the canonical id of every record by lowercase id
+    let mut renames: HashMap<String, String> = HashMap::new();
+    for object in &plugin.objects {
+        if matches!(
+            object,
+            TES3Object::Header(_) | TES3Object::Cell(_) | TES3Object::Dialogue(_)
+        ) {
+            continue;
+        }
+        let id = object.editor_id();
+        if id.is_empty() {
+            continue;
+        }
+        let canonical = if preserve {
+            id.to_string()
+        } else {
+            id.to_lowercase()
+        };
+        renames.entry(id.to_lowercase()).or_insert(canonical);
+    }
+
+    let (records, references, scripts) = rename_records(&mut plugin, &renames)?;
+    println!(
+        "Normalized {} records and {} references",
+        records, references
+    );
+    warn_recompile(scripts);
+
+    let output_path = match output {
+        Some(o) => o,
+        None => input_path,
+    };
+    save_plugin(&plugin, output_path, dry_run)?;
+
+    Ok((records, references))
+}
+
+/// Only the script source is renamed, the compiled bytecode still holds the old ids
+fn warn_recompile(scripts: usize) {
+    if scripts > 0 {
+        log::warn!(
+            "Changed the source of {} scripts, recompile them in the construction set",
+            scripts
+        );
+    }
+}
+
+/// Rename the ids of records and the references to them, ids are keyed by lowercase old id.
+/// Returns the number of changed records, references and script texts
+fn rename_records(
+    plugin: &mut Plugin,
+    renames: &HashMap<String, String>,
+) -> io::Result<(usize, usize, usize)> {
+    let script_pattern = get_script_pattern(renames)?;
+    let mut records = 0;
+    let mut references = 0;
+    let mut scripts = 0;
+    for object in plugin.objects.iter_mut() {
+        let mut value = serde_json::to_value(&*object)?;
+        let mut changed = false;
+
+        if let Some(Value::String(id)) = value.get_mut("id") {
+            if let Some(new_id) = renames
+                .get(&id.to_lowercase())
+                .filter(|n| n.as_str() != id.as_str())
+            {
+                *id = new_id.clone();
+                records += 1;
+                changed = true;
+            }
+        }
+
+        // script text references ids by name
+        let script_field = match object {
+            TES3Object::Script(_) => Some("text"),
+            TES3Object::DialogueInfo(_) => Some("script_text"),
+            _ => None,
+        };
+        if let (Some(pattern), Some(Value::String(text))) =
+            (&script_pattern, script_field.and_then(|f| value.get_mut(f)))
+        {
+            let count = rename_in_script(text, pattern, renames);
+            if count > 0 {
+                references += count;
+                scripts += 1;
+                changed = true;
+            }
+        }
+
+        if let Value::Object(map) = &mut value {
+            for (key, field) in map.iter_mut() {
+                if key == "id" || TEXT_FIELDS.contains(&key.as_str()) {
+                    continue;
+                }
+                let count = rename_in_value(field, renames);
+                references += count;
+                changed |= count > 0;
+            }
+        }
+
+        if changed {
+            *object = serde_json::from_value(value)?;
+        }
+    }
+
+    Ok((records, references, scripts))
+}
+
+/// Read a json object or csv file of old and new ids, keyed by lowercase old id
+fn read_mapping(path: &Path) -> io::Result<HashMap<String, String>> {
+    let text = fs::read_to_string(path)?;
+    let pairs: Vec<(String, String)> = if is_extension(path, "json") {
+        let map: HashMap<String, String> = serde_json::from_str(&text)?;
+        map.into_iter().collect()
+    } else {
+        let mut pairs = vec![];
+        for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            let Some((old, new)) = line.split_once(',') else {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Expected old_id,new_id: {}", line),
+                ));
+            };
+            pairs.push((
+                old.trim().trim_matches('"').to_owned(),
+                new.trim().trim_matches('"').to_owned(),
+            ));
+        }
+        pairs
+    };
+
+    Ok(pairs
+        .into_iter()
+        .map(|(old, new)| (old.to_lowercase(), new))
+        .collect())
+}
+
+/// Replace all strings in a value that are renamed ids, returns the number of replacements
+fn rename_in_value(value: &mut Value, renames: &HashMap<String, String>) -> usize {
+    match value {
+        Value::String(s) => match renames.get(&s.to_lowercase()) {
+            Some(new_id) if new_id != s => {
+                *s = new_id.clone();
+                1
+            }
+            _ => 0,
+        },
+        Value::Array(values) => values.iter_mut().map(|v| rename_in_value(v, renames)).sum(),
+        Value::Object(map) => map
+            .iter_mut()
+            .filter(|(key, _)| !TEXT_FIELDS.contains(&key.as_str()))
+            .map(|(_, v)| rename_in_value(v, renames))
+            .sum(),
+        _ => 0,
+    }
+}
+
+/// Compile one case-insensitive pattern matching any renamed id as a whole word.
+/// Longer ids come first so an id is not matched by one of its prefixes
+fn get_script_pattern(renames: &HashMap<String, String>) -> io::Result<Option<Regex>> {
+    let is_word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+    let mut olds: Vec<&String> = renames.keys().filter(|o| !o.is_empty()).collect();
+    if olds.is_empty() {
+        return Ok(None);
+    }
+    olds.sort_by(|a, b| b.len().cmp(&a.len()).then(a.cmp(b)));
+
+    let alternatives: Vec<String> = olds
+        .into_iter()
+        .map(|old| {
+            // word boundaries only apply to ids starting or ending with a word character
+            let start = if is_word(old.chars().next()) {
+                r"\b"
+            } else {
+                ""
+            };
+            let end = if is_word(old.chars().last()) {
+                r"\b"
+            } else {
+                ""
+            };
+            format!("{}{}{}", start, regex::escape(old), end)
+        })
+        .collect();
+    match Regex::new(&format!("(?i){}", alternatives.join("|"))) {
+        Ok(r) => Ok(Some(r)),
+        Err(e) => Err(Error::new(ErrorKind::InvalidInput, e.to_string())),
+    }
+}
+
+/// Replace renamed ids in script text in a single pass, so chained or swapped renames
+/// are not applied twice. Returns the number of replacements
+fn rename_in_script(
+    text: &mut String,
+    pattern: &Regex,
+    renames: &HashMap<String, String>,
+) -> usize {
+    let mut count = 0;
+    let replaced = pattern.replace_all(text, |caps: &regex::Captures| {
+        let found = &caps[0];
+        match renames.get(&found.to_lowercase()) {
+            Some(new) if new != found => {
+                count += 1;
+                new.clone()
+            }
+            _ => found.to_owned(),
+        }
+    });
+    if count > 0 {
+        *text = replaced.into_owned();
+    }
+    count
+}
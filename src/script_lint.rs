@@ -0,0 +1,447 @@
+/// A structural problem found in a script
+#[derive(Debug, PartialEq)]
+pub struct LintWarning {
+    /// 1-based line number, the last line for unclosed blocks
+    pub line: usize,
+    pub message: String,
+}
+
+/// Check the structure of an MWScript text.
+/// This is not a full parser, only common errors are caught:
+/// - a missing or duplicate Begin or End, statements outside of Begin/End
+/// - unbalanced if/endif and while/endwhile blocks
+/// - set statements without "to" and conditions without an expression
+/// - statements that don't start with a known keyword or function.
+///   Functions of script extenders like MWSE are not known and reported too
+pub fn lint_script(text: &str) -> Vec<LintWarning> {
+    let mut warnings = vec![];
+    let mut warn = |line: usize, message: String| warnings.push(LintWarning { line, message });
+
+    let mut begun = false;
+    let mut ended = false;
+    // open if and while blocks with their line numbers
+    let mut blocks: Vec<(&str, usize)> = vec![];
+    let mut last_line = 0;
+
+    for (i, raw) in text.lines().enumerate() {
+        let line_number = i + 1;
+        last_line = line_number;
+        let line = strip_comment(raw).trim();
+        if line.is_empty() {
+            continue;
+        }
+        let lower = line.to_lowercase();
+        let mut tokens = lower.split(|c: char| c.is_whitespace() || matches!(c, ',' | '(' | ')'));
+        let keyword = tokens.next().unwrap_or_default();
+
+        match keyword {
+            "begin" => {
+                if begun {
+                    warn(line_number, String::from("Duplicate Begin"));
+                } else if tokens.all(|t| t.is_empty()) {
+                    warn(line_number, String::from("Begin without a script name"));
+                }
+                begun = true;
+            }
+            "end" => {
+                if !begun {
+                    warn(line_number, String::from("End without Begin"));
+                } else if ended {
+                    warn(line_number, String::from("Duplicate End"));
+                }
+                ended = true;
+            }
+            _ if !begun || ended => {
+                // declarations may come before Begin
+                if !matches!(keyword, "short" | "long" | "float") {
+                    warn(
+                        line_number,
+                        format!("Statement outside of Begin/End: {}", line),
+                    );
+                }
+            }
+            "if" | "while" => {
+                if tokens.all(|t| t.is_empty()) {
+                    warn(line_number, format!("{} without a condition", keyword));
+                }
+                blocks.push((if keyword == "if" { "if" } else { "while" }, line_number));
+            }
+            "elseif" | "else" => {
+                if !matches!(blocks.last(), Some(("if", _))) {
+                    warn(line_number, format!("{} without if", keyword));
+                } else if keyword == "elseif" && tokens.all(|t| t.is_empty()) {
+                    warn(line_number, String::from("elseif without a condition"));
+                }
+            }
+            "endif" | "endwhile" => {
+                let expected = if keyword == "endif" { "if" } else { "while" };
+                match blocks.last() {
+                    Some((open, _)) if *open == expected => {
+                        blocks.pop();
+                    }
+                    _ => warn(line_number, format!("{} without {}", keyword, expected)),
+                }
+            }
+            "set" => {
+                if !tokens.any(|t| t == "to") {
+                    warn(line_number, String::from("set without to"));
+                }
+            }
+            "return" | "short" | "long" | "float" => {}
+            _ => {
+                // functions may be called on a reference: "object id"->function
+                let function = lower
+                    .split_once("->")
+                    .and_then(|(_, rest)| rest.split_whitespace().next())
+                    .unwrap_or(keyword);
+                if !is_known_function(function) {
+                    warn(
+                        line_number,
+                        format!("Unknown statement keyword: {}", function),
+                    );
+                }
+            }
+        }
+    }
+
+    for (open, line_number) in blocks {
+        warn(line_number, format!("{} is never closed", open));
+    }
+    if !begun {
+        warn(last_line.max(1), String::from("Missing Begin"));
+    } else if !ended {
+        warn(last_line, String::from("Missing End"));
+    }
+
+    warnings
+}
+
+/// Remove a ; comment from a line, semicolons in quoted strings are kept
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' if !quoted => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// Attributes, skills and AI settings with Get, Set and Mod functions
+const STATS: &[&str] = &[
+    "strength",
+    "intelligence",
+    "willpower",
+    "agility",
+    "speed",
+    "endurance",
+    "personality",
+    "luck",
+    "health",
+    "magicka",
+    "fatigue",
+    "block",
+    "armorer",
+    "mediumarmor",
+    "heavyarmor",
+    "bluntweapon",
+    "longblade",
+    "axe",
+    "spear",
+    "athletics",
+    "enchant",
+    "destruction",
+    "alteration",
+    "illusion",
+    "conjuration",
+    "mysticism",
+    "restoration",
+    "alchemy",
+    "unarmored",
+    "security",
+    "sneak",
+    "acrobatics",
+    "lightarmor",
+    "shortblade",
+    "marksman",
+    "mercantile",
+    "speechcraft",
+    "handtohand",
+    "resistblight",
+    "resistcorprus",
+    "resistdisease",
+    "resistfire",
+    "resistfrost",
+    "resistmagicka",
+    "resistnormalweapons",
+    "resistparalysis",
+    "resistpoison",
+    "resistshock",
+    "attackbonus",
+    "armorbonus",
+    "blindness",
+    "castpenalty",
+    "chameleon",
+    "defendbonus",
+    "flying",
+    "invisible",
+    "paralysis",
+    "silence",
+    "superjump",
+    "swimspeed",
+    "waterbreathing",
+    "waterwalking",
+    "reputation",
+    "disposition",
+    "level",
+    "fight",
+    "flee",
+    "alarm",
+    "hello",
+    "scale",
+    "angle",
+    "pos",
+    "waterlevel",
+    "pccrimelevel",
+    "pcvisionbonus",
+    "pcfacrep",
+    "factionreaction",
+    "journalindex",
+];
+
+/// Functions of Morrowind, Tribunal and Bloodmoon that are not Get, Set or Mod stats
+const FUNCTIONS: &[&str] = &[
+    "activate",
+    "additem",
+    "addsoulgem",
+    "addspell",
+    "addtolevcreature",
+    "addtolevitem",
+    "addtopic",
+    "aiactivate",
+    "aiescort",
+    "aiescortcell",
+    "aifollow",
+    "aifollowcell",
+    "aitravel",
+    "aiwander",
+    "becomewerewolf",
+    "cast",
+    "cellchanged",
+    "centeroncell",
+    "centeronexterior",
+    "changeweather",
+    "choice",
+    "clearforcejump",
+    "clearforcemovejump",
+    "clearforcerun",
+    "clearforcesneak",
+    "clearinfoactor",
+    "disable",
+    "disablelevitation",
+    "disableplayercontrols",
+    "disableplayerfighting",
+    "disableplayerjumping",
+    "disableplayerlooking",
+    "disableplayermagic",
+    "disableplayerviewswitch",
+    "disableteleporting",
+    "disablevanitymode",
+    "dontsaveobject",
+    "drop",
+    "dropsoulgem",
+    "enable",
+    "enablebirthmenu",
+    "enableclassmenu",
+    "enableinventorymenu",
+    "enablelevitation",
+    "enablemagicmenu",
+    "enablemapmenu",
+    "enablenamemenu",
+    "enableplayercontrols",
+    "enableplayerfighting",
+    "enableplayerjumping",
+    "enableplayerlooking",
+    "enableplayermagic",
+    "enableplayerviewswitch",
+    "enableracemenu",
+    "enablerest",
+    "enablestatreviewmenu",
+    "enablestatsmenu",
+    "enableteleporting",
+    "enablevanitymode",
+    "equip",
+    "explodespell",
+    "face",
+    "fadein",
+    "fadeout",
+    "fadeto",
+    "fall",
+    "forcegreeting",
+    "forcejump",
+    "forcemovejump",
+    "forcerun",
+    "forcesneak",
+    "getaipackagedone",
+    "getarmortype",
+    "getattacked",
+    "getblightdisease",
+    "getbuttonpressed",
+    "getcollidingactor",
+    "getcollidingpc",
+    "getcommondisease",
+    "getcurrentaipackage",
+    "getcurrentweather",
+    "getdeadcount",
+    "getdetected",
+    "getdisabled",
+    "getdistance",
+    "geteffect",
+    "getfatiguegetratio",
+    "getforcejump",
+    "getforcemovejump",
+    "getforcerun",
+    "getforcesneak",
+    "gethealthgetratio",
+    "getinterior",
+    "getitemcount",
+    "getlineofsight",
+    "getlocked",
+    "getlos",
+    "getmagickagetratio",
+    "getpccell",
+    "getpcinjail",
+    "getpcjumping",
+    "getpcrank",
+    "getpcrunning",
+    "getpcsleep",
+    "getpcsneaking",
+    "getpctraveling",
+    "getplayercontrolsdisabled",
+    "getplayerfightingdisabled",
+    "getplayerjumpingdisabled",
+    "getplayerlookingdisabled",
+    "getplayermagicdisabled",
+    "getrace",
+    "getscriptrunning",
+    "getsecondspassed",
+    "getsoundplaying",
+    "getspell",
+    "getspelleffects",
+    "getspellreadied",
+    "getsquareroot",
+    "getstandingactor",
+    "getstandingpc",
+    "getstartingangle",
+    "getstartingpos",
+    "gettarget",
+    "getweapondrawn",
+    "getweapontype",
+    "getwerewolfkills",
+    "getwindspeed",
+    "goodbye",
+    "gotojail",
+    "hasitemequipped",
+    "hassoulgem",
+    "hitattemptonme",
+    "hitonme",
+    "hurtcollidingactor",
+    "hurtstandingactor",
+    "iswerewolf",
+    "journal",
+    "lock",
+    "loopgroup",
+    "lowerrank",
+    "menumode",
+    "messagebox",
+    "modcurrentfatigue",
+    "modcurrenthealth",
+    "modcurrentmagicka",
+    "modregion",
+    "move",
+    "moveworld",
+    "onactivate",
+    "ondeath",
+    "onknockout",
+    "onmurder",
+    "onpcadd",
+    "onpcdrop",
+    "onpcequip",
+    "onpchitme",
+    "onpcrepair",
+    "onpcsoulgemuse",
+    "onrepair",
+    "payfine",
+    "payfinethief",
+    "pcclearexpelled",
+    "pcexpell",
+    "pcexpelled",
+    "pcforce1stperson",
+    "pcforce3rdperson",
+    "pcget3rdperson",
+    "pcjoinfaction",
+    "pclowerrank",
+    "pcraiserank",
+    "placeatme",
+    "placeatpc",
+    "placeitem",
+    "placeitemcell",
+    "playbink",
+    "playgroup",
+    "playloopsound3d",
+    "playloopsound3dvp",
+    "playsound",
+    "playsound3d",
+    "playsound3dvp",
+    "playsoundvp",
+    "position",
+    "positioncell",
+    "raiserank",
+    "random",
+    "removeeffects",
+    "removefromlevcreature",
+    "removefromlevitem",
+    "removeitem",
+    "removesoulgem",
+    "removespell",
+    "removespelleffects",
+    "repairedonme",
+    "resurrect",
+    "rotate",
+    "rotateworld",
+    "samefaction",
+    "say",
+    "saydone",
+    "setatstart",
+    "setdelete",
+    "setwerewolfacrobatics",
+    "showmap",
+    "showrestmenu",
+    "skipanim",
+    "startcombat",
+    "startscript",
+    "stopcombat",
+    "stopscript",
+    "stopsound",
+    "streammusic",
+    "turnmoonred",
+    "turnmoonwhite",
+    "undowerewolf",
+    "unlock",
+    "wakeuppc",
+    "xbox",
+];
+
+/// Whether a lowercase statement keyword is a known MWScript function
+fn is_known_function(function: &str) -> bool {
+    if FUNCTIONS.contains(&function) {
+        return true;
+    }
+    ["get", "set", "mod"].iter().any(|prefix| {
+        function
+            .strip_prefix(prefix)
+            .is_some_and(|stat| STATS.contains(&stat))
+    })
+}
@@ -0,0 +1,128 @@
+use std::{
+    collections::HashMap,
+    env, fs,
+    io::{self, Error, ErrorKind},
+    path::{Path, PathBuf},
+};
+
+use tes3::esp::TES3Object;
+
+use crate::{is_extension, parse_plugin, plugin_file_exists, save_plugin, write_script, EEncoding};
+
+/// Write the text of all scripts in a plugin to <id>.mwscript files in the output folder
+pub fn extract_scripts(input: &Option<PathBuf>, output: &Option<PathBuf>) -> io::Result<usize> {
+    let input_path: &PathBuf;
+    // check no input
+    if let Some(i) = input {
+        input_path = i;
+    } else {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "No input path specified.",
+        ));
+    }
+    if !plugin_file_exists(input_path) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Input path does not exist",
+        ));
+    }
+
+    // check output path, default is cwd
+    let mut out_dir_path = env::current_dir()?;
+    if let Some(p) = output {
+        p.clone_into(&mut out_dir_path);
+    }
+
+    let plugin = match parse_plugin(input_path) {
+        Ok(p) => p,
+        Err(_) => return Err(Error::new(ErrorKind::Other, "Plugin parsing failed.")),
+    };
+
+    let mut count = 0;
+    for object in &plugin.objects {
+        if let TES3Object::Script(script) = object {
+            write_script(script, &out_dir_path, &EEncoding::Utf8)?;
+            count += 1;
+        }
+    }
+    println!("Extracted {} scripts to {}", count, out_dir_path.display());
+
+    Ok(count)
+}
+
+/// Replace the text of scripts in a plugin with the contents of <id>.mwscript files in a folder.
+/// File names are matched to script ids case-insensitively. The plugin is saved in place by default
+pub fn inject_scripts(
+    input: &Option<PathBuf>,
+    scripts_dir: &Path,
+    output: &Option<PathBuf>,
+    dry_run: bool,
+) -> io::Result<usize> {
+    let input_path: &PathBuf;
+    // check no input
+    if let Some(i) = input {
+        input_path = i;
+    } else {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "No input path specified.",
+        ));
+    }
+    if !input_path.is_file() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Input path does not exist",
+        ));
+    }
+    if !scripts_dir.is_dir() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Scripts folder does not exist",
+        ));
+    }
+
+    // script texts by lowercase id
+    let mut texts: HashMap<String, String> = HashMap::new();
+    for entry in fs::read_dir(scripts_dir)?.flatten() {
+        let path = entry.path();
+        if !path.is_file() || !is_extension(&path, "mwscript") {
+            continue;
+        }
+        if let Some(stem) = path.file_stem() {
+            texts.insert(
+                stem.to_string_lossy().to_lowercase(),
+                fs::read_to_string(&path)?,
+            );
+        }
+    }
+
+    let mut plugin = match parse_plugin(input_path) {
+        Ok(p) => p,
+        Err(_) => return Err(Error::new(ErrorKind::Other, "Plugin parsing failed.")),
+    };
+
+    let mut count = 0;
+    for object in plugin.objects.iter_mut() {
+        if let TES3Object::Script(script) = object {
+            if let Some(text) = texts.remove(&script.id.to_lowercase()) {
+                if script.text != text {
+                    script.text = text;
+                    count += 1;
+                }
+            }
+        }
+    }
+    for id in texts.keys() {
+        progress!("No script found for {}.mwscript", id);
+    }
+    println!("Updated {} scripts", count);
+
+    let output_path = match output {
+        Some(o) => o,
+        None => input_path,
+    };
+    save_plugin(&plugin, output_path, dry_run)?;
+
+    Ok(count)
+}
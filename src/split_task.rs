@@ -0,0 +1,90 @@
+use std::{
+    collections::BTreeMap,
+    io::{self, Error, ErrorKind},
+    path::{Path, PathBuf},
+};
+
+use tes3::esp::{Plugin, TES3Object, TypeInfo};
+
+use crate::{parse_plugin, plugin_file_exists, save_plugin};
+
+/// Split a plugin into one plugin per record tag named <stem>_<TAG>.esp.
+/// If tags are given only those are split out and the remaining records are written to <stem>_rest.esp.
+/// The header is copied into every plugin so each loads standalone
+pub fn split(
+    input: &Option<PathBuf>,
+    output: &Option<PathBuf>,
+    by: &[String],
+    dry_run: bool,
+) -> io::Result<usize> {
+    let input_path: &PathBuf;
+    // check no input
+    if let Some(i) = input {
+        input_path = i;
+    } else {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "No input path specified.",
+        ));
+    }
+    if !plugin_file_exists(input_path) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Input path does not exist",
+        ));
+    }
+
+    // output folder, defaults to the folder of the input
+    let out_dir_path = match output {
+        Some(o) => o.to_owned(),
+        None => input_path.parent().unwrap_or(Path::new("")).to_path_buf(),
+    };
+
+    let plugin = match parse_plugin(input_path) {
+        Ok(p) => p,
+        Err(_) => return Err(Error::new(ErrorKind::Other, "Plugin parsing failed.")),
+    };
+    let Some(header) = plugin
+        .objects
+        .iter()
+        .find(|o| matches!(o, TES3Object::Header(_)))
+    else {
+        return Err(Error::new(ErrorKind::InvalidData, "Plugin has no header"));
+    };
+
+    // group records by output name, in record order
+    let by: Vec<String> = by.iter().map(|t| t.to_uppercase()).collect();
+    let mut groups: BTreeMap<String, Vec<TES3Object>> = BTreeMap::new();
+    for object in &plugin.objects {
+        if matches!(object, TES3Object::Header(_)) {
+            continue;
+        }
+        let tag = object.tag_str();
+        let group = if by.is_empty() || by.iter().any(|t| t == tag) {
+            tag.to_owned()
+        } else {
+            String::from("rest")
+        };
+        groups.entry(group).or_default().push(object.clone());
+    }
+
+    let stem = input_path.file_stem().unwrap_or_default().to_string_lossy();
+    for (group, objects) in &groups {
+        let mut split_plugin = Plugin::new();
+        split_plugin.objects.push(header.clone());
+        split_plugin.objects.extend(objects.iter().cloned());
+
+        let output_path = out_dir_path.join(format!("{}_{}.esp", stem, group));
+        progress!("{} records in {}", objects.len(), output_path.display());
+        save_plugin(&split_plugin, &output_path, dry_run)?;
+    }
+    let records: usize = groups.values().map(Vec::len).sum();
+    println!(
+        "Split {} records into {} plugins in {}",
+        records,
+        groups.len(),
+        out_dir_path.display()
+    );
+
+    Ok(groups.len())
+}
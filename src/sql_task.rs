@@ -1,17 +1,50 @@
 use crate::get_all_tags;
 use fnv_rs::{Fnv64, FnvHasher};
-use rusqlite::{params, Connection, Result};
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use rayon::prelude::*;
+use rusqlite::{params, types::ValueRef, Connection, Result};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    io::{self, Error, ErrorKind},
+    path::{Path, PathBuf},
+    time::Instant,
+};
 use tes3::esp::traits::TableSchema;
-use tes3::esp::EditorId;
 use tes3::esp::SqlInfo;
-//use sha1::{Digest, Sha1};
-use std::{collections::HashMap, path::PathBuf};
+use tes3::esp::{CellFlags, EditorId, TES3Object, TypeInfo};
 
 use crate::as_json;
 use crate::as_option;
 use crate::create_from_tag;
+use crate::csv_task::to_csv_line;
+use crate::file_crc;
+use crate::filter_plugins_by_glob;
+use crate::get_plugins_sorted;
+use crate::no_plugins_error;
 use crate::parse_plugin;
 
+const PLUGINS_TABLE: &str = "CREATE TABLE IF NOT EXISTS plugins (
+    id   TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    crc INTEGER NOT NULL,
+    load_order INTEGER NOT NULL
+)";
+
+/// Records edited by more than one of the inserted plugins.
+/// mods is the comma separated list of plugin names in load order,
+/// identical is 1 if all plugins write the same record
+const DUPLICATES_TABLE: &str = "CREATE TABLE _duplicates (
+    tag TEXT NOT NULL,
+    id TEXT NOT NULL,
+    mods TEXT NOT NULL,
+    identical INTEGER NOT NULL,
+    PRIMARY KEY (tag, id)
+)";
+
+/// The serialized records of each plugin by tag and key
+type RecordVersions = BTreeMap<(String, String), Vec<(String, String)>>;
+
 struct PluginModel {
     id: String,
     name: String,
@@ -19,76 +52,535 @@ struct PluginModel {
     load_order: u32,
 }
 
-pub fn sql_task(input: &Option<PathBuf>, output: &Option<PathBuf>) -> Result<()> {
+/// Options for building the sql database
+#[derive(Default)]
+pub struct SqlOptions {
+    /// Run a query against the database after it is built and print the result
+    pub query: Option<String>,
+    /// Print the query result as csv instead of a table
+    pub csv: bool,
+    /// Config file to read the load order of a folder from (openmw.cfg or Morrowind.ini)
+    pub cfg: Option<PathBuf>,
+    /// Include OpenMW script lists (.omwscripts) in folder input
+    pub omw: bool,
+    /// Show progress bars on stderr
+    pub progress: bool,
+    /// Only create and populate tables of these tags
+    pub include: Vec<String>,
+    /// Don't create and populate tables of these tags
+    pub exclude: Vec<String>,
+    /// Delete an existing database at the output path
+    pub overwrite: bool,
+    /// Update an existing database: the rows of the input plugins are deleted and re-inserted,
+    /// including rows in tables excluded from this run.
+    /// Records are re-inserted after their plugin row, so foreign keys to plugins still hold.
+    /// Rows of earlier plugins that an input plugin overrode are not restored
+    pub append: bool,
+    /// Don't create indexes on the mod and foreign key columns
+    pub no_index: bool,
+    /// Write the schema to this file and exit without building the database
+    pub schema_only: Option<PathBuf>,
+    /// Report records that are edited by more than one plugin in a _duplicates table
+    pub dedup_report: bool,
+    /// Fail if the foreign key check finds violations
+    pub strict: bool,
+    /// Use a rollback journal instead of write-ahead logging, so no sidecar files are left
+    pub readonly: bool,
+    /// Only load plugins of a folder whose file name matches this glob pattern
+    pub glob: Option<String>,
+}
+
+impl SqlOptions {
+    /// Check if the table of a tag is created and populated
+    fn is_selected(&self, tag: &str) -> bool {
+        let contains = |tags: &[String]| tags.iter().any(|t| t.eq_ignore_ascii_case(tag));
+        !contains(&self.exclude) && (self.include.is_empty() || contains(&self.include))
+    }
+}
+
+/// Build an sqlite database from a plugin or all plugins in a folder
+pub fn sql_task(
+    input: &Option<PathBuf>,
+    output: &Option<PathBuf>,
+    options: &SqlOptions,
+) -> io::Result<()> {
+    if let Some(path) = &options.schema_only {
+        let schema = get_schema_sql(&get_schemas(options)).join(";\n\n");
+        fs::write(path, format!("{};\n", schema))?;
+        println!("Schema written to {}", path.display());
+        return Ok(());
+    }
+
+    // a folder is loaded in load order, a single plugin gets load order 0
+    let mut plugin_paths = vec![];
+    if let Some(input) = input {
+        if input.is_dir() {
+            plugin_paths = get_plugins_sorted(input, options.omw, &options.cfg)?;
+            plugin_paths = filter_plugins_by_glob(plugin_paths, &options.glob)?;
+        } else {
+            plugin_paths.push(input.to_owned());
+        }
+    }
+    // never build an empty database
+    if plugin_paths.is_empty() {
+        let folder = input.as_deref().unwrap_or(Path::new("."));
+        return Err(no_plugins_error(folder));
+    }
+
     if let Some(output) = output {
-        // create esp db
-        let db = Connection::open(output)?;
+        // never delete an existing database without confirmation
+        if output.exists() && !options.append {
+            if !options.overwrite {
+                return Err(Error::new(
+                    ErrorKind::AlreadyExists,
+                    format!(
+                        "Output database already exists, use --overwrite to replace it: {}",
+                        output.display()
+                    ),
+                ));
+            }
+            fs::remove_file(output)?;
+        }
+
+        let (inserted, violations) = match build_db(&plugin_paths, output, options) {
+            Ok(v) => v,
+            Err(e) => return Err(Error::new(ErrorKind::Other, e)),
+        };
+        if options.strict && violations > 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("{} foreign key violations", violations),
+            ));
+        }
+        // keep stdout clean for the query results
+        if options.query.is_none() {
+            println!(
+                "Wrote {} plugins to {} with {} foreign key violations",
+                inserted,
+                output.display(),
+                violations
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Create the tables and insert all records, plugins that fail to parse are skipped.
+/// Returns the number of inserted plugins and of foreign key violations
+fn build_db(
+    plugin_paths: &[PathBuf],
+    output: &Path,
+    options: &SqlOptions,
+) -> Result<(usize, usize)> {
+    // create esp db
+    let mut db = Connection::open(output)?;
+    if options.readonly {
+        db.pragma_update(None, "journal_mode", "DELETE")?;
+    } else {
+        db.pragma_update(None, "journal_mode", "WAL")?;
+        db.pragma_update(None, "synchronous", "NORMAL")?;
+    }
 
-        // create plugins db
+    let schemas = get_schemas(options);
+    let start = Instant::now();
+    create_tables(&db, &schemas)?;
+    log::info!(
+        "Created {} tables in {:.1}s",
+        schemas.len(),
+        start.elapsed().as_secs_f32()
+    );
+
+    // debug todo
+    for tag in get_all_tags()
+        .into_iter()
+        .filter(|t| options.is_selected(t))
+    {
+        match create_from_tag(&tag) {
+            Some(instance) => log::debug!("{}", instance.table_insert()),
+            None => log::warn!("Skipping unknown record tag: {}", tag),
+        }
+    }
+
+    // progress bars are drawn to stderr
+    let bars = MultiProgress::new();
+    let parse_bar = bars.add(new_progress_bar(plugin_paths.len(), options.progress));
+    parse_bar.set_message("Parsing plugins");
+
+    // plugins are parsed in parallel, the results stay in load order
+    let start = Instant::now();
+    let parsed: Vec<_> = plugin_paths
+        .par_iter()
+        .map(|input| {
+            let plugin_start = Instant::now();
+            let plugin = parse_plugin(input);
+            log::debug!(
+                "Parsed {} in {:.2}s",
+                input.display(),
+                plugin_start.elapsed().as_secs_f32()
+            );
+            let crc = file_crc(input);
+            parse_bar.inc(1);
+            (input, plugin, crc)
+        })
+        .collect();
+
+    let mut plugins = vec![];
+    for (load_order, (input, plugin, crc)) in parsed.into_iter().enumerate() {
+        // populate db
+        let plugin = match plugin {
+            Ok(plugin) => plugin,
+            Err(e) => {
+                log::warn!(
+                    "Skipping plugin, parsing failed: {}: {}",
+                    input.display(),
+                    e
+                );
+                continue;
+            }
+        };
+        let filename = input
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let hash = Fnv64::hash(filename.as_bytes()).as_hex();
+        let crc = match crc {
+            Ok(c) => c,
+            Err(e) => {
+                log::warn!("Could not compute crc of {}: {}", filename, e);
+                0
+            }
+        };
+        let mut load_order = load_order as u32;
+        if options.append {
+            load_order = delete_plugin_rows(&db, &hash, &schemas)?;
+        }
+        let plugin_model = PluginModel {
+            id: hash.to_owned(),
+            name: filename.to_string(),
+            crc,
+            load_order,
+        };
+        // add plugin to db
         db.execute(
-            "CREATE TABLE plugins (
-            id   TEXT PRIMARY KEY,
-            name TEXT NOT NULL,
-            crc INTEGER NOT NULL,
-            load_order INTEGER NOT NULL
-        )",
-            (), // empty list of parameters.
+            "INSERT OR REPLACE INTO plugins (id, name, crc, load_order) VALUES (?1, ?2, ?3, ?4)",
+            params![
+                plugin_model.id,
+                plugin_model.name,
+                plugin_model.crc,
+                plugin_model.load_order
+            ],
         )?;
 
-        let schemas = get_schemas();
-        create_tables(&db, &schemas)?;
+        plugins.push((hash, filename, plugin));
+    }
+    parse_bar.finish_and_clear();
+    log::info!(
+        "Parsed {} plugins in {:.1}s",
+        plugins.len(),
+        start.elapsed().as_secs_f32()
+    );
 
-        // debug todo
-        for tag in get_all_tags() {
-            if let Some(instance) = create_from_tag(&tag) {
-                let txt = instance.table_insert();
-                println!("{}", txt);
+    let insert_bar = bars.add(new_progress_bar(plugins.len(), options.progress));
+    insert_bar.set_message("Inserting plugins");
+    let start = Instant::now();
+    let mut versions = RecordVersions::new();
+    for (hash, filename, plugin) in &plugins {
+        let plugin_start = Instant::now();
+        let record_bar = bars.add(new_progress_bar(plugin.objects.len(), options.progress));
+        record_bar.set_message("Inserting records");
+
+        // the records of a plugin are inserted in one transaction, a failed insert rolls it back
+        let tx = db.transaction()?;
+        let mut empty_ids: HashMap<&str, usize> = HashMap::new();
+        for (index, record) in plugin.objects.iter().enumerate() {
+            record_bar.inc(1);
+            if !options.is_selected(record.tag_str()) {
+                continue;
+            }
+            if record.editor_id().is_empty() {
+                *empty_ids.entry(record.tag_str()).or_insert(0) += 1;
             }
-        }
 
-        let mut plugins = HashMap::new();
-
-        if let Some(input) = input {
-            // populate db
-            if let Ok(plugin) = parse_plugin(input) {
-                let filename = input.file_name().unwrap().to_str().unwrap();
-                let hash = Fnv64::hash(filename.as_bytes()).as_hex();
-                //let mut hasher = Sha1::new();
-                let plugin_model = PluginModel {
-                    id: hash.to_owned(),
-                    name: filename.to_string(),
-                    crc: 0,        // todo
-                    load_order: 0, // todo
-                };
-                // add plugin to db
-                db.execute(
-                    "INSERT INTO plugins (id, name, crc, load_order) VALUES (?1, ?2, ?3, ?4)",
-                    params![
-                        plugin_model.id,
-                        plugin_model.name,
-                        plugin_model.crc,
-                        plugin_model.load_order
-                    ],
-                )?;
-
-                plugins.insert(hash, plugin);
+            let key = record_key(record, index);
+            if let Err(e) = insert_into_db(&tx, hash, &key, record) {
+                log::error!("Could not insert into db {}", key);
+                return Err(e);
+            }
+            if options.dedup_report && has_stable_key(record) {
+                versions
+                    .entry((record.tag_str().to_owned(), key.to_lowercase()))
+                    .or_default()
+                    .push((
+                        filename.to_owned(),
+                        serde_json::to_string(record).unwrap_or_default(),
+                    ));
             }
         }
 
-        for (hash, plugin) in &plugins {
-            for record in &plugin.objects {
-                insert_into_db(&db, hash, record);
-            }
+        tx.commit()?;
+
+        for (tag, count) in empty_ids {
+            log::info!(
+                "{} {} records with empty ids, using surrogate keys",
+                count,
+                tag
+            );
         }
+        record_bar.finish_and_clear();
+        insert_bar.inc(1);
+        log::debug!(
+            "Inserted {} in {:.2}s",
+            filename,
+            plugin_start.elapsed().as_secs_f32()
+        );
+    }
+    insert_bar.finish_and_clear();
+    log::info!("Inserted records in {:.1}s", start.elapsed().as_secs_f32());
+
+    if !options.no_index {
+        let start = Instant::now();
+        create_indexes(&db, &schemas)?;
+        log::info!("Created indexes in {:.1}s", start.elapsed().as_secs_f32());
+    }
+
+    if options.dedup_report {
+        let start = Instant::now();
+        let (count, identical) = write_duplicates(&db, &versions)?;
+        log::info!(
+            "Wrote the duplicates report in {:.1}s",
+            start.elapsed().as_secs_f32()
+        );
+        log::info!(
+            "{} records are edited by more than one plugin, {} of them identically",
+            count,
+            identical
+        );
+    }
+
+    // foreign keys are not enforced while inserting, report broken references instead
+    let violations = check_foreign_keys(&db)?;
+    if violations > 0 {
+        log::warn!("{} foreign key violations", violations);
+    }
+
+    // the database is shipped as one file with statistics for the query planner
+    let start = Instant::now();
+    db.execute_batch("PRAGMA wal_checkpoint(TRUNCATE); ANALYZE; PRAGMA optimize;")?;
+    log::info!("Optimized in {:.1}s", start.elapsed().as_secs_f32());
+
+    if let Some(query) = &options.query {
+        run_query(&db, query, options.csv)?;
+    }
+
+    Ok((plugins.len(), violations))
+}
+
+/// Log every row that references a missing row, returns the number of violations
+fn check_foreign_keys(db: &Connection) -> Result<usize> {
+    let mut stmt = db.prepare("PRAGMA foreign_key_check")?;
+    let mut rows = stmt.query([])?;
+    let mut count = 0;
+    while let Some(row) = rows.next()? {
+        let table: String = row.get(0)?;
+        let rowid: Option<i64> = row.get(1)?;
+        let parent: String = row.get(2)?;
+        log::warn!(
+            "Foreign key violation in {} row {}: missing row in {}",
+            table,
+            rowid.map(|r| r.to_string()).unwrap_or_default(),
+            parent
+        );
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Write the records with more than one version to the _duplicates table.
+/// Returns the number of duplicated records and how many of them are identical in all plugins
+fn write_duplicates(db: &Connection, versions: &RecordVersions) -> Result<(usize, usize)> {
+    db.execute("DROP TABLE IF EXISTS _duplicates", ())?;
+    db.execute(DUPLICATES_TABLE, ())?;
+
+    let mut count = 0;
+    let mut identical_count = 0;
+    for ((tag, id), records) in versions.iter().filter(|(_, r)| r.len() > 1) {
+        let mods: Vec<&str> = records.iter().map(|(name, _)| name.as_str()).collect();
+        let identical = records.iter().all(|(_, text)| *text == records[0].1);
+        db.execute(
+            "INSERT INTO _duplicates (tag, id, mods, identical) VALUES (?1, ?2, ?3, ?4)",
+            params![tag, id, mods.join(","), identical],
+        )?;
+        count += 1;
+        if identical {
+            identical_count += 1;
+        }
+    }
+
+    Ok((count, identical_count))
+}
+
+/// Check if the key of a record identifies the same record in other plugins.
+/// Exterior cells are keyed by their grid, surrogate keys made from the index in the plugin
+/// are not stable
+fn has_stable_key(record: &TES3Object) -> bool {
+    match record {
+        TES3Object::Cell(s) if s.editor_id().is_empty() => {
+            !s.data.flags.contains(CellFlags::IS_INTERIOR)
+        }
+        _ => !record.editor_id().is_empty(),
+    }
+}
+
+/// Delete the plugin row and all record rows of a plugin before it is re-inserted.
+/// Returns the load order of the deleted plugin, or the next free load order for a new one
+fn delete_plugin_rows(db: &Connection, hash: &str, schemas: &[TableSchema]) -> Result<u32> {
+    let load_order: u32 = db.query_row(
+        "SELECT COALESCE(
+            (SELECT load_order FROM plugins WHERE id = ?1),
+            (SELECT COALESCE(MAX(load_order) + 1, 0) FROM plugins)
+        )",
+        params![hash],
+        |row| row.get(0),
+    )?;
+
+    // records reference the plugin row, delete them first.
+    // tables excluded from this run may still hold rows of an earlier build
+    let mut tables: Vec<String> = schemas.iter().map(|s| s.name.to_owned()).collect();
+    let mut stmt = db.prepare(
+        "SELECT m.name FROM sqlite_master m WHERE m.type = 'table' AND m.name != 'plugins'
+        AND EXISTS (SELECT 1 FROM pragma_table_info(m.name) WHERE name = 'mod')",
+    )?;
+    for name in stmt.query_map([], |row| row.get::<_, String>(0))? {
+        let name = name?;
+        if !tables.contains(&name) {
+            tables.push(name);
+        }
+    }
+    for table in tables {
+        let sql = format!("DELETE FROM {} WHERE mod = ?1", table);
+        db.execute(&sql, params![hash])?;
+    }
+    db.execute("DELETE FROM plugins WHERE id = ?1", params![hash])?;
+
+    Ok(load_order)
+}
+
+/// Create a progress bar, hidden unless progress is enabled
+fn new_progress_bar(len: usize, progress: bool) -> ProgressBar {
+    if !progress {
+        return ProgressBar::hidden();
+    }
+    let bar = ProgressBar::new(len as u64);
+    if let Ok(style) = ProgressStyle::with_template("{msg} [{bar:40}] {pos}/{len}") {
+        bar.set_style(style);
+    }
+    bar
+}
+
+/// Execute a query and print the result rows as a table or as csv
+fn run_query(db: &Connection, query: &str, csv: bool) -> Result<()> {
+    let mut stmt = db.prepare(query)?;
+    let columns: Vec<String> = stmt.column_names().iter().map(|c| c.to_string()).collect();
+
+    let mut rows = vec![];
+    let mut result = stmt.query([])?;
+    while let Some(row) = result.next()? {
+        let mut values = vec![];
+        for i in 0..columns.len() {
+            values.push(value_to_string(row.get_ref(i)?));
+        }
+        rows.push(values);
+    }
+
+    if csv {
+        println!("{}", to_csv_line(&columns));
+        for row in &rows {
+            println!("{}", to_csv_line(row));
+        }
+    } else {
+        print_table(&columns, &rows);
     }
 
     Ok(())
 }
 
+fn value_to_string(value: ValueRef) -> String {
+    match value {
+        ValueRef::Null => "NULL".to_string(),
+        ValueRef::Integer(i) => i.to_string(),
+        ValueRef::Real(f) => f.to_string(),
+        ValueRef::Text(t) => String::from_utf8_lossy(t).to_string(),
+        ValueRef::Blob(b) => format!("<{} bytes>", b.len()),
+    }
+}
+
+/// Print rows as a table with padded columns
+fn print_table(columns: &[String], rows: &[Vec<String>]) {
+    let mut widths: Vec<usize> = columns.iter().map(|c| c.len()).collect();
+    for row in rows {
+        for (i, value) in row.iter().enumerate() {
+            widths[i] = widths[i].max(value.len());
+        }
+    }
+
+    let format_row = |values: &[String]| {
+        values
+            .iter()
+            .enumerate()
+            .map(|(i, v)| format!("{:<width$}", v, width = widths[i]))
+            .collect::<Vec<_>>()
+            .join(" | ")
+    };
+
+    println!("{}", format_row(columns));
+    println!(
+        "{}",
+        widths
+            .iter()
+            .map(|w| "-".repeat(*w))
+            .collect::<Vec<_>>()
+            .join("-+-")
+    );
+    for row in rows {
+        println!("{}", format_row(row));
+    }
+    println!("({} rows)", rows.len());
+}
+
 fn create_tables(conn: &Connection, schemas: &[TableSchema]) -> Result<()> {
+    for sql in get_schema_sql(schemas) {
+        log::debug!("{}", sql);
+
+        conn.execute(&sql, [])?;
+    }
+    Ok(())
+}
+
+/// Get the create statements of the plugins table and all record tables
+fn get_schema_sql(schemas: &[TableSchema]) -> Vec<String> {
+    let mut statements = vec![PLUGINS_TABLE.to_string()];
+    let tables: Vec<String> = schemas.iter().map(|s| s.name.to_string()).collect();
     for schema in schemas {
         let columns = schema.columns.join(", ");
-        let constraints = schema.constraints.join(", ");
+        // skip foreign keys to tables that are not created
+        let constraints = schema
+            .constraints
+            .iter()
+            .filter(|c| match referenced_table(c) {
+                Some(table) if table != "plugins" && !tables.iter().any(|t| t == table) => {
+                    log::warn!(
+                        "Skipping foreign key of {} to excluded table {}",
+                        schema.name,
+                        table
+                    );
+                    false
+                }
+                _ => true,
+            })
+            .cloned()
+            .collect::<Vec<_>>()
+            .join(", ");
         // TODO flags
         let sql = if constraints.is_empty() {
             format!(
@@ -112,56 +604,130 @@ fn create_tables(conn: &Connection, schemas: &[TableSchema]) -> Result<()> {
                 schema.name, columns, constraints
             )
         };
+        statements.push(sql);
+    }
+    statements
+}
 
-        println!("{}", sql);
-
-        conn.execute(&sql, [])?;
+/// Create indexes on the mod column and the foreign key columns of all tables
+fn create_indexes(conn: &Connection, schemas: &[TableSchema]) -> Result<()> {
+    for schema in schemas {
+        let mut columns = vec!["mod"];
+        columns.extend(
+            schema
+                .constraints
+                .iter()
+                .filter_map(|c| foreign_key_column(c)),
+        );
+        for column in columns {
+            let sql = format!(
+                "CREATE INDEX IF NOT EXISTS idx_{0}_{1} ON {0}({1})",
+                schema.name, column
+            );
+            log::debug!("{}", sql);
+            conn.execute(&sql, [])?;
+        }
     }
     Ok(())
 }
 
-fn get_schemas() -> Vec<TableSchema> {
+/// Get the column of a foreign key constraint
+fn foreign_key_column(constraint: &str) -> Option<&str> {
+    let (_, rest) = constraint.split_once("FOREIGN KEY")?;
+    let (column, _) = rest.trim_start().strip_prefix('(')?.split_once(')')?;
+    Some(column.trim())
+}
+
+/// Get the table referenced by a foreign key constraint
+fn referenced_table(constraint: &str) -> Option<&str> {
+    let (_, rest) = constraint.split_once("REFERENCES")?;
+    rest.trim_start().split(['(', ' ']).next()
+}
+
+fn get_schemas(options: &SqlOptions) -> Vec<TableSchema> {
     let mut schemas = Vec::new();
-    for tag in get_all_tags() {
-        if let Some(instance) = create_from_tag(&tag) {
-            schemas.push(instance.table_schema());
+    for tag in get_all_tags()
+        .into_iter()
+        .filter(|t| options.is_selected(t))
+    {
+        match create_from_tag(&tag) {
+            Some(instance) => schemas.push(instance.table_schema()),
+            None => log::warn!("Skipping unknown record tag: {}", tag),
         }
     }
 
     schemas
 }
 
-fn insert_into_db(db: &Connection, hash: &str, record: &tes3::esp::TES3Object) {
+/// Get the primary key of a record.
+/// Records with empty ids get a surrogate key:
+/// - CELL with an empty id: the grid coordinates "x,y"
+/// - any other record with an empty id: the tag and the index in the plugin "TAG:n"
+fn record_key(record: &TES3Object, index: usize) -> String {
     match record {
-        tes3::esp::TES3Object::GameSetting(s) => {
+        TES3Object::Cell(s) if s.editor_id().is_empty() => {
+            format!("{},{}", s.data.grid.0, s.data.grid.1)
+        }
+        _ if record.editor_id().is_empty() => format!("{}:{}", record.tag_str(), index),
+        _ => record.editor_id().to_string(),
+    }
+}
+
+/// Get the insert statement of a table as an upsert on the record id.
+/// An existing row is only replaced by the same plugin or one later in the load order,
+/// so each table holds the winning version of a record and appending an earlier plugin
+/// does not overwrite the rows of the plugins that override it
+fn upsert_sql(sql: String) -> String {
+    let sql = sql.trim_end().trim_end_matches(';');
+    let Some((table, columns)) = sql
+        .strip_prefix("INSERT INTO ")
+        .and_then(|s| s.split_once('('))
+        .and_then(|(table, rest)| rest.split_once(')').map(|(c, _)| (table.trim(), c)))
+    else {
+        return sql.to_owned();
+    };
+    let updates = columns
+        .split(',')
+        .map(str::trim)
+        .filter(|c| *c != "id")
+        .map(|c| format!("{c} = excluded.{c}"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!(
+        "{sql} ON CONFLICT(id) DO UPDATE SET {updates} \
+        WHERE (SELECT load_order FROM plugins WHERE id = {table}.mod) \
+        <= (SELECT load_order FROM plugins WHERE id = excluded.mod)"
+    )
+}
+
+fn insert_into_db(db: &Connection, hash: &str, key: &str, record: &TES3Object) -> Result<()> {
+    match record {
+        TES3Object::GameSetting(s) => {
             db.execute(
-                s.table_insert().as_str(),
-                params![s.id, hash, as_json!(s.value)],
-            )
-            .unwrap_or_else(|_| panic!("Could not insert into db {}", s.id));
+                &upsert_sql(s.table_insert()),
+                params![key, hash, as_json!(s.value)],
+            )?;
         }
-        tes3::esp::TES3Object::GlobalVariable(s) => {
+        TES3Object::GlobalVariable(s) => {
             let value = match s.value {
                 tes3::esp::GlobalValue::Float(f) => f.to_string(),
                 tes3::esp::GlobalValue::Short(s) => s.to_string(),
                 tes3::esp::GlobalValue::Long(l) => l.to_string(),
             };
 
-            db.execute(s.table_insert().as_str(), params![s.id, hash, value])
-                .unwrap_or_else(|_| panic!("Could not insert into db {}", s.id));
+            db.execute(&upsert_sql(s.table_insert()), params![key, hash, value])?;
         }
-        tes3::esp::TES3Object::Class(s) => {
+        TES3Object::Class(s) => {
             db.execute(
-                s.table_insert().as_str(),
-                params![s.id, hash, s.name, s.description, as_json!(s.data)],
-            )
-            .unwrap_or_else(|_| panic!("Could not insert into db {}", s.id));
+                &upsert_sql(s.table_insert()),
+                params![key, hash, s.name, s.description, as_json!(s.data)],
+            )?;
         }
-        tes3::esp::TES3Object::Faction(s) => {
+        TES3Object::Faction(s) => {
             db.execute(
-                s.table_insert().as_str(),
+                &upsert_sql(s.table_insert()),
                 params![
-                    s.id,
+                    key,
                     hash,
                     s.name,
                     as_json!(s.rank_names),
@@ -171,28 +737,26 @@ fn insert_into_db(db: &Connection, hash: &str, record: &tes3::esp::TES3Object) {
                     as_json!(s.data.favored_skills),
                     as_json!(s.data.flags)
                 ],
-            )
-            .unwrap_or_else(|_| panic!("Could not insert into db {}", s.id));
+            )?;
         }
-        tes3::esp::TES3Object::Race(s) => {
+        TES3Object::Race(s) => {
             db.execute(
-                s.table_insert().as_str(),
+                &upsert_sql(s.table_insert()),
                 params![
-                    s.id,
+                    key,
                     hash,
                     s.name,
                     as_json!(s.spells),
                     s.description,
                     as_json!(s.data)
                 ],
-            )
-            .unwrap_or_else(|_| panic!("Could not insert into db {}", s.id));
+            )?;
         }
-        tes3::esp::TES3Object::MiscItem(s) => {
+        TES3Object::MiscItem(s) => {
             db.execute(
-                s.table_insert().as_str(),
+                &upsert_sql(s.table_insert()),
                 params![
-                    s.id,
+                    key,
                     hash,
                     s.name,
                     as_option!(s.script),
@@ -202,14 +766,13 @@ fn insert_into_db(db: &Connection, hash: &str, record: &tes3::esp::TES3Object) {
                     s.data.value,
                     as_json!(s.data.flags)
                 ],
-            )
-            .unwrap_or_else(|_| panic!("Could not insert into db {}", s.id));
+            )?;
         }
-        tes3::esp::TES3Object::Weapon(s) => {
+        TES3Object::Weapon(s) => {
             db.execute(
-                s.table_insert().as_str(),
+                &upsert_sql(s.table_insert()),
                 params![
-                    s.id,
+                    key,
                     hash,
                     s.name,
                     as_option!(s.script),
@@ -231,18 +794,16 @@ fn insert_into_db(db: &Connection, hash: &str, record: &tes3::esp::TES3Object) {
                     s.data.thrust_max,
                     as_json!(s.data.flags)
                 ],
-            )
-            .unwrap_or_else(|_| panic!("Could not insert into db {}", s.id));
+            )?;
         }
-        tes3::esp::TES3Object::Static(s) => {
-            db.execute(s.table_insert().as_str(), params![s.id, hash, s.mesh])
-                .unwrap_or_else(|_| panic!("Could not insert into db {}", s.id));
+        TES3Object::Static(s) => {
+            db.execute(&upsert_sql(s.table_insert()), params![key, hash, s.mesh])?;
         }
-        tes3::esp::TES3Object::Npc(s) => {
+        TES3Object::Npc(s) => {
             db.execute(
-                s.table_insert().as_str(),
+                &upsert_sql(s.table_insert()),
                 params![
-                    s.id,
+                    key,
                     hash,
                     s.name,
                     as_option!(s.script),
@@ -266,25 +827,22 @@ fn insert_into_db(db: &Connection, hash: &str, record: &tes3::esp::TES3Object) {
                     s.data.rank,
                     s.data.gold
                 ],
-            )
-            .unwrap_or_else(|_| panic!("Could not insert into db {}", s.id));
+            )?;
         }
-        tes3::esp::TES3Object::Activator(s) => {
+        TES3Object::Activator(s) => {
             db.execute(
-                s.table_insert().as_str(),
-                params![s.id, hash, s.name, as_option!(s.script), s.mesh],
-            )
-            .unwrap_or_else(|_| panic!("Could not insert into db {}", s.id));
+                &upsert_sql(s.table_insert()),
+                params![key, hash, s.name, as_option!(s.script), s.mesh],
+            )?;
         }
-        tes3::esp::TES3Object::Script(s) => {
-            db.execute(s.table_insert().as_str(), params![s.id, hash, s.text])
-                .unwrap_or_else(|_| panic!("Could not insert into db {}", s.id));
+        TES3Object::Script(s) => {
+            db.execute(&upsert_sql(s.table_insert()), params![key, hash, s.text])?;
         }
-        tes3::esp::TES3Object::Region(s) => {
+        TES3Object::Region(s) => {
             db.execute(
-                s.table_insert().as_str(),
+                &upsert_sql(s.table_insert()),
                 params![
-                    s.id,
+                    key,
                     hash,
                     s.name,
                     s.weather_chances.clear,
@@ -301,31 +859,28 @@ fn insert_into_db(db: &Connection, hash: &str, record: &tes3::esp::TES3Object) {
                     as_json!(s.map_color),
                     as_json!(s.sounds)
                 ],
-            )
-            .unwrap_or_else(|_| panic!("Could not insert into db {}", s.id));
+            )?;
         }
-        tes3::esp::TES3Object::LeveledItem(s) => {
+        TES3Object::LeveledItem(s) => {
             db.execute(
-                s.table_insert().as_str(),
+                &upsert_sql(s.table_insert()),
                 params![
-                    s.id,
+                    key,
                     hash,
                     as_json!(s.leveled_item_flags),
                     s.chance_none,
                     as_json!(s.items)
                 ],
-            )
-            .unwrap_or_else(|_| panic!("Could not insert into db {}", s.id));
+            )?;
         }
-        tes3::esp::TES3Object::Cell(s) => {
+        TES3Object::Cell(s) => {
             let references =
-                serde_json::to_string_pretty(&s.references.values().collect::<Vec<_>>()).unwrap();
-            let id = s.editor_id().to_string();
-
+                serde_json::to_string_pretty(&s.references.values().collect::<Vec<_>>())
+                    .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
             db.execute(
-                s.table_insert().as_str(),
+                &upsert_sql(s.table_insert()),
                 params![
-                    id,
+                    key,
                     hash,
                     s.name,
                     as_json!(s.data.flags),
@@ -334,21 +889,25 @@ fn insert_into_db(db: &Connection, hash: &str, record: &tes3::esp::TES3Object) {
                     s.water_height,
                     references
                 ],
-            )
-            .unwrap_or_else(|_| panic!("Could not insert into db {}", id));
+            )?;
         }
         _ => {}
     }
+
+    Ok(())
 }
 
 #[test]
-fn test_sql_task() -> Result<()> {
+fn test_sql_task() -> io::Result<()> {
     let input = std::path::Path::new("tests/assets/Morrowind.esm");
     let output = std::path::Path::new("./tes3.db3");
-    // delete db if exists
-    if output.exists() {
-        std::fs::remove_file(output).expect("Could not delete file");
-    }
 
-    sql_task(&Some(input.into()), &Some(output.into()))
+    sql_task(
+        &Some(input.into()),
+        &Some(output.into()),
+        &SqlOptions {
+            overwrite: true,
+            ..Default::default()
+        },
+    )
 }
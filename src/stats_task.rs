@@ -0,0 +1,403 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{self, Error, ErrorKind},
+    path::{Path, PathBuf},
+};
+
+use serde_json::{json, Value};
+use tes3::esp::{EditorId, Plugin, TES3Object, TypeInfo};
+
+use crate::{
+    get_plugins_sorted, no_plugins_error, parse_plugin, plugin_file_exists, plugin_tag_counts,
+    report_to_string, serialize_object, ESerializedType,
+};
+
+/// A record and its serialized size in bytes
+struct RecordSize {
+    tag: String,
+    id: String,
+    size: usize,
+}
+
+/// Record counts and header summary of a plugin
+struct PluginStats {
+    name: String,
+    author: String,
+    description: String,
+    masters: Vec<String>,
+    total: usize,
+    counts: BTreeMap<String, usize>,
+    /// The largest records, only measured if requested
+    top: Vec<RecordSize>,
+}
+
+/// Print statistics about a plugin or all plugins in a folder.
+/// Without a format a table is printed, otherwise the statistics are serialized to stdout
+pub fn stats(
+    input: &Option<PathBuf>,
+    top: Option<usize>,
+    format: &Option<ESerializedType>,
+    omw: bool,
+) -> io::Result<()> {
+    let input_path: &PathBuf;
+    // check no input
+    if let Some(i) = input {
+        input_path = i;
+    } else {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "No input path specified.",
+        ));
+    }
+    if !input_path.is_dir() && !plugin_file_exists(input_path) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Input path does not exist",
+        ));
+    }
+
+    // a folder is read in load order
+    let plugin_paths = if input_path.is_dir() {
+        get_plugins_sorted(input_path, omw, &None)?
+    } else {
+        vec![input_path.to_owned()]
+    };
+    if plugin_paths.is_empty() {
+        return Err(no_plugins_error(input_path));
+    }
+
+    let mut all_stats = vec![];
+    for path in &plugin_paths {
+        let plugin = match parse_plugin(path) {
+            Ok(p) => p,
+            Err(_) => return Err(Error::new(ErrorKind::Other, "Plugin parsing failed.")),
+        };
+        let mut plugin_stats = get_plugin_stats(path, &plugin);
+        if let Some(n) = top {
+            let mut sizes = get_record_sizes(&plugin.objects);
+            sizes.truncate(n);
+            plugin_stats.top = sizes;
+        }
+
+        if format.is_none() {
+            print_plugin_stats(&plugin_stats);
+            if top.is_some() {
+                print_top_records(&plugin_stats.top);
+            }
+        }
+        all_stats.push(plugin_stats);
+    }
+
+    // aggregate totals of all plugins in a folder
+    let aggregate = if input_path.is_dir() {
+        Some(get_aggregate(&all_stats))
+    } else {
+        None
+    };
+
+    match format {
+        Some(format) => {
+            let mut report = json!({
+                "plugins": all_stats.iter().map(to_value).collect::<Vec<_>>(),
+            });
+            if let Some((total, counts)) = &aggregate {
+                report["aggregate"] = json!({
+                    "plugins": all_stats.len(),
+                    "total": total,
+                    "counts": counts,
+                });
+            }
+            println!("{}", report_to_string(format, &report)?);
+        }
+        None => {
+            if let Some((total, counts)) = &aggregate {
+                println!("Aggregate of {} plugins", all_stats.len());
+                print_counts(*total, counts);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Count the records of a plugin by tag and read the header summary
+fn get_plugin_stats(path: &Path, plugin: &Plugin) -> PluginStats {
+    let mut plugin_stats = PluginStats {
+        name: path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        author: String::new(),
+        description: String::new(),
+        masters: vec![],
+        total: plugin.objects.len(),
+        counts: plugin_tag_counts(plugin),
+        top: vec![],
+    };
+
+    for object in &plugin.objects {
+        if let TES3Object::Header(header) = object {
+            plugin_stats.author = header.author.to_string();
+            plugin_stats.description = header.description.to_string();
+            plugin_stats.masters = header.masters.iter().map(|(m, _)| m.clone()).collect();
+        }
+    }
+
+    plugin_stats
+}
+
+/// Sum up the record counts of all plugins
+fn get_aggregate(all_stats: &[PluginStats]) -> (usize, BTreeMap<String, usize>) {
+    let mut total = 0;
+    let mut counts = BTreeMap::new();
+    for plugin_stats in all_stats {
+        total += plugin_stats.total;
+        for (tag, count) in &plugin_stats.counts {
+            *counts.entry(tag.clone()).or_default() += count;
+        }
+    }
+    (total, counts)
+}
+
+fn to_value(plugin_stats: &PluginStats) -> Value {
+    let mut value = json!({
+        "name": plugin_stats.name,
+        "author": plugin_stats.author,
+        "description": plugin_stats.description,
+        "masters": plugin_stats.masters,
+        "total": plugin_stats.total,
+        "counts": plugin_stats.counts,
+    });
+    if !plugin_stats.top.is_empty() {
+        value["top"] = plugin_stats
+            .top
+            .iter()
+            .map(|r| json!({ "tag": r.tag, "id": r.id, "size": r.size }))
+            .collect();
+    }
+    value
+}
+
+/// Print the header summary and record counts of a plugin
+fn print_plugin_stats(plugin_stats: &PluginStats) {
+    println!("Plugin: {}", plugin_stats.name);
+    println!("Author: {}", plugin_stats.author);
+    println!("Description: {}", plugin_stats.description);
+    println!("Masters: {}", plugin_stats.masters.join(", "));
+    print_counts(plugin_stats.total, &plugin_stats.counts);
+}
+
+/// Print record counts as a table, most common first
+fn print_counts(total: usize, counts: &BTreeMap<String, usize>) {
+    let mut sorted: Vec<_> = counts.iter().collect();
+    sorted.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+
+    println!("{:<6} {:>8}", "Tag", "Count");
+    for (tag, count) in sorted {
+        println!("{:<6} {:>8}", tag, count);
+    }
+    println!("{:<6} {:>8}", "Total", total);
+    println!();
+}
+
+/// Serialize each record and measure its size, records that fail to serialize are skipped
+fn get_record_sizes(objects: &[TES3Object]) -> Vec<RecordSize> {
+    let mut sizes = vec![];
+    for object in objects {
+        let text = match serialize_object(object, &ESerializedType::Yaml) {
+            Ok(text) => text,
+            Err(e) => {
+                log::warn!(
+                    "Skipping {} {}: {}",
+                    object.type_name(),
+                    object.editor_id(),
+                    e
+                );
+                continue;
+            }
+        };
+        sizes.push(RecordSize {
+            tag: object.tag_str().to_owned(),
+            id: object.editor_id().to_string(),
+            size: text.len(),
+        });
+    }
+
+    // largest first
+    sizes.sort_by(|a, b| b.size.cmp(&a.size));
+    sizes
+}
+
+/// Print the largest records as a table
+fn print_top_records(sizes: &[RecordSize]) {
+    println!("{:<6} {:<40} {:>12}", "Tag", "Id", "Size (bytes)");
+    for record in sizes {
+        println!("{:<6} {:<40} {:>12}", record.tag, record.id, record.size);
+    }
+}
+
+/// Print script counts and sizes of a plugin, scripts larger than max_bytes are flagged.
+/// Without a format a summary is printed, otherwise the report is serialized to stdout.
+/// Returns the number of flagged scripts
+pub fn script_stats(
+    input: &Option<PathBuf>,
+    max_bytes: usize,
+    format: &Option<ESerializedType>,
+) -> io::Result<usize> {
+    let input_path: &PathBuf;
+    // check no input
+    if let Some(i) = input {
+        input_path = i;
+    } else {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "No input path specified.",
+        ));
+    }
+    if !plugin_file_exists(input_path) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Input path does not exist",
+        ));
+    }
+
+    let plugin = match parse_plugin(input_path) {
+        Ok(p) => p,
+        Err(_) => return Err(Error::new(ErrorKind::Other, "Plugin parsing failed.")),
+    };
+
+    // id, bytes and lines of every script
+    let scripts: Vec<(&str, usize, usize)> = plugin
+        .objects
+        .iter()
+        .filter_map(|o| match o {
+            TES3Object::Script(s) => Some((s.id.as_str(), s.text.len(), s.text.lines().count())),
+            _ => None,
+        })
+        .collect();
+    let total_bytes: usize = scripts.iter().map(|(_, bytes, _)| bytes).sum();
+    let total_lines: usize = scripts.iter().map(|(_, _, lines)| lines).sum();
+    let longest = scripts.iter().max_by_key(|(_, bytes, _)| *bytes);
+    let oversized: Vec<_> = scripts
+        .iter()
+        .filter(|(_, bytes, _)| *bytes > max_bytes)
+        .collect();
+
+    match format {
+        Some(format) => {
+            let report = json!({
+                "scripts": scripts.len(),
+                "bytes": total_bytes,
+                "lines": total_lines,
+                "longest": longest.map(|(id, bytes, lines)| json!({ "id": id, "bytes": bytes, "lines": lines })),
+                "max_bytes": max_bytes,
+                "oversized": oversized
+                    .iter()
+                    .map(|(id, bytes, _)| json!({ "id": id, "bytes": bytes }))
+                    .collect::<Vec<_>>(),
+            });
+            println!("{}", report_to_string(format, &report)?);
+        }
+        None => {
+            println!("Scripts: {}", scripts.len());
+            println!("Bytes: {}", total_bytes);
+            println!("Lines: {}", total_lines);
+            if let Some((id, bytes, lines)) = longest {
+                println!("Longest: {} ({} bytes, {} lines)", id, bytes, lines);
+            }
+            for (id, bytes, _) in &oversized {
+                println!("{} exceeds {} bytes: {} bytes", id, max_bytes, bytes);
+            }
+        }
+    }
+
+    Ok(oversized.len())
+}
+
+/// Count the placements of every base object in the cells of a plugin, most placed first.
+/// Placed ids without a record in the plugin are listed separately.
+/// Returns the number of unresolved ids
+pub fn ref_count(input: &Option<PathBuf>, format: &Option<ESerializedType>) -> io::Result<usize> {
+    let input_path: &PathBuf;
+    // check no input
+    if let Some(i) = input {
+        input_path = i;
+    } else {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "No input path specified.",
+        ));
+    }
+    if !plugin_file_exists(input_path) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Input path does not exist",
+        ));
+    }
+
+    let plugin = match parse_plugin(input_path) {
+        Ok(p) => p,
+        Err(_) => return Err(Error::new(ErrorKind::Other, "Plugin parsing failed.")),
+    };
+
+    // the tag of every base object by lowercase id
+    let tags: HashMap<String, &str> = plugin
+        .objects
+        .iter()
+        .filter(|o| !matches!(o, TES3Object::Header(_) | TES3Object::Cell(_)))
+        .map(|o| (o.editor_id().to_lowercase(), o.tag_str()))
+        .collect();
+
+    // placements by lowercase id, with the id as first placed
+    let mut counts: HashMap<String, (&str, usize)> = HashMap::new();
+    for object in &plugin.objects {
+        if let TES3Object::Cell(cell) = object {
+            for reference in cell.references.values() {
+                counts
+                    .entry(reference.id.to_lowercase())
+                    .or_insert((reference.id.as_str(), 0))
+                    .1 += 1;
+            }
+        }
+    }
+
+    let mut resolved = vec![];
+    let mut unresolved = vec![];
+    for (key, (id, count)) in counts {
+        match tags.get(&key) {
+            Some(tag) => resolved.push((id, *tag, count)),
+            None => unresolved.push((id, count)),
+        }
+    }
+    resolved.sort_by(|a, b| b.2.cmp(&a.2).then_with(|| a.0.cmp(b.0)));
+    unresolved.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+
+    match format {
+        Some(format) => {
+            let report = json!({
+                "references": resolved
+                    .iter()
+                    .map(|(id, tag, count)| json!({ "id": id, "type": tag, "count": count }))
+                    .collect::<Vec<_>>(),
+                "unresolved": unresolved
+                    .iter()
+                    .map(|(id, count)| json!({ "id": id, "count": count }))
+                    .collect::<Vec<_>>(),
+            });
+            println!("{}", report_to_string(format, &report)?);
+        }
+        None => {
+            for (id, tag, count) in &resolved {
+                println!("{:>6}  {}  {}", count, tag, id);
+            }
+            if !unresolved.is_empty() {
+                println!("Unresolved:");
+                for (id, count) in &unresolved {
+                    println!("{:>6}  {}", count, id);
+                }
+            }
+        }
+    }
+
+    Ok(unresolved.len())
+}
@@ -0,0 +1,217 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{self, File},
+    io::{self, Error, ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+
+use serde_json::{json, Value};
+use tes3::esp::{EditorId, Plugin, TES3Object, TypeInfo};
+
+use crate::{parse_plugin, plugin_file_exists, report_to_string, ESerializedType};
+
+/// A reference to an id that is defined neither in the plugin nor in its masters
+struct UnresolvedReference {
+    tag: String,
+    id: String,
+    field: String,
+    reference: String,
+}
+
+/// Editor ids defined by a plugin and its masters, by tag
+#[derive(Default)]
+struct DefinedIds {
+    by_tag: HashMap<String, HashSet<String>>,
+    all: HashSet<String>,
+}
+
+impl DefinedIds {
+    fn add_plugin(&mut self, plugin: &Plugin) {
+        for object in &plugin.objects {
+            let id = object.editor_id().to_lowercase();
+            if id.is_empty() {
+                continue;
+            }
+            self.by_tag
+                .entry(object.tag_str().to_owned())
+                .or_default()
+                .insert(id.clone());
+            self.all.insert(id);
+        }
+    }
+
+    /// Check if an id is defined, optionally restricted to a tag
+    fn contains(&self, tag: Option<&str>, id: &str) -> bool {
+        let id = id.to_lowercase();
+        match tag {
+            Some(tag) => self.by_tag.get(tag).is_some_and(|ids| ids.contains(&id)),
+            None => self.all.contains(&id),
+        }
+    }
+}
+
+/// Check a plugin for references to ids that can't be resolved.
+/// Ids defined in masters found in masters_dir count as resolved.
+/// Returns the number of unresolved references
+pub fn validate(
+    input: &Option<PathBuf>,
+    masters_dir: &Option<PathBuf>,
+    output: &Option<PathBuf>,
+    format: &Option<ESerializedType>,
+) -> io::Result<usize> {
+    let input_path: &PathBuf;
+    // check no input
+    if let Some(i) = input {
+        input_path = i;
+    } else {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "No input path specified.",
+        ));
+    }
+    if !plugin_file_exists(input_path) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Input path does not exist",
+        ));
+    }
+
+    let plugin = match parse_plugin(input_path) {
+        Ok(p) => p,
+        Err(_) => return Err(Error::new(ErrorKind::Other, "Plugin parsing failed.")),
+    };
+
+    let mut defined = DefinedIds::default();
+    defined.add_plugin(&plugin);
+    if let Some(dir) = masters_dir {
+        for master in get_masters(&plugin) {
+            match find_master(dir, &master) {
+                Some(path) => match parse_plugin(&path) {
+                    Ok(p) => defined.add_plugin(&p),
+                    Err(e) => log::warn!("Could not parse master {}: {}", path.display(), e),
+                },
+                None => log::warn!("Master not found in {}: {}", dir.display(), master),
+            }
+        }
+    }
+
+    let mut unresolved = vec![];
+    for object in &plugin.objects {
+        collect_unresolved(object, &defined, &mut unresolved)?;
+    }
+
+    println!("Unresolved references: {}", unresolved.len());
+    match output {
+        Some(path) => {
+            let report: Vec<Value> = unresolved
+                .iter()
+                .map(|u| {
+                    json!({
+                        "tag": u.tag,
+                        "id": u.id,
+                        "field": u.field,
+                        "reference": u.reference,
+                    })
+                })
+                .collect();
+            let format = format.clone().unwrap_or_default();
+            let text = report_to_string(&format, &json!({ "unresolved": report }))?;
+            File::create(path)?.write_all(text.as_bytes())?;
+        }
+        None => {
+            for u in &unresolved {
+                progress!("{} {}: {} -> {}", u.tag, u.id, u.field, u.reference);
+            }
+        }
+    }
+
+    Ok(unresolved.len())
+}
+
+/// Get the master file names from the plugin header
+fn get_masters(plugin: &Plugin) -> Vec<String> {
+    for object in &plugin.objects {
+        if let TES3Object::Header(header) = object {
+            return header.masters.iter().map(|(m, _)| m.clone()).collect();
+        }
+    }
+    vec![]
+}
+
+/// Find a master by file name in a folder, case-insensitive
+fn find_master(dir: &Path, name: &str) -> Option<PathBuf> {
+    let name = name.to_lowercase();
+    fs::read_dir(dir)
+        .ok()?
+        .flatten()
+        .map(|e| e.path())
+        .find(|p| {
+            p.file_name()
+                .is_some_and(|n| n.to_string_lossy().to_lowercase() == name)
+        })
+}
+
+/// Scan the fields of a record for id references that are not defined
+fn collect_unresolved(
+    object: &TES3Object,
+    defined: &DefinedIds,
+    unresolved: &mut Vec<UnresolvedReference>,
+) -> io::Result<()> {
+    let value = serde_json::to_value(object)?;
+    let Value::Object(fields) = value else {
+        return Ok(());
+    };
+
+    let mut references = vec![];
+    for (field, value) in &fields {
+        // the tag the reference must resolve to, None resolves to any record
+        let tag = match field.as_str() {
+            "script" => Some("SCPT"),
+            "enchanting" => Some("ENCH"),
+            "spells" => Some("SPEL"),
+            "inventory" | "items" | "creatures" | "references" => None,
+            _ => continue,
+        };
+        for reference in get_reference_ids(value) {
+            references.push((field.clone(), tag, reference));
+        }
+    }
+
+    for (field, tag, reference) in references {
+        if reference.is_empty() || defined.contains(tag, &reference) {
+            continue;
+        }
+        unresolved.push(UnresolvedReference {
+            tag: object.tag_str().to_owned(),
+            id: object.editor_id().to_string(),
+            field,
+            reference,
+        });
+    }
+
+    Ok(())
+}
+
+/// Get the referenced ids of a serialized field.
+/// Lists of (count, id) pairs and cell references with an id field are supported
+fn get_reference_ids(value: &Value) -> Vec<String> {
+    match value {
+        Value::String(s) => vec![s.clone()],
+        Value::Array(list) => list
+            .iter()
+            .flat_map(|v| match v {
+                Value::Array(pair) => pair
+                    .iter()
+                    .filter_map(|p| p.as_str().map(str::to_owned))
+                    .collect(),
+                _ => get_reference_ids(v),
+            })
+            .collect(),
+        Value::Object(map) => match map.get("id") {
+            Some(Value::String(id)) => vec![id.clone()],
+            // a map of references
+            _ => map.values().flat_map(get_reference_ids).collect(),
+        },
+        _ => vec![],
+    }
+}
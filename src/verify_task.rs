@@ -0,0 +1,111 @@
+use std::{
+    io::{self, Error, ErrorKind},
+    path::PathBuf,
+};
+
+use tes3::esp::{EditorId, TES3Object, TypeInfo};
+
+use crate::{
+    diff_task::diff_plugins, format_from_path, pack_plugin, parse_plugin, plugin_file_exists,
+    plugin_tag_counts, read_serialized_plugin, MANIFEST_FILE_NAME,
+};
+
+/// Check that a dumped folder or a serialized file packs back into the original plugin.
+/// The records are compared by tag and editor id, every differing record is printed.
+/// The record order is compared too, a folder keeps it only if it was dumped with a manifest.
+/// Returns true if the plugins differ
+pub fn verify(original: &Option<PathBuf>, dumped: &Option<PathBuf>) -> io::Result<bool> {
+    let (original_path, dumped_path) = match (original, dumped) {
+        (Some(a), Some(b)) => (a, b),
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "An original plugin and a dumped folder or file must be specified.",
+            ))
+        }
+    };
+    if !plugin_file_exists(original_path) || !dumped_path.exists() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Input path does not exist",
+        ));
+    }
+
+    let original = match parse_plugin(original_path) {
+        Ok(p) => p,
+        Err(_) => return Err(Error::new(ErrorKind::Other, "Plugin parsing failed.")),
+    };
+    // a folder is packed in memory, a file is deserialized
+    let packed = if dumped_path.is_dir() {
+        let use_manifest = dumped_path.join(MANIFEST_FILE_NAME).is_file();
+        pack_plugin(dumped_path, &None, use_manifest)?
+    } else if format_from_path(dumped_path).is_some() {
+        read_serialized_plugin(dumped_path)?
+    } else {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Unknown serialized format",
+        ));
+    };
+
+    let mut differ = false;
+    // records without id can't be matched, compare the counts too
+    let original_counts = plugin_tag_counts(&original);
+    let packed_counts = plugin_tag_counts(&packed);
+    if original_counts != packed_counts {
+        for (tag, count) in &original_counts {
+            let packed_count = packed_counts.get(tag).copied().unwrap_or_default();
+            if *count != packed_count {
+                progress!("{}: {} records, {} after packing", tag, count, packed_count);
+            }
+        }
+        for (tag, count) in packed_counts
+            .iter()
+            .filter(|(t, _)| !original_counts.contains_key(*t))
+        {
+            progress!("{}: 0 records, {} after packing", tag, count);
+        }
+        differ = true;
+    }
+
+    let diffs = diff_plugins(&original, &packed);
+    for diff in &diffs {
+        progress!(
+            "{} {} {}",
+            diff["status"].as_str().unwrap_or_default(),
+            diff["tag"].as_str().unwrap_or_default(),
+            diff["id"].as_str().unwrap_or_default()
+        );
+        for field in diff["fields"].as_array().into_iter().flatten() {
+            progress!("  {}", field["path"].as_str().unwrap_or_default());
+        }
+    }
+    differ |= !diffs.is_empty();
+
+    // the game resolves some records by position, e.g. INFO records by their topic
+    let key = |o: &TES3Object| (o.tag_str(), o.editor_id().to_lowercase());
+    let position = original
+        .objects
+        .iter()
+        .map(key)
+        .zip(packed.objects.iter().map(key))
+        .position(|(a, b)| a != b);
+    if let Some(i) = position {
+        progress!(
+            "Record order differs at {}: {} {}, {} {} after packing",
+            i,
+            original.objects[i].tag_str(),
+            original.objects[i].editor_id(),
+            packed.objects[i].tag_str(),
+            packed.objects[i].editor_id()
+        );
+        differ = true;
+    }
+
+    if differ {
+        println!("Round trip is not lossless, {} records differ", diffs.len());
+    } else {
+        println!("Round trip is lossless.");
+    }
+    Ok(differ)
+}
@@ -1,43 +1,327 @@
-use std::path::{Path, PathBuf};
+use std::{
+    fs::{self, File},
+    io::Write,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
 
-use tes3util::{deserialize_plugin, dump, pack, serialize_plugin, ESerializedType};
+use flate2::{write::GzEncoder, Compression};
+use tes3::esp::{
+    Cell, Dialogue, DialogueInfo, Header, Plugin, Reference, Script, Static, TES3Object,
+};
+
+use tes3::esp::{EditorId, TypeInfo};
+use tes3util::{
+    assets_task, cells_task, clean_task, convert_task, csv_task, dedupe_objects,
+    deserialize_plugin, dialogue_task, dump, get_task, masters_task, merge_task, pack,
+    remaster_task, rename_task, scripts_task, serialize_plugin, split_task, sql_task, stats_task,
+    verify_task, DumpOptions, EEncoding, ESerializedType, PackOptions, SerializeOptions,
+};
 
 #[test]
 #[ignore]
 fn test_serialize_to_yaml() -> std::io::Result<()> {
     let input = Path::new("tests/assets/Ashlander Crafting.ESP");
-    serialize_plugin(&Some(input.into()), &None, &Some(ESerializedType::Yaml))
+    serialize_plugin(
+        &Some(input.into()),
+        &None,
+        &SerializeOptions {
+            format: Some(ESerializedType::Yaml),
+            ..Default::default()
+        },
+    )?;
+    Ok(())
 }
 #[test]
 #[ignore]
 fn test_serialize_to_toml() -> std::io::Result<()> {
     let input = Path::new("tests/assets/Ashlander Crafting.ESP");
-    serialize_plugin(&Some(input.into()), &None, &Some(ESerializedType::Toml))
+    serialize_plugin(
+        &Some(input.into()),
+        &None,
+        &SerializeOptions {
+            format: Some(ESerializedType::Toml),
+            ..Default::default()
+        },
+    )?;
+    Ok(())
 }
 #[test]
 #[ignore]
 fn test_serialize_to_json() -> std::io::Result<()> {
     let input = Path::new("tests/assets/Ashlander Crafting.ESP");
-    serialize_plugin(&Some(input.into()), &None, &Some(ESerializedType::Json))
+    serialize_plugin(
+        &Some(input.into()),
+        &None,
+        &SerializeOptions {
+            format: Some(ESerializedType::Json),
+            ..Default::default()
+        },
+    )?;
+    Ok(())
+}
+#[test]
+#[ignore]
+fn test_serialize_to_keyed_json() -> std::io::Result<()> {
+    let input = Path::new("tests/assets/Ashlander Crafting.ESP");
+    let output = Path::new("tests/assets/out/Ashlander Crafting.keyed");
+    serialize_plugin(
+        &Some(input.into()),
+        &Some(output.into()),
+        &SerializeOptions {
+            format: Some(ESerializedType::Json),
+            keyed: true,
+            ..Default::default()
+        },
+    )?;
+    Ok(())
+}
+#[test]
+fn test_serialize_format_from_output() -> std::io::Result<()> {
+    let input = Path::new("tests/assets/Ashlander Crafting.ESP");
+    let output = Path::new("tests/assets/out/inferred.json");
+    fs::create_dir_all("tests/assets/out")?;
+    serialize_plugin(
+        &Some(input.into()),
+        &Some(output.into()),
+        &SerializeOptions {
+            ..Default::default()
+        },
+    )?;
+    let text = fs::read_to_string(output)?;
+    assert!(serde_json::from_str::<Plugin>(&text).is_ok());
+
+    // a conflicting format is an error
+    assert!(serialize_plugin(
+        &Some(input.into()),
+        &Some(output.into()),
+        &SerializeOptions {
+            format: Some(ESerializedType::Yaml),
+            ..Default::default()
+        },
+    )
+    .is_err());
+    Ok(())
+}
+#[test]
+fn test_serialize_compact_json() -> std::io::Result<()> {
+    let input = Path::new("tests/assets/Ashlander Crafting.ESP");
+    let pretty = Path::new("tests/assets/out/pretty.json");
+    let compact = Path::new("tests/assets/out/compact.json");
+    fs::create_dir_all("tests/assets/out")?;
+    for (output, is_compact) in [(pretty, false), (compact, true)] {
+        serialize_plugin(
+            &Some(input.into()),
+            &Some(output.into()),
+            &SerializeOptions {
+                compact: is_compact,
+                ..Default::default()
+            },
+        )?;
+    }
+
+    let text = fs::read_to_string(compact)?;
+    assert!(!text.contains('\n'));
+    assert!(text.len() < fs::metadata(pretty)?.len() as usize);
+    assert_eq!(
+        serde_json::from_str::<Plugin>(&text)?.objects.len(),
+        Plugin::from_path(input)?.objects.len()
+    );
+    Ok(())
+}
+#[test]
+fn test_serialize_limit() -> std::io::Result<()> {
+    let input = Path::new("tests/assets/Ashlander Crafting.ESP");
+    let output = Path::new("tests/assets/out/limit.json");
+    fs::create_dir_all("tests/assets/out")?;
+    serialize_plugin(
+        &Some(input.into()),
+        &Some(output.into()),
+        &SerializeOptions {
+            limit: Some(3),
+            ..Default::default()
+        },
+    )?;
+
+    // the header is kept
+    let plugin = serde_json::from_str::<Plugin>(&fs::read_to_string(output)?)?;
+    assert_eq!(plugin.objects.len(), 4);
+    assert!(matches!(plugin.objects[0], TES3Object::Header(_)));
+    Ok(())
+}
+#[test]
+fn test_serialize_lenient() -> std::io::Result<()> {
+    let input = Path::new("tests/assets/Ashlander Crafting.ESP");
+    let output = Path::new("tests/assets/out/lenient.json");
+    fs::create_dir_all("tests/assets/out")?;
+    let summary = serialize_plugin(
+        &Some(input.into()),
+        &Some(output.into()),
+        &SerializeOptions {
+            lenient: true,
+            ..Default::default()
+        },
+    )?;
+
+    // nothing is skipped if all records serialize
+    let plugin = serde_json::from_str::<Plugin>(&fs::read_to_string(output)?)?;
+    assert_eq!(
+        plugin.objects.len(),
+        Plugin::from_path(input)?.objects.len()
+    );
+    assert_eq!(summary.records, plugin.objects.len());
+    assert_eq!(summary.skipped, 0);
+    Ok(())
+}
+#[test]
+fn test_serialize_lenient_skips_record() -> std::io::Result<()> {
+    // toml integers are signed, a master size above i64::MAX can't be written
+    fs::create_dir_all("tests/assets/out")?;
+    let input = PathBuf::from("tests/assets/out/lenient_input.esp");
+    let mut plugin = Plugin::new();
+    plugin.objects.push(TES3Object::from(Header {
+        masters: vec![("Huge.esm".into(), u64::MAX)],
+        ..Default::default()
+    }));
+    for id in ["static_a", "static_b"] {
+        plugin.objects.push(TES3Object::from(Static {
+            id: id.into(),
+            ..Default::default()
+        }));
+    }
+    plugin.save_path(&input)?;
+
+    let output = Path::new("tests/assets/out/lenient_skip.toml");
+    let summary = serialize_plugin(
+        &Some(input),
+        &Some(output.into()),
+        &SerializeOptions {
+            lenient: true,
+            ..Default::default()
+        },
+    )?;
+    assert_eq!((summary.records, summary.skipped), (2, 1));
+
+    let text = fs::read_to_string(output)?;
+    let plugin: Plugin = toml::from_str(&text).unwrap();
+    let ids: Vec<&str> = plugin.objects.iter().map(|o| o.editor_id()).collect();
+    assert_eq!(ids, ["static_a", "static_b"]);
+    Ok(())
+}
+#[test]
+fn test_serialize_toml_inline() -> std::io::Result<()> {
+    let input = Path::new("tests/assets/Ashlander Crafting.ESP");
+    let output = Path::new("tests/assets/out/inline.toml");
+    fs::create_dir_all("tests/assets/out")?;
+    serialize_plugin(
+        &Some(input.into()),
+        &Some(output.into()),
+        &SerializeOptions {
+            toml_inline: true,
+            ..Default::default()
+        },
+    )?;
+
+    // inline tables read back to the same records
+    let plugin = toml::from_str::<Plugin>(&fs::read_to_string(output)?).unwrap();
+    assert_eq!(
+        plugin.objects.len(),
+        Plugin::from_path(input)?.objects.len()
+    );
+
+    // only toml has inline tables
+    assert!(serialize_plugin(
+        &Some(input.into()),
+        &Some(PathBuf::from("tests/assets/out/inline.json")),
+        &SerializeOptions {
+            toml_inline: true,
+            ..Default::default()
+        },
+    )
+    .is_err());
+    Ok(())
+}
+#[test]
+fn test_serialize_gzip() -> std::io::Result<()> {
+    let input = Path::new("tests/assets/Ashlander Crafting.ESP");
+    let compressed = Path::new("tests/assets/out/Ashlander Crafting.ESP.gz");
+    fs::create_dir_all("tests/assets/out")?;
+    let mut encoder = GzEncoder::new(File::create(compressed)?, Compression::default());
+    encoder.write_all(&fs::read(input)?)?;
+    encoder.finish()?;
+
+    serialize_plugin(
+        &Some(compressed.into()),
+        &None,
+        &SerializeOptions {
+            format: Some(ESerializedType::Json),
+            ..Default::default()
+        },
+    )?;
+    Ok(())
+}
+
+#[test]
+fn test_omw_plugins_in_folder() -> std::io::Result<()> {
+    let folder = Path::new("tests/assets/out/omw");
+    if folder.exists() {
+        fs::remove_dir_all(folder)?;
+    }
+    fs::create_dir_all(folder)?;
+    // omwaddons are tes3 plugins, omwscripts are lists of lua scripts
+    fs::copy(
+        "tests/assets/Ashlander Crafting.ESP",
+        folder.join("Crafting.omwaddon"),
+    )?;
+    let mut encoder = GzEncoder::new(
+        File::create(folder.join("Compressed.esp.gz"))?,
+        Compression::default(),
+    );
+    encoder.write_all(&fs::read("tests/assets/Ashlander Crafting.ESP")?)?;
+    encoder.finish()?;
+    fs::write(
+        folder.join("Scripts.omwscripts"),
+        "PLAYER: scripts/crafting/player.lua\nGLOBAL: scripts/crafting/global.lua\n",
+    )?;
+
+    let mut names: Vec<String> = tes3util::get_plugins_in_folder(folder, false)?
+        .iter()
+        .map(|p| p.file_name().unwrap().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+    assert_eq!(names, ["Compressed.esp.gz", "Crafting.omwaddon"]);
+    assert_eq!(tes3util::get_plugins_in_folder(folder, true)?.len(), 3);
+
+    // the omwaddon and the compressed plugin are dumped, the script list is skipped
+    let summary = dump(
+        &Some(folder.into()),
+        &Some(folder.join("dumped")),
+        &DumpOptions::default(),
+    )?;
+    assert_eq!(summary.outputs.len(), 2);
+    Ok(())
 }
 
 #[test]
 #[ignore]
 fn test_deserialize_from_yaml() -> std::io::Result<()> {
     let input = Path::new("tests/assets/Ashlander Crafting.ESP.yaml");
-    deserialize_plugin(&Some(input.into()), &None, false)
+    deserialize_plugin(&Some(input.into()), &None, false, &None, false)?;
+    Ok(())
 }
 #[test]
 #[ignore]
 fn test_deserialize_from_toml() -> std::io::Result<()> {
     let input = Path::new("tests/assets/Ashlander Crafting.ESP.toml");
-    deserialize_plugin(&Some(input.into()), &None, false)
+    deserialize_plugin(&Some(input.into()), &None, false, &None, false)?;
+    Ok(())
 }
 #[test]
 #[ignore]
 fn test_deserialize_from_json() -> std::io::Result<()> {
     let input = Path::new("tests/assets/Ashlander Crafting.ESP.json");
-    deserialize_plugin(&Some(input.into()), &None, false)
+    deserialize_plugin(&Some(input.into()), &None, false, &None, false)?;
+    Ok(())
 }
 
 #[test]
@@ -48,11 +332,12 @@ fn test_dump_yaml() -> std::io::Result<()> {
     dump(
         &Some(input.into()),
         &Some(output.into()),
-        false,
-        &[],
-        &[],
-        &Some(ESerializedType::Yaml),
-    )
+        &DumpOptions {
+            format: Some(ESerializedType::Yaml),
+            ..Default::default()
+        },
+    )?;
+    Ok(())
 }
 #[test]
 #[ignore]
@@ -62,11 +347,12 @@ fn test_dump_toml() -> std::io::Result<()> {
     dump(
         &Some(input.into()),
         &Some(output.into()),
-        false,
-        &[],
-        &[],
-        &Some(tes3util::ESerializedType::Toml),
-    )
+        &DumpOptions {
+            format: Some(tes3util::ESerializedType::Toml),
+            ..Default::default()
+        },
+    )?;
+    Ok(())
 }
 #[test]
 #[ignore]
@@ -76,11 +362,12 @@ fn test_dump_json() -> std::io::Result<()> {
     dump(
         &Some(input.into()),
         &Some(output.into()),
-        false,
-        &[],
-        &[],
-        &Some(ESerializedType::Json),
-    )
+        &DumpOptions {
+            format: Some(ESerializedType::Json),
+            ..Default::default()
+        },
+    )?;
+    Ok(())
 }
 
 #[test]
@@ -88,26 +375,1736 @@ fn test_dump_json() -> std::io::Result<()> {
 fn test_pack_yaml() -> std::io::Result<()> {
     let input = PathBuf::from("tests/assets/out");
     let output = PathBuf::from("tests/assets/out/test.yaml.esp");
-    pack(&Some(input), &Some(output), &Some(ESerializedType::Yaml))
+    pack(
+        &Some(input),
+        &Some(output),
+        &PackOptions {
+            format: Some(ESerializedType::Yaml),
+            ..Default::default()
+        },
+    )?;
+    Ok(())
 }
 #[test]
 #[ignore]
 fn test_pack_toml() -> std::io::Result<()> {
     let input = PathBuf::from("tests/assets/out");
     let output = PathBuf::from("tests/assets/out/test.toml.esp");
-    pack(&Some(input), &Some(output), &Some(ESerializedType::Toml))
+    pack(
+        &Some(input),
+        &Some(output),
+        &PackOptions {
+            format: Some(ESerializedType::Toml),
+            ..Default::default()
+        },
+    )?;
+    Ok(())
 }
 #[test]
 #[ignore]
 fn test_pack_json() -> std::io::Result<()> {
     let input = PathBuf::from("tests/assets/out");
     let output = PathBuf::from("tests/assets/out/test.json.esp");
-    pack(&Some(input), &Some(output), &Some(ESerializedType::Json))
+    pack(
+        &Some(input),
+        &Some(output),
+        &PackOptions {
+            format: Some(ESerializedType::Json),
+            ..Default::default()
+        },
+    )?;
+    Ok(())
+}
+
+#[test]
+fn test_pack_without_header() -> std::io::Result<()> {
+    let input = Path::new("tests/assets/Ashlander Crafting.ESP");
+    let dumped = PathBuf::from("tests/assets/out/no_header");
+    dump(
+        &Some(input.into()),
+        &Some(dumped.clone()),
+        &DumpOptions {
+            exclude: vec!["TES3".to_string()],
+            ..Default::default()
+        },
+    )?;
+
+    let output = PathBuf::from("tests/assets/out/no_header.esp");
+    pack(
+        &Some(dumped),
+        &Some(output),
+        &PackOptions {
+            format: Some(ESerializedType::Yaml),
+            ..Default::default()
+        },
+    )?;
+    Ok(())
+}
+
+#[test]
+fn test_pack_two_headers() -> std::io::Result<()> {
+    let input = Path::new("tests/assets/Ashlander Crafting.ESP");
+    let dumped = PathBuf::from("tests/assets/out/two_headers");
+    if dumped.exists() {
+        fs::remove_dir_all(&dumped)?;
+    }
+    dump(
+        &Some(input.into()),
+        &Some(dumped.clone()),
+        &DumpOptions {
+            include: vec!["TES3".to_string()],
+            ..Default::default()
+        },
+    )?;
+
+    // copy the header into a second record folder
+    let header_dir = fs::read_dir(&dumped)?.flatten().next().unwrap().path();
+    let header = fs::read_dir(&header_dir)?.flatten().next().unwrap().path();
+    let copy_dir = dumped.join("Copy");
+    fs::create_dir_all(&copy_dir)?;
+    fs::copy(&header, copy_dir.join(header.file_name().unwrap()))?;
+
+    let output = PathBuf::from("tests/assets/out/two_headers.esp");
+    assert!(pack(
+        &Some(dumped),
+        &Some(output),
+        &PackOptions {
+            format: Some(ESerializedType::Yaml),
+            ..Default::default()
+        }
+    )
+    .is_err());
+    Ok(())
+}
+
+#[test]
+fn test_pack_mixed_formats() -> std::io::Result<()> {
+    let input = Path::new("tests/assets/Ashlander Crafting.ESP");
+    let dumped = PathBuf::from("tests/assets/out/mixed");
+    if dumped.exists() {
+        fs::remove_dir_all(&dumped)?;
+    }
+    dump(
+        &Some(input.into()),
+        &Some(dumped.clone()),
+        &DumpOptions::default(),
+    )?;
+
+    // convert one record to json
+    let (yaml, object) = walkdir::WalkDir::new(&dumped)
+        .into_iter()
+        .flatten()
+        .filter(|e| e.file_type().is_file())
+        .filter_map(|e| {
+            let text = fs::read_to_string(e.path()).ok()?;
+            let object: TES3Object = serde_yaml::from_str(&text).ok()?;
+            Some((e.into_path(), object))
+        })
+        .find(|(_, o)| o.tag_str() != "TES3")
+        .unwrap();
+    fs::write(yaml.with_extension("json"), serde_json::to_string(&object)?)?;
+    fs::remove_file(&yaml)?;
+
+    let output = PathBuf::from("tests/assets/out/mixed.esp");
+    let summary = pack(
+        &Some(dumped),
+        &Some(output.clone()),
+        &PackOptions::default(),
+    )?;
+    assert_eq!(summary.outputs, vec![output.clone()]);
+    assert_eq!(
+        Plugin::from_path(&output)?.objects.len(),
+        Plugin::from_path(input)?.objects.len()
+    );
+    assert_eq!(summary.records, Plugin::from_path(input)?.objects.len());
+    Ok(())
+}
+
+#[test]
+fn test_pack_reproducible() -> std::io::Result<()> {
+    let input = Path::new("tests/assets/Ashlander Crafting.ESP");
+    let dumped = PathBuf::from("tests/assets/out/reproducible");
+    dump(
+        &Some(input.into()),
+        &Some(dumped.clone()),
+        &DumpOptions::default(),
+    )?;
+
+    let first = PathBuf::from("tests/assets/out/reproducible_1.esp");
+    let second = PathBuf::from("tests/assets/out/reproducible_2.esp");
+    pack(
+        &Some(dumped.clone()),
+        &Some(first.clone()),
+        &PackOptions::default(),
+    )?;
+    pack(
+        &Some(dumped),
+        &Some(second.clone()),
+        &PackOptions::default(),
+    )?;
+    assert_eq!(fs::read(first)?, fs::read(second)?);
+    Ok(())
+}
+
+#[test]
+fn test_dump_baseline() -> std::io::Result<()> {
+    let input = Path::new("tests/assets/Ashlander Crafting.ESP");
+    let output = Path::new("tests/assets/out/baseline");
+    let summary = dump(
+        &Some(input.into()),
+        &Some(output.into()),
+        &DumpOptions {
+            baseline: Some(input.into()),
+            ..Default::default()
+        },
+    )?;
+
+    // a plugin has no changes relative to itself, only records without id are dumped
+    let plugin = Plugin::from_path(input)?;
+    let without_id = plugin
+        .objects
+        .iter()
+        .filter(|o| o.editor_id().is_empty())
+        .count();
+    assert_eq!(summary.records, without_id);
+    Ok(())
+}
+
+#[test]
+fn test_dump_flatten() -> std::io::Result<()> {
+    let input = Path::new("tests/assets/Ashlander Crafting.ESP");
+    let output = Path::new("tests/assets/out/flatten");
+    if output.exists() {
+        fs::remove_dir_all(output)?;
+    }
+    dump(
+        &Some(input.into()),
+        &Some(output.into()),
+        &DumpOptions {
+            flatten: true,
+            ..Default::default()
+        },
+    )?;
+
+    // no type subfolders are created
+    let entries: Vec<_> = fs::read_dir(output)?.flatten().collect();
+    assert!(!entries.is_empty());
+    assert!(entries.iter().all(|e| e.path().is_file()));
+    assert!(output.join("Header.yaml").exists());
+    Ok(())
+}
+
+#[test]
+fn test_dump_shared_path() -> std::io::Result<()> {
+    // two records with the same type and id are dumped to the same file
+    fs::create_dir_all("tests/assets/out")?;
+    let input = PathBuf::from("tests/assets/out/shared_path.esp");
+    let mut plugin = Plugin::new();
+    plugin.objects.push(TES3Object::from(Header::default()));
+    for mesh in ["first.nif", "second.nif"] {
+        plugin.objects.push(TES3Object::from(Static {
+            id: "shared_static".into(),
+            mesh: mesh.into(),
+            ..Default::default()
+        }));
+    }
+    plugin.save_path(&input)?;
+
+    let output = Path::new("tests/assets/out/shared_path");
+    if output.exists() {
+        fs::remove_dir_all(output)?;
+    }
+    dump(&Some(input), &Some(output.into()), &DumpOptions::default())?;
+
+    // the last record wins
+    let text = fs::read_to_string(output.join("Static").join("shared_static.yaml"))?;
+    assert!(text.contains("second.nif"));
+    assert!(!text.contains("first.nif"));
+    Ok(())
+}
+
+#[test]
+fn test_dump_skips_failed_record() -> std::io::Result<()> {
+    // toml integers are signed, a master size above i64::MAX can't be written
+    fs::create_dir_all("tests/assets/out")?;
+    let input = PathBuf::from("tests/assets/out/dump_skip.esp");
+    let mut plugin = Plugin::new();
+    plugin.objects.push(TES3Object::from(Header {
+        masters: vec![("Huge.esm".into(), u64::MAX)],
+        ..Default::default()
+    }));
+    plugin.objects.push(TES3Object::from(Static {
+        id: "static_a".into(),
+        ..Default::default()
+    }));
+    plugin.save_path(&input)?;
+
+    let output = Path::new("tests/assets/out/dump_skip");
+    let summary = dump(
+        &Some(input),
+        &Some(output.into()),
+        &DumpOptions {
+            format: Some(ESerializedType::Toml),
+            ..Default::default()
+        },
+    )?;
+    assert_eq!((summary.records, summary.skipped), (1, 1));
+    Ok(())
+}
+
+#[test]
+fn test_dump_cp1252() -> std::io::Result<()> {
+    // a script with a character outside of ascii
+    fs::create_dir_all("tests/assets/out")?;
+    let input = PathBuf::from("tests/assets/out/cp1252.esp");
+    let mut plugin = Plugin::new();
+    plugin.objects.push(TES3Object::from(Header::default()));
+    plugin.objects.push(TES3Object::from(Script {
+        id: "cp1252_script".into(),
+        text: "Begin cp1252_script\n; café\nEnd".into(),
+        ..Default::default()
+    }));
+    plugin.save_path(&input)?;
+
+    let output = Path::new("tests/assets/out/cp1252");
+    dump(
+        &Some(input),
+        &Some(output.into()),
+        &DumpOptions {
+            encoding: EEncoding::Cp1252,
+            ..Default::default()
+        },
+    )?;
+
+    // é is a single byte in Windows-1252
+    let bytes = fs::read(output.join("Script").join("cp1252_script.mwscript"))?;
+    assert!(bytes.contains(&0xE9));
+    assert!(String::from_utf8(bytes).is_err());
+
+    // the serialized records stay utf-8 and pack back to the same text
+    let packed = PathBuf::from("tests/assets/out/cp1252_packed.esp");
+    pack(
+        &Some(output.into()),
+        &Some(packed.clone()),
+        &PackOptions::default(),
+    )?;
+    let script = Plugin::from_path(&packed)?
+        .objects
+        .into_iter()
+        .find_map(|o| match o {
+            TES3Object::Script(s) => Some(s),
+            _ => None,
+        })
+        .unwrap();
+    assert!(script.text.contains("café"));
+    Ok(())
+}
+
+#[test]
+fn test_dump_glob() -> std::io::Result<()> {
+    // the pattern is matched case-insensitively against file names
+    let output = Path::new("tests/assets/out/glob");
+    let summary = dump(
+        &Some("tests/assets".into()),
+        &Some(output.into()),
+        &DumpOptions {
+            glob: Some("ashlander*.esp".into()),
+            ..Default::default()
+        },
+    )?;
+    assert_eq!(summary.outputs.len(), 1);
+
+    let result = dump(
+        &Some("tests/assets".into()),
+        &Some(output.into()),
+        &DumpOptions {
+            glob: Some("Tamriel_Data*.esm".into()),
+            ..Default::default()
+        },
+    );
+    assert!(result.is_err());
+    Ok(())
+}
+
+#[test]
+fn test_pack_flat() -> std::io::Result<()> {
+    let input = Path::new("tests/assets/Ashlander Crafting.ESP");
+    let dumped = Path::new("tests/assets/out/pack_flat");
+    if dumped.exists() {
+        fs::remove_dir_all(dumped)?;
+    }
+    dump(
+        &Some(input.into()),
+        &Some(dumped.into()),
+        &DumpOptions {
+            flatten: true,
+            ..Default::default()
+        },
+    )?;
+
+    let output = dumped.join("packed.esp");
+    let summary = pack(
+        &Some(dumped.into()),
+        &Some(output.clone()),
+        &PackOptions::default(),
+    )?;
+    assert_eq!(summary.records, Plugin::from_path(input)?.objects.len());
+    assert_eq!(Plugin::from_path(output)?.objects.len(), summary.records);
+    Ok(())
+}
+
+#[test]
+fn test_pack_dialogue() -> std::io::Result<()> {
+    // two topics with their responses in a flat folder, in file name order
+    let folder = Path::new("tests/assets/out/pack_dialogue");
+    if folder.exists() {
+        fs::remove_dir_all(folder)?;
+    }
+    fs::create_dir_all(folder)?;
+    let info = |id: &str| {
+        TES3Object::from(DialogueInfo {
+            id: id.into(),
+            ..Default::default()
+        })
+    };
+    let dial = |id: &str| {
+        TES3Object::from(Dialogue {
+            id: id.into(),
+            ..Default::default()
+        })
+    };
+    let records = [
+        TES3Object::from(Header::default()),
+        dial("zed"),
+        info("b"),
+        info("a"),
+        dial("apple"),
+        info("c"),
+    ];
+    for (i, record) in records.iter().enumerate() {
+        let text = serde_yaml::to_string(record).unwrap();
+        fs::write(folder.join(format!("{}.yaml", i)), text)?;
+    }
+
+    let output = folder.join("packed.esp");
+    pack(
+        &Some(folder.into()),
+        &Some(output.clone()),
+        &PackOptions::default(),
+    )?;
+    let ids: Vec<String> = Plugin::from_path(output)?
+        .objects
+        .iter()
+        .skip(1)
+        .map(|o| o.editor_id().to_string())
+        .collect();
+    assert_eq!(ids, ["apple", "c", "zed", "b", "a"]);
+    Ok(())
+}
+
+#[test]
+fn test_dump_id_filter() -> std::io::Result<()> {
+    let input = Path::new("tests/assets/Ashlander Crafting.ESP");
+    let output = Path::new("tests/assets/out/id_filter");
+    if output.exists() {
+        fs::remove_dir_all(output)?;
+    }
+    dump(
+        &Some(input.into()),
+        &Some(output.into()),
+        &DumpOptions {
+            id_include: Some("chitin".to_string()),
+            id_exclude: Some("_bracer_".to_string()),
+            ..Default::default()
+        },
+    )?;
+
+    let mut folders = vec![];
+    for folder in fs::read_dir(output)?.flatten() {
+        for file in fs::read_dir(folder.path())?.flatten() {
+            let name = file.file_name().to_string_lossy().to_string();
+            assert!(name.contains("chitin"));
+            assert!(!name.contains("_bracer_"));
+        }
+        folders.push(folder.file_name());
+    }
+    // armor, bodyparts, misc items and weapons
+    assert!(folders.len() > 1);
+    Ok(())
+}
+
+#[test]
+fn test_sql_load_order() {
+    let folder = Path::new("tests/assets/out/load_order");
+    if folder.exists() {
+        fs::remove_dir_all(folder).unwrap();
+    }
+    fs::create_dir_all(folder).unwrap();
+
+    // an empty plugin and a copy of the test plugin, modified one after the other
+    let first = folder.join("First.esp");
+    let mut plugin = Plugin::new();
+    plugin.objects.push(TES3Object::from(Header::default()));
+    plugin.save_path(&first).unwrap();
+    let second = folder.join("Second.esp");
+    fs::copy("tests/assets/Ashlander Crafting.ESP", &second).unwrap();
+
+    let now = SystemTime::now();
+    File::options()
+        .write(true)
+        .open(&first)
+        .unwrap()
+        .set_modified(now - Duration::from_secs(60))
+        .unwrap();
+    File::options()
+        .write(true)
+        .open(&second)
+        .unwrap()
+        .set_modified(now)
+        .unwrap();
+
+    let output = Path::new("tests/assets/out/load_order.db3");
+    sql_task::sql_task(
+        &Some(folder.into()),
+        &Some(output.into()),
+        &sql_task::SqlOptions {
+            overwrite: true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    let db = rusqlite::Connection::open(output).unwrap();
+    let mut stmt = db
+        .prepare("SELECT name, load_order FROM plugins ORDER BY load_order")
+        .unwrap();
+    let rows: Vec<(String, u32)> = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+        .unwrap()
+        .flatten()
+        .collect();
+    assert_eq!(
+        rows,
+        vec![("First.esp".to_string(), 0), ("Second.esp".to_string(), 1)]
+    );
+
+    // the mod columns are indexed
+    let indexes: usize = db
+        .query_row(
+            "SELECT COUNT(*) FROM sqlite_master WHERE type = 'index' AND name LIKE 'idx_%_mod'",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert!(indexes > 0);
+}
+
+#[test]
+fn test_sql_append() {
+    let input = Path::new("tests/assets/Ashlander Crafting.ESP");
+    let output = Path::new("tests/assets/out/append.db3");
+    fs::create_dir_all("tests/assets/out").unwrap();
+
+    let count_rows = || {
+        let db = rusqlite::Connection::open(output).unwrap();
+        let tables: Vec<String> = db
+            .prepare("SELECT name FROM sqlite_master WHERE type = 'table'")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .flatten()
+            .collect();
+        tables
+            .iter()
+            .map(|t| {
+                db.query_row(&format!("SELECT COUNT(*) FROM {}", t), [], |row| {
+                    row.get::<_, usize>(0)
+                })
+                .unwrap()
+            })
+            .sum::<usize>()
+    };
+
+    sql_task::sql_task(
+        &Some(input.into()),
+        &Some(output.into()),
+        &sql_task::SqlOptions {
+            overwrite: true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let built = count_rows();
+
+    // re-inserting the same plugin replaces its rows
+    sql_task::sql_task(
+        &Some(input.into()),
+        &Some(output.into()),
+        &sql_task::SqlOptions {
+            append: true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(count_rows(), built);
+}
+
+#[test]
+fn test_sql_append_override() {
+    let folder = Path::new("tests/assets/out/append_override");
+    if folder.exists() {
+        fs::remove_dir_all(folder).unwrap();
+    }
+    fs::create_dir_all(folder).unwrap();
+    // b.esp overrides every record of a.esp
+    let input = Path::new("tests/assets/Ashlander Crafting.ESP");
+    let first = folder.join("a.esp");
+    let second = folder.join("b.esp");
+    fs::copy(input, &first).unwrap();
+    fs::copy(input, &second).unwrap();
+    let now = SystemTime::now();
+    File::options()
+        .write(true)
+        .open(&first)
+        .unwrap()
+        .set_modified(now - Duration::from_secs(60))
+        .unwrap();
+    File::options()
+        .write(true)
+        .open(&second)
+        .unwrap()
+        .set_modified(now)
+        .unwrap();
+    let output = Path::new("tests/assets/out/append_override.db3");
+
+    let count_rows = |name: &str| {
+        let db = rusqlite::Connection::open(output).unwrap();
+        let tables: Vec<String> = db
+            .prepare(
+                "SELECT m.name FROM sqlite_master m WHERE m.type = 'table' AND m.name != 'plugins'
+                AND EXISTS (SELECT 1 FROM pragma_table_info(m.name) WHERE name = 'mod')",
+            )
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .flatten()
+            .collect();
+        tables
+            .iter()
+            .map(|t| {
+                db.query_row(
+                    &format!(
+                        "SELECT COUNT(*) FROM {t} JOIN plugins ON plugins.id = {t}.mod WHERE plugins.name = ?1"
+                    ),
+                    [name],
+                    |row| row.get::<_, usize>(0),
+                )
+                .unwrap()
+            })
+            .sum::<usize>()
+    };
+
+    sql_task::sql_task(
+        &Some(folder.into()),
+        &Some(output.into()),
+        &sql_task::SqlOptions {
+            overwrite: true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let built = count_rows("b.esp");
+    assert!(built > 0);
+
+    // appending the earlier plugin keeps the rows of the later one
+    sql_task::sql_task(
+        &Some(first),
+        &Some(output.into()),
+        &sql_task::SqlOptions {
+            append: true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(count_rows("b.esp"), built);
+}
+
+#[test]
+fn test_sql_dedup_report() -> std::io::Result<()> {
+    let folder = Path::new("tests/assets/out/dedup_report");
+    if folder.exists() {
+        fs::remove_dir_all(folder)?;
+    }
+    fs::create_dir_all(folder)?;
+    // the same plugin twice edits every record identically
+    let input = Path::new("tests/assets/Ashlander Crafting.ESP");
+    fs::copy(input, folder.join("a.esp"))?;
+    fs::copy(input, folder.join("b.esp"))?;
+    let output = Path::new("tests/assets/out/dedup_report.db3");
+
+    sql_task::sql_task(
+        &Some(folder.into()),
+        &Some(output.into()),
+        &sql_task::SqlOptions {
+            overwrite: true,
+            dedup_report: true,
+            ..Default::default()
+        },
+    )?;
+
+    let db = rusqlite::Connection::open(output).unwrap();
+    let (count, identical): (usize, usize) = db
+        .query_row(
+            "SELECT COUNT(*), SUM(identical) FROM _duplicates",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .unwrap();
+    assert!(count > 0);
+    assert_eq!(count, identical);
+    Ok(())
+}
+
+#[test]
+fn test_sql_strict() -> std::io::Result<()> {
+    // a plugin without records has no broken references
+    fs::create_dir_all("tests/assets/out")?;
+    let input = PathBuf::from("tests/assets/out/strict.esp");
+    let mut plugin = Plugin::new();
+    plugin.objects.push(TES3Object::from(Header::default()));
+    plugin.save_path(&input)?;
+
+    let output = Path::new("tests/assets/out/strict.db3");
+    sql_task::sql_task(
+        &Some(input.clone()),
+        &Some(output.into()),
+        &sql_task::SqlOptions {
+            overwrite: true,
+            strict: true,
+            ..Default::default()
+        },
+    )?;
+
+    // the records of a plugin whose row is missing break the mod references
+    let other = PathBuf::from("tests/assets/out/strict_other.esp");
+    plugin.objects.push(TES3Object::from(Static {
+        id: "strict_static".into(),
+        ..Default::default()
+    }));
+    plugin.save_path(&other)?;
+    sql_task::sql_task(
+        &Some(other),
+        &Some(output.into()),
+        &sql_task::SqlOptions {
+            append: true,
+            ..Default::default()
+        },
+    )?;
+    let db = rusqlite::Connection::open(output).unwrap();
+    db.execute("DELETE FROM plugins WHERE name = 'strict_other.esp'", [])
+        .unwrap();
+    drop(db);
+
+    let result = sql_task::sql_task(
+        &Some(input),
+        &Some(output.into()),
+        &sql_task::SqlOptions {
+            append: true,
+            strict: true,
+            ..Default::default()
+        },
+    );
+    assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+    Ok(())
+}
+
+#[test]
+fn test_empty_folder() -> std::io::Result<()> {
+    let folder = Path::new("tests/assets/out/empty");
+    fs::create_dir_all(folder)?;
+    let output = Path::new("tests/assets/out/empty.db3");
+    if output.exists() {
+        fs::remove_file(output)?;
+    }
+
+    let result = dump(
+        &Some(folder.into()),
+        &Some("tests/assets/out/empty_dump".into()),
+        &DumpOptions::default(),
+    );
+    assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::NotFound);
+
+    // no database is created
+    let result = sql_task::sql_task(
+        &Some(folder.into()),
+        &Some(output.into()),
+        &sql_task::SqlOptions::default(),
+    );
+    assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::NotFound);
+    assert!(!output.exists());
+    Ok(())
+}
+
+#[test]
+fn test_dialogue() -> std::io::Result<()> {
+    let input = PathBuf::from("tests/assets/Ashlander Crafting.ESP");
+    let output = PathBuf::from("tests/assets/out/dialogue.md");
+    fs::create_dir_all("tests/assets/out")?;
+    let topics = dialogue_task::dialogue(&Some(input.clone()), &Some(output.clone()))?;
+
+    // every topic gets a section
+    let plugin = Plugin::from_path(&input)?;
+    let dials = plugin
+        .objects
+        .iter()
+        .filter(|o| matches!(o, TES3Object::Dialogue(_)))
+        .count();
+    assert_eq!(topics, dials);
+    let text = fs::read_to_string(output)?;
+    assert_eq!(text.matches("\n## ").count(), dials);
+    Ok(())
+}
+
+#[test]
+fn test_sql_readonly() -> std::io::Result<()> {
+    let input = PathBuf::from("tests/assets/Ashlander Crafting.ESP");
+    let output = PathBuf::from("tests/assets/out/readonly.db3");
+    fs::create_dir_all("tests/assets/out")?;
+    sql_task::sql_task(
+        &Some(input),
+        &Some(output.clone()),
+        &sql_task::SqlOptions {
+            overwrite: true,
+            readonly: true,
+            ..Default::default()
+        },
+    )?;
+
+    // no write-ahead log is left next to the database
+    assert!(!PathBuf::from("tests/assets/out/readonly.db3-wal").exists());
+    let db = rusqlite::Connection::open(&output).unwrap();
+    let mode: String = db
+        .query_row("PRAGMA journal_mode", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(mode, "delete");
+    Ok(())
+}
+
+#[test]
+fn test_ref_count() -> std::io::Result<()> {
+    // a cell placing a record of the plugin twice and a missing record once
+    fs::create_dir_all("tests/assets/out")?;
+    let input = PathBuf::from("tests/assets/out/ref_count.esp");
+    let mut plugin = Plugin::new();
+    plugin.objects.push(TES3Object::from(Header::default()));
+    plugin.objects.push(TES3Object::from(Static {
+        id: "placed_static".into(),
+        ..Default::default()
+    }));
+    let mut cell = Cell::default();
+    for (i, id) in ["placed_static", "Placed_Static", "missing"]
+        .iter()
+        .enumerate()
+    {
+        cell.references.insert(
+            (0, i as u32 + 1),
+            Reference {
+                id: id.to_string(),
+                ..Default::default()
+            },
+        );
+    }
+    plugin.objects.push(TES3Object::from(cell));
+    plugin.save_path(&input)?;
+
+    let unresolved = stats_task::ref_count(&Some(input), &None)?;
+    assert_eq!(unresolved, 1);
+    Ok(())
+}
+
+#[test]
+fn test_normalize_ids() -> std::io::Result<()> {
+    // a record, and a cell reference and a script using it with another casing
+    fs::create_dir_all("tests/assets/out")?;
+    let input = PathBuf::from("tests/assets/out/normalize.esp");
+    let mut plugin = Plugin::new();
+    plugin.objects.push(TES3Object::from(Header::default()));
+    plugin.objects.push(TES3Object::from(Static {
+        id: "Some_Static".into(),
+        ..Default::default()
+    }));
+    let mut cell = Cell::default();
+    cell.references.insert(
+        (0, 1),
+        Reference {
+            id: "SOME_STATIC".into(),
+            ..Default::default()
+        },
+    );
+    plugin.objects.push(TES3Object::from(cell));
+    plugin.objects.push(TES3Object::from(Script {
+        id: "normalize_script".into(),
+        text: "Begin normalize_script\nPlaceAtPC \"SOME_STATIC\" 1 0 0\nEnd".into(),
+        ..Default::default()
+    }));
+    plugin.save_path(&input)?;
+    let script_text = |plugin: &Plugin| {
+        plugin
+            .objects
+            .iter()
+            .find_map(|o| match o {
+                TES3Object::Script(s) => Some(s.text.clone()),
+                _ => None,
+            })
+            .unwrap_or_default()
+    };
+
+    let output = PathBuf::from("tests/assets/out/normalized.esp");
+    let (records, references) =
+        rename_task::normalize_ids(&Some(input.clone()), &Some(output.clone()), false, false)?;
+    assert_eq!((records, references), (1, 1));
+    let plugin = Plugin::from_path(&output)?;
+    assert!(plugin
+        .objects
+        .iter()
+        .any(|o| o.editor_id() == "some_static"));
+    assert!(script_text(&plugin).contains("\"some_static\""));
+
+    // the first casing is kept with preserve, so only the reference and script change
+    let (records, references) =
+        rename_task::normalize_ids(&Some(input), &Some(output.clone()), true, false)?;
+    assert_eq!((records, references), (0, 1));
+    let plugin = Plugin::from_path(&output)?;
+    assert!(script_text(&plugin).contains("\"Some_Static\""));
+    Ok(())
+}
+
+#[test]
+fn test_sql_schema_only() -> std::io::Result<()> {
+    fs::create_dir_all("tests/assets/out")?;
+    let schema = Path::new("tests/assets/out/schema.sql");
+    let output = Path::new("tests/assets/out/schema_only.db3");
+    if output.exists() {
+        fs::remove_file(output)?;
+    }
+
+    sql_task::sql_task(
+        &None,
+        &Some(output.into()),
+        &sql_task::SqlOptions {
+            schema_only: Some(schema.into()),
+            ..Default::default()
+        },
+    )?;
+
+    let text = fs::read_to_string(schema)?;
+    assert!(text.contains("CREATE TABLE IF NOT EXISTS plugins"));
+    assert!(!output.exists());
+    Ok(())
+}
+
+#[test]
+fn test_read_load_order() -> std::io::Result<()> {
+    fs::create_dir_all("tests/assets/out")?;
+    let ini = Path::new("tests/assets/out/Morrowind.ini");
+    fs::write(
+        ini,
+        "[Game Files]\nGameFile1=Tribunal.esm\nGameFile0=Morrowind.esm\n",
+    )?;
+    assert_eq!(
+        tes3util::read_load_order(ini)?,
+        vec!["Morrowind.esm", "Tribunal.esm"]
+    );
+
+    let cfg = Path::new("tests/assets/out/openmw.cfg");
+    fs::write(
+        cfg,
+        "data=\"Data Files\"\ncontent=Morrowind.esm\ncontent=Tribunal.esm\n",
+    )?;
+    assert_eq!(
+        tes3util::read_load_order(cfg)?,
+        vec!["Morrowind.esm", "Tribunal.esm"]
+    );
+    Ok(())
 }
 
 #[test]
 fn test_atlas_coverage() -> std::io::Result<()> {
     let input = Path::new("tests/assets");
     let output = Path::new("tests/assets/out");
-    tes3util::atlas_coverage(&Some(input.into()), &Some(output.into()))
+    tes3util::atlas_coverage(
+        &Some(input.into()),
+        &Some(output.into()),
+        &tes3util::AtlasOptions::default(),
+    )?;
+    assert!(output.join("texture_usage.yaml").exists());
+    let stats = fs::read_to_string(output.join("atlas_coverage_stats.yaml"))?;
+    assert!(stats.contains("reference_coverage"));
+    assert!(stats.contains("internal_meshes"));
+
+    tes3util::atlas_coverage(
+        &Some(input.into()),
+        &Some(output.into()),
+        &tes3util::AtlasOptions {
+            exclude_internal: true,
+            ..Default::default()
+        },
+    )?;
+    let coverage = fs::read_to_string(output.join("atlas_coverage.yaml"))?;
+    assert!(!coverage.contains("- internal"));
+    Ok(())
+}
+
+#[test]
+fn test_merge_one_record_per_id() -> std::io::Result<()> {
+    let folder = Path::new("tests/assets/out/merge");
+    fs::create_dir_all(folder)?;
+
+    // a patch overriding every record of the base plugin, with the base as master
+    let base = folder.join("Base.esp");
+    fs::copy("tests/assets/Ashlander Crafting.ESP", &base)?;
+    let original = Plugin::from_path(&base)?;
+    let mut patch = Plugin::new();
+    patch.objects.push(TES3Object::from(Header {
+        masters: vec![("Base.esp".to_string(), 0)],
+        ..Default::default()
+    }));
+    patch.objects.extend(
+        original
+            .objects
+            .iter()
+            .filter(|o| !matches!(o, TES3Object::Header(_)))
+            .cloned(),
+    );
+    let patch_path = folder.join("Patch.esp");
+    patch.save_path(&patch_path)?;
+
+    for first_wins in [false, true] {
+        let output = folder.join("Merged.esp");
+        merge_task::merge(
+            &[base.clone(), patch_path.clone()],
+            &Some(output.clone()),
+            false,
+            first_wins,
+            false,
+        )?;
+
+        let merged = Plugin::from_path(&output)?;
+        let mut ids = std::collections::HashSet::new();
+        for object in &merged.objects {
+            let key = (object.tag_str(), object.editor_id().to_lowercase());
+            assert!(ids.insert(key), "duplicate record");
+        }
+
+        // the merged base is no longer a master
+        let Some(TES3Object::Header(header)) = merged.objects.first() else {
+            panic!("missing header");
+        };
+        assert!(header.masters.is_empty());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_merge_dialogue_interleaved() -> std::io::Result<()> {
+    let folder = Path::new("tests/assets/out/merge_dialogue");
+    fs::create_dir_all(folder)?;
+    let plugin = |infos: &[&str]| {
+        let mut plugin = Plugin::new();
+        plugin.objects.push(TES3Object::from(Header::default()));
+        plugin.objects.push(TES3Object::from(Dialogue {
+            id: "greeting".into(),
+            ..Default::default()
+        }));
+        for id in infos {
+            plugin.objects.push(TES3Object::from(DialogueInfo {
+                id: id.to_string(),
+                text: format!("response {}", id),
+                ..Default::default()
+            }));
+        }
+        plugin
+    };
+
+    // the second plugin adds responses between and after the ones of the first
+    let first = folder.join("First.esp");
+    plugin(&["a1", "a2"]).save_path(&first)?;
+    let second = folder.join("Second.esp");
+    plugin(&["a1", "b1", "a2", "b2"]).save_path(&second)?;
+
+    let output = folder.join("Merged.esp");
+    merge_task::merge(&[first, second], &Some(output.clone()), true, false, false)?;
+
+    let merged = Plugin::from_path(&output)?;
+    let ids: Vec<&str> = merged
+        .objects
+        .iter()
+        .filter_map(|o| match o {
+            TES3Object::DialogueInfo(info) => Some(info.id.as_str()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(ids, vec!["a1", "b1", "a2", "b2"]);
+    Ok(())
+}
+
+#[test]
+fn test_clean_remove() -> std::io::Result<()> {
+    fs::create_dir_all("tests/assets/out")?;
+    let input = Path::new("tests/assets/Ashlander Crafting.ESP");
+    let output = Path::new("tests/assets/out/Ashlander Crafting_clean.ESP");
+    clean_task::clean(
+        &Some(input.into()),
+        &Some(output.into()),
+        &["scpt".to_string(), "GLOB".to_string()],
+        &None,
+        false,
+    )?;
+
+    let cleaned = Plugin::from_path(output)?;
+    assert!(matches!(
+        cleaned.objects.first(),
+        Some(TES3Object::Header(_))
+    ));
+    assert!(!cleaned
+        .objects
+        .iter()
+        .any(|o| o.tag_str() == "SCPT" || o.tag_str() == "GLOB"));
+    Ok(())
+}
+
+#[test]
+fn test_clean_identical_to_master() -> std::io::Result<()> {
+    fs::create_dir_all("tests/assets/out")?;
+    let input = Path::new("tests/assets/Ashlander Crafting.ESP");
+    let output = Path::new("tests/assets/out/Ashlander Crafting_identical.ESP");
+    clean_task::clean(
+        &Some(input.into()),
+        &Some(output.into()),
+        &[],
+        &Some(input.into()),
+        false,
+    )?;
+
+    // every record with an id is identical to itself
+    let cleaned = Plugin::from_path(output)?;
+    assert!(cleaned
+        .objects
+        .iter()
+        .all(|o| { matches!(o, TES3Object::Header(_)) || o.editor_id().is_empty() }));
+    Ok(())
+}
+
+/// Serialize a plugin to the format and deserialize it back.
+/// Panics with the first differing record if the round-tripped plugin doesn't match
+fn assert_round_trip(input: &Path, format: ESerializedType) -> std::io::Result<()> {
+    let folder = Path::new("tests/assets/out/round_trip");
+    fs::create_dir_all(folder)?;
+    let name = input.file_name().unwrap().to_string_lossy().into_owned();
+
+    let serialized_stem = folder.join(&name);
+    serialize_plugin(
+        &Some(input.into()),
+        &Some(serialized_stem.clone()),
+        &SerializeOptions {
+            format: Some(format.clone()),
+            ..Default::default()
+        },
+    )?;
+    let serialized = PathBuf::from(format!("{}.{}", serialized_stem.display(), format));
+    let output = folder.join(format!("{}.{}.esp", name, format));
+    deserialize_plugin(
+        &Some(serialized),
+        &Some(output.clone()),
+        false,
+        &None,
+        false,
+    )?;
+
+    let original = Plugin::from_path(input)?;
+    let round_trip = Plugin::from_path(&output)?;
+    for (a, b) in original.objects.iter().zip(&round_trip.objects) {
+        assert_eq!(
+            serde_json::to_string(a)?,
+            serde_json::to_string(b)?,
+            "{} round trip differs at {} {}",
+            format,
+            a.tag_str(),
+            a.editor_id()
+        );
+    }
+    assert_eq!(
+        original.objects.len(),
+        round_trip.objects.len(),
+        "{} round trip differs in record count",
+        format
+    );
+    Ok(())
+}
+
+#[test]
+fn test_round_trip() -> std::io::Result<()> {
+    let input = Path::new("tests/assets/Ashlander Crafting.ESP");
+    for format in [
+        ESerializedType::Yaml,
+        ESerializedType::Toml,
+        ESerializedType::Json,
+        ESerializedType::Msgpack,
+        ESerializedType::Jsonl,
+    ] {
+        assert_round_trip(input, format)?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_export_csv() -> std::io::Result<()> {
+    let input = Path::new("tests/assets/Ashlander Crafting.ESP");
+    let output = Path::new("tests/assets/out/csv");
+    csv_task::export_csv(
+        &Some(input.into()),
+        &Some(output.into()),
+        &["weap".to_string()],
+    )?;
+
+    // a header row and one row per weapon
+    let text = fs::read_to_string(output.join("WEAP.csv"))?;
+    assert_eq!(text.lines().count(), 17);
+    assert!(!output.join("ARMO.csv").exists());
+    Ok(())
+}
+
+#[test]
+fn test_masters() {
+    let mut plugin = Plugin::new();
+    plugin.objects.push(TES3Object::from(Header {
+        masters: vec![
+            ("Morrowind.esm".to_string(), 79837557),
+            ("Tribunal.esm".to_string(), 4565686),
+        ],
+        ..Default::default()
+    }));
+    assert_eq!(
+        masters_task::get_masters(&plugin),
+        vec![
+            ("Morrowind.esm".to_string(), 79837557),
+            ("Tribunal.esm".to_string(), 4565686)
+        ]
+    );
+}
+
+#[test]
+fn test_remaster() -> std::io::Result<()> {
+    let folder = Path::new("tests/assets/out/remaster");
+    fs::create_dir_all(folder)?;
+    let input = folder.join("Patch.esp");
+    let mut plugin = Plugin::new();
+    plugin.objects.push(TES3Object::from(Header {
+        masters: vec![("Old.esm".to_string(), 1)],
+        ..Default::default()
+    }));
+    plugin.save_path(&input)?;
+    fs::write(folder.join("New.esm"), [0u8; 16])?;
+
+    let to = vec!["New.esm".to_string()];
+    remaster_task::remaster(
+        &Some(input.clone()),
+        &None,
+        &["old.esm".to_string()],
+        &to,
+        true,
+        false,
+    )?;
+    let plugin = Plugin::from_path(&input)?;
+    assert_eq!(
+        masters_task::get_masters(&plugin),
+        vec![("New.esm".to_string(), 16)]
+    );
+
+    // a missing master is an error
+    assert!(remaster_task::remaster(
+        &Some(input),
+        &None,
+        &["Old.esm".to_string()],
+        &to,
+        false,
+        false
+    )
+    .is_err());
+    Ok(())
+}
+
+#[test]
+fn test_dedupe_keeps_last() -> std::io::Result<()> {
+    let mut plugin = Plugin::from_path("tests/assets/Ashlander Crafting.ESP")?;
+    let record = plugin
+        .objects
+        .iter()
+        .find(|o| o.tag_str() != "TES3" && !o.editor_id().is_empty())
+        .cloned()
+        .unwrap();
+    let (tag, id) = (record.tag_str(), record.editor_id().to_string());
+    plugin.objects.push(record.clone());
+
+    assert_eq!(dedupe_objects(&mut plugin.objects), 1);
+    let matching: Vec<usize> = plugin
+        .objects
+        .iter()
+        .enumerate()
+        .filter(|(_, o)| o.tag_str() == tag && o.editor_id() == id)
+        .map(|(i, _)| i)
+        .collect();
+    // the duplicate at the end survives
+    assert_eq!(matching, vec![plugin.objects.len() - 1]);
+    Ok(())
+}
+
+#[test]
+fn test_dry_run_writes_nothing() -> std::io::Result<()> {
+    let input = Path::new("tests/assets/Ashlander Crafting.ESP");
+    let output = Path::new("tests/assets/out/dry_run.esp");
+    if output.exists() {
+        fs::remove_file(output)?;
+    }
+    clean_task::clean(
+        &Some(input.into()),
+        &Some(output.into()),
+        &["SCPT".to_string()],
+        &None,
+        true,
+    )?;
+    assert!(!output.exists());
+    Ok(())
+}
+
+#[test]
+fn test_scripts_round_trip() -> std::io::Result<()> {
+    let input = Path::new("tests/assets/Ashlander Crafting.ESP");
+    let folder = Path::new("tests/assets/out/scripts");
+    let plugin_path = folder.join("Scripts.esp");
+    fs::create_dir_all(folder)?;
+    fs::copy(input, &plugin_path)?;
+
+    let scripts_dir = folder.join("mwscript");
+    let count =
+        scripts_task::extract_scripts(&Some(plugin_path.clone()), &Some(scripts_dir.clone()))?;
+    assert!(count > 0);
+
+    // edit one script and inject it back
+    let file = fs::read_dir(&scripts_dir)?.flatten().next().unwrap().path();
+    let text = format!("; edited\n{}", fs::read_to_string(&file)?);
+    fs::write(&file, &text)?;
+    let updated =
+        scripts_task::inject_scripts(&Some(plugin_path.clone()), &scripts_dir, &None, false)?;
+    assert_eq!(updated, 1);
+
+    let id = file.file_stem().unwrap().to_string_lossy().to_lowercase();
+    let plugin = Plugin::from_path(&plugin_path)?;
+    assert!(plugin.objects.iter().any(|o| matches!(
+        o,
+        TES3Object::Script(s) if s.id.to_lowercase() == id && s.text == text
+    )));
+    Ok(())
+}
+
+#[test]
+fn test_convert() -> std::io::Result<()> {
+    let input = Path::new("tests/assets/Ashlander Crafting.ESP");
+    let folder = Path::new("tests/assets/out/convert");
+    fs::create_dir_all(folder)?;
+    let yaml = folder.join("plugin.yaml");
+    serialize_plugin(
+        &Some(input.into()),
+        &Some(yaml.clone()),
+        &SerializeOptions {
+            ..Default::default()
+        },
+    )?;
+
+    let json = folder.join("plugin.json");
+    let count = convert_task::convert(&Some(yaml), &Some(json.clone()), &None, &None)?;
+    let plugin: Plugin = serde_json::from_str(&fs::read_to_string(json)?).unwrap();
+    assert_eq!(count, plugin.objects.len());
+    assert_eq!(count, Plugin::from_path(input)?.objects.len());
+    Ok(())
+}
+
+#[test]
+fn test_split_by_tag() -> std::io::Result<()> {
+    let input = Path::new("tests/assets/Ashlander Crafting.ESP");
+    let folder = Path::new("tests/assets/out/split");
+    if folder.exists() {
+        fs::remove_dir_all(folder)?;
+    }
+    fs::create_dir_all(folder)?;
+
+    let plugin = Plugin::from_path(input)?;
+    let tag = plugin.objects[1].tag_str();
+    split_task::split(
+        &Some(input.into()),
+        &Some(folder.into()),
+        &[tag.to_string()],
+        false,
+    )?;
+
+    let split = Plugin::from_path(folder.join(format!("Ashlander Crafting_{}.esp", tag)))?;
+    let rest = Plugin::from_path(folder.join("Ashlander Crafting_rest.esp"))?;
+    // every plugin has a header and no record is lost
+    assert!(matches!(split.objects[0], TES3Object::Header(_)));
+    assert!(matches!(rest.objects[0], TES3Object::Header(_)));
+    assert!(split.objects[1..].iter().all(|o| o.tag_str() == tag));
+    assert_eq!(
+        split.objects.len() + rest.objects.len(),
+        plugin.objects.len() + 1
+    );
+    Ok(())
+}
+
+#[test]
+fn test_get() -> std::io::Result<()> {
+    let input = PathBuf::from("tests/assets/Ashlander Crafting.ESP");
+    let plugin = Plugin::from_path(&input)?;
+    let id = plugin
+        .objects
+        .iter()
+        .map(|o| o.editor_id().to_string())
+        .find(|id| !id.is_empty())
+        .unwrap();
+
+    assert!(get_task::get(&Some(input.clone()), &id.to_uppercase(), &None, &None)? >= 1);
+    assert!(get_task::get(&Some(input), "no_such_record", &None, &None).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_set_gmst() -> std::io::Result<()> {
+    fs::create_dir_all("tests/assets/out")?;
+    let config = Path::new("tests/assets/out/gmst.ini");
+    fs::write(config, "; balance\nfPickLockMult = -2.5\n")?;
+    let output = PathBuf::from("tests/assets/out/gmst.esp");
+
+    let count = gmst_task::set_gmst(
+        &Some("tests/assets/Ashlander Crafting.ESP".into()),
+        &Some(output.clone()),
+        &Some(config.into()),
+        &["iMaxLevel=100".to_owned(), "my_global=3".to_owned()],
+        false,
+    )?;
+    assert_eq!(count, 3);
+
+    let plugin = Plugin::from_path(&output)?;
+    let settings: Vec<_> = plugin
+        .objects
+        .iter()
+        .filter(|o| matches!(o, TES3Object::GameSetting(_)))
+        .map(|o| o.editor_id().to_lowercase())
+        .collect();
+    assert!(settings.contains(&"fpicklockmult".to_owned()));
+    assert!(settings.contains(&"imaxlevel".to_owned()));
+    assert!(plugin
+        .objects
+        .iter()
+        .any(|o| matches!(o, TES3Object::GlobalVariable(g) if g.id == "my_global")));
+    Ok(())
+}
+
+#[test]
+fn test_stats_from_archive() -> std::io::Result<()> {
+    fs::create_dir_all("tests/assets/out")?;
+    let archive = Path::new("tests/assets/out/mod.zip");
+    let mut writer = zip::ZipWriter::new(File::create(archive)?);
+    writer.start_file(
+        "Data Files/Plugin.esp",
+        zip::write::SimpleFileOptions::default(),
+    )?;
+    writer.write_all(&fs::read("tests/assets/Ashlander Crafting.ESP")?)?;
+    writer.finish()?;
+
+    // entries are matched case-insensitively
+    let input = PathBuf::from("tests/assets/out/mod.zip!data files/plugin.esp");
+    stats_task::stats(&Some(input), Some(5), &None, false)?;
+    assert!(stats_task::stats(
+        &Some("tests/assets/out/missing.zip!plugin.esp".into()),
+        Some(5),
+        &None,
+        false
+    )
+    .is_err());
+    Ok(())
+}
+
+#[test]
+fn test_pack_manifest() -> std::io::Result<()> {
+    let input = Path::new("tests/assets/Ashlander Crafting.ESP");
+    let dumped = PathBuf::from("tests/assets/out/manifest");
+    if dumped.exists() {
+        fs::remove_dir_all(&dumped)?;
+    }
+    dump(
+        &Some(input.into()),
+        &Some(dumped.clone()),
+        &DumpOptions {
+            manifest: true,
+            ..Default::default()
+        },
+    )?;
+    let manifest = fs::read_to_string(dumped.join("manifest.yaml"))?;
+    assert!(manifest.contains("Ashlander Crafting.ESP"));
+
+    // the manifest order is the original record order
+    let output = dumped.join("packed.esp");
+    pack(
+        &Some(dumped.clone()),
+        &Some(output.clone()),
+        &PackOptions {
+            use_manifest: true,
+            ..Default::default()
+        },
+    )?;
+    let original: Vec<String> = Plugin::from_path(input)?
+        .objects
+        .iter()
+        .map(|o| o.tag_str().to_owned())
+        .collect();
+    let packed: Vec<String> = Plugin::from_path(&output)?
+        .objects
+        .iter()
+        .map(|o| o.tag_str().to_owned())
+        .collect();
+    assert_eq!(packed, original);
+
+    // files listed in the manifest must exist
+    let header = fs::read_dir(dumped.join("Header"))?
+        .flatten()
+        .next()
+        .unwrap()
+        .path();
+    fs::remove_file(header)?;
+    assert!(pack(
+        &Some(dumped),
+        &Some(output),
+        &PackOptions {
+            use_manifest: true,
+            ..Default::default()
+        }
+    )
+    .is_err());
+    Ok(())
+}
+
+#[test]
+fn test_script_stats() -> std::io::Result<()> {
+    let input = PathBuf::from("tests/assets/Ashlander Crafting.ESP");
+    let scripts = Plugin::from_path(&input)?
+        .objects
+        .iter()
+        .filter(|o| matches!(o, TES3Object::Script(_)))
+        .count();
+
+    // every script is larger than zero bytes
+    assert_eq!(
+        stats_task::script_stats(&Some(input.clone()), 0, &None)?,
+        scripts
+    );
+    assert_eq!(
+        stats_task::script_stats(&Some(input), usize::MAX, &Some(ESerializedType::Json))?,
+        0
+    );
+    Ok(())
+}
+
+#[test]
+fn test_assets() -> std::io::Result<()> {
+    fs::create_dir_all("tests/assets/out")?;
+    let output = PathBuf::from("tests/assets/out/assets.txt");
+    let count = assets_task::assets(
+        &Some("tests/assets/Ashlander Crafting.ESP".into()),
+        &Some(output.clone()),
+        &None,
+    )?;
+
+    let lines: Vec<String> = fs::read_to_string(output)?
+        .lines()
+        .map(str::to_owned)
+        .collect();
+    assert_eq!(lines.len(), count);
+    let mut sorted = lines.clone();
+    sorted.sort();
+    assert_eq!(lines, sorted);
+    assert!(lines.iter().all(|l| !l.contains('\\')));
+    Ok(())
+}
+
+#[test]
+fn test_pack_master() -> std::io::Result<()> {
+    let input = Path::new("tests/assets/Ashlander Crafting.ESP");
+    let dumped = PathBuf::from("tests/assets/out/pack_master");
+    if dumped.exists() {
+        fs::remove_dir_all(&dumped)?;
+    }
+    dump(
+        &Some(input.into()),
+        &Some(dumped.clone()),
+        &DumpOptions::default(),
+    )?;
+
+    // the default output is named after the folder
+    let summary = pack(
+        &Some(dumped.clone()),
+        &None,
+        &PackOptions {
+            master: true,
+            ..Default::default()
+        },
+    )?;
+    assert_eq!(summary.outputs, vec![dumped.join("pack_master.esm")]);
+    let plugin = Plugin::from_path(dumped.join("pack_master.esm"))?;
+    assert!(matches!(
+        plugin.objects.first(),
+        Some(TES3Object::Header(h)) if matches!(h.file_type, tes3::esp::FileType::Esm)
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_diff_against_binary_unknown_format() -> std::io::Result<()> {
+    fs::create_dir_all("tests/assets/out")?;
+    let input = PathBuf::from("tests/assets/out/edited.txt");
+    fs::write(&input, "not a plugin")?;
+    let original = PathBuf::from("tests/assets/Ashlander Crafting.ESP");
+    let result = tes3util::diff_task::diff_against_binary(&Some(input), &original, &None, &None);
+    assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+    Ok(())
+}
+
+#[test]
+fn test_verify() -> std::io::Result<()> {
+    let input = PathBuf::from("tests/assets/Ashlander Crafting.ESP");
+    let dumped = PathBuf::from("tests/assets/out/verify");
+    if dumped.exists() {
+        fs::remove_dir_all(&dumped)?;
+    }
+    dump(
+        &Some(input.clone()),
+        &Some(dumped.clone()),
+        &DumpOptions {
+            manifest: true,
+            ..Default::default()
+        },
+    )?;
+    assert!(!verify_task::verify(
+        &Some(input.clone()),
+        &Some(dumped.clone())
+    )?);
+
+    // swapped records are reported
+    let manifest_path = dumped.join("manifest.yaml");
+    let manifest = fs::read_to_string(&manifest_path)?;
+    let mut swapped: serde_yaml::Value = serde_yaml::from_str(&manifest).unwrap();
+    swapped["files"].as_sequence_mut().unwrap().swap(1, 2);
+    fs::write(&manifest_path, serde_yaml::to_string(&swapped).unwrap())?;
+    assert!(verify_task::verify(
+        &Some(input.clone()),
+        &Some(dumped.clone())
+    )?);
+    fs::remove_file(&manifest_path)?;
+
+    // a missing record is reported
+    let header = fs::read_dir(dumped.join("Header"))?
+        .flatten()
+        .next()
+        .unwrap()
+        .path();
+    let record_dir = fs::read_dir(&dumped)?
+        .flatten()
+        .map(|e| e.path())
+        .find(|p| p.is_dir() && *p != header.parent().unwrap())
+        .unwrap();
+    let record = fs::read_dir(record_dir)?
+        .flatten()
+        .map(|e| e.path())
+        .find(|p| p.extension().is_some_and(|e| e == "yaml"))
+        .unwrap();
+    fs::remove_file(record)?;
+    assert!(verify_task::verify(&Some(input), &Some(dumped))?);
+    Ok(())
+}
+
+#[test]
+fn test_cells() -> std::io::Result<()> {
+    let input = PathBuf::from("tests/assets/Ashlander Crafting.ESP");
+    let output = PathBuf::from("tests/assets/out/cells.esp");
+    fs::create_dir_all("tests/assets/out")?;
+
+    // an empty range has no cells but keeps the header
+    let count = cells_task::cells(
+        &Some(input),
+        &Some(output.clone()),
+        &cells_task::CellsOptions {
+            min_x: Some(1),
+            max_x: Some(0),
+            land: true,
+            references: true,
+            ..Default::default()
+        },
+        false,
+    )?;
+    assert_eq!(count, 0);
+    let plugin = Plugin::from_path(&output)?;
+    assert_eq!(plugin.objects.len(), 1);
+    assert!(matches!(plugin.objects[0], TES3Object::Header(_)));
+    Ok(())
+}
+
+#[test]
+fn test_rename() -> std::io::Result<()> {
+    // two swapped statics referenced by a cell and a script
+    fs::create_dir_all("tests/assets/out")?;
+    let input = PathBuf::from("tests/assets/out/rename.esp");
+    let mut plugin = Plugin::new();
+    plugin.objects.push(TES3Object::from(Header::default()));
+    for id in ["static_a", "static_b"] {
+        plugin.objects.push(TES3Object::from(Static {
+            id: id.into(),
+            ..Default::default()
+        }));
+    }
+    let mut cell = Cell::default();
+    cell.references.insert(
+        (0, 1),
+        Reference {
+            id: "Static_A".into(),
+            ..Default::default()
+        },
+    );
+    plugin.objects.push(TES3Object::from(cell));
+    plugin.objects.push(TES3Object::from(Script {
+        id: "place_script".into(),
+        text: "Begin place_script\nplaceatpc static_a 1 0 0\nplaceatpc STATIC_B 1 0 0\nEnd".into(),
+        ..Default::default()
+    }));
+    plugin.save_path(&input)?;
+
+    let mapping = Path::new("tests/assets/out/rename.csv");
+    fs::write(mapping, "STATIC_A,static_b\nstatic_b,static_a\n")?;
+    let output = PathBuf::from("tests/assets/out/renamed.esp");
+    let (records, references) =
+        rename_task::rename(&Some(input), &Some(output.clone()), mapping, false)?;
+    assert_eq!((records, references), (2, 3));
+
+    let plugin = Plugin::from_path(&output)?;
+    for object in &plugin.objects {
+        match object {
+            TES3Object::Cell(cell) => {
+                assert_eq!(cell.references[&(0, 1)].id, "static_b");
+            }
+            TES3Object::Script(script) => assert_eq!(
+                script.text,
+                "Begin place_script\nplaceatpc static_b 1 0 0\nplaceatpc static_a 1 0 0\nEnd"
+            ),
+            _ => {}
+        }
+    }
+    Ok(())
 }
@@ -1,4 +1,4 @@
-use tes3::esp::Plugin;
+use tes3::esp::{Plugin, TypeInfo};
 
 #[test]
 fn test_serialize_to_yaml() -> std::io::Result<()> {
@@ -40,3 +40,111 @@ fn test_deserialize_from_json() {
     let deserialized: Result<Plugin, _> = serde_json::from_str(&text);
     assert!(deserialized.is_ok());
 }
+
+#[test]
+fn test_sort_objects() {
+    let mut plugin = Plugin::from_path("tests/assets/Ashlander Crafting.ESP").unwrap();
+    let count = plugin.objects.len();
+    tes3util::sort_objects(&mut plugin.objects);
+    assert_eq!(plugin.objects.len(), count);
+    assert_eq!(plugin.objects[0].tag_str(), "TES3");
+}
+
+#[test]
+fn test_create_from_unknown_tag() {
+    assert!(tes3util::create_from_tag("GMST").is_some());
+    assert!(tes3util::create_from_tag("XXXX").is_none());
+    assert_eq!(
+        tes3util::ERecordType::try_from("XXXX"),
+        Err(tes3util::UnknownTagError(String::from("XXXX")))
+    );
+}
+
+#[test]
+fn test_lint_valid_script() {
+    let text = "Begin test_script\nshort done ; a comment\n\nif ( done == 0 )\n    set done to 1\nelse\n    MessageBox \"done; really\"\nendif\nEnd test_script\n";
+    assert!(tes3util::script_lint::lint_script(text).is_empty());
+}
+
+#[test]
+fn test_lint_unbalanced_blocks() {
+    let text =
+        "Begin test_script\nif ( GetJournalIndex A1_1 > 0 )\nwhile ( x < 1 )\nendif\nset x 1\n";
+    let lines: Vec<usize> = tes3util::script_lint::lint_script(text)
+        .iter()
+        .map(|w| w.line)
+        .collect();
+    // endif closes a while, set without to, the if and while are never closed, no End
+    assert_eq!(lines, vec![4, 5, 2, 3, 5]);
+}
+
+#[test]
+fn test_lint_statement_after_end() {
+    let warnings = tes3util::script_lint::lint_script("Begin a\nEnd\nset x to 1\nEnd\n");
+    assert_eq!(warnings.len(), 2);
+    assert_eq!(warnings[0].line, 3);
+    assert_eq!(warnings[1].message, "Duplicate End");
+}
+
+#[test]
+fn test_lint_statement_keywords() {
+    let text = "Begin test_script\nif(x == 1)\n    player->AddItem \"gold_001\" 10\nelseif(x == 2)\n    \"fargoth\"->ModDisposition 5\n    Frobnicate 1\nendif\nEnd\n";
+    let warnings = tes3util::script_lint::lint_script(text);
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(warnings[0].line, 6);
+    assert_eq!(warnings[0].message, "Unknown statement keyword: frobnicate");
+}
+
+#[test]
+fn test_lint_vanilla_functions() {
+    let text = "Begin moon_script\nDontSaveObject\nif ( GetPCCell \"Vivec\" == 1 )\n    TurnMoonRed\nelse\n    TurnMoonWhite\nendif\nEnd\n";
+    assert!(tes3util::script_lint::lint_script(text).is_empty());
+}
+
+#[test]
+fn test_serialize_object() -> std::io::Result<()> {
+    let plugin = Plugin::from_path("tests/assets/Ashlander Crafting.ESP")?;
+    let object = &plugin.objects[0];
+    let text = tes3util::serialize_object(object, &tes3util::ESerializedType::Json)?;
+    let deserialized: tes3::esp::TES3Object = serde_json::from_str(&text).unwrap();
+    assert_eq!(deserialized.tag_str(), object.tag_str());
+    assert!(tes3util::serialize_object(object, &tes3util::ESerializedType::Msgpack).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_yaml_record_round_trip() -> std::io::Result<()> {
+    // every record, including externally tagged enums like GMST values,
+    // reads back to the same document it was written from
+    let plugin = Plugin::from_path("tests/assets/Ashlander Crafting.ESP")?;
+    for object in &plugin.objects {
+        let text = tes3util::serialize_object(object, &tes3util::ESerializedType::Yaml)?;
+        let deserialized: tes3::esp::TES3Object = serde_yaml::from_str(&text).unwrap();
+        let round_trip =
+            tes3util::serialize_object(&deserialized, &tes3util::ESerializedType::Yaml)?;
+        assert_eq!(text, round_trip);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_get_plugins_sorted() -> std::io::Result<()> {
+    let plugins = tes3util::get_plugins_sorted(std::path::Path::new("tests/assets"), false, &None)?;
+    assert_eq!(plugins.len(), 1);
+    assert!(
+        tes3util::get_plugins_sorted(std::path::Path::new("tests/missing"), false, &None).is_err()
+    );
+    Ok(())
+}
+
+#[test]
+fn test_plugin_tags() -> std::io::Result<()> {
+    let plugin = Plugin::from_path("tests/assets/Ashlander Crafting.ESP")?;
+    let tags = tes3util::plugin_tags(&plugin);
+    let counts = tes3util::plugin_tag_counts(&plugin);
+    assert!(tags.contains("TES3"));
+    assert_eq!(counts["TES3"], 1);
+    assert!(tags.iter().eq(counts.keys()));
+    assert_eq!(counts.values().sum::<usize>(), plugin.objects.len());
+    Ok(())
+}